@@ -0,0 +1,135 @@
+//! Throughput of the interpreter, in instructions per second: running games,
+//! with and without the instruction cache, drawing sprites in a tight loop,
+//! and decoding alone.
+//!
+//! Run with `cargo bench`, or `cargo bench -- <name>` for a single benchmark.
+
+use std::path::PathBuf;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+
+use rs_chip8::{assemble, Chip8, Instruction, KeyEvent, Quirks};
+
+/// Instructions executed by each iteration of a benchmark.
+const INSTRUCTIONS: u64 = 10_000;
+
+/// Instructions between two ticks of the timers, as a fast interpreter would run.
+const CYCLES_PER_FRAME: u64 = 30;
+
+/// Frames run before measuring, to get past the title screens.
+const WARM_UP_FRAMES: u64 = 120;
+
+/// Runs `emulator` until it has executed `count` instructions, ticking the
+/// timers every frame and answering FX0A with a key, so it never blocks.
+fn run(emulator: &mut Chip8, count: u64) {
+    let end = emulator.cycles() + count;
+    let mut frame_end = emulator.cycles() + CYCLES_PER_FRAME;
+
+    while emulator.cycles() < end {
+        if let Some(x) = emulator.waiting_for_key() {
+            emulator.handle_input(KeyEvent::Down(x));
+            emulator.handle_input(KeyEvent::Up(x));
+        }
+
+        if let Err(err) = emulator.step() {
+            panic!("halted at {:03X}: {}", emulator.pc(), err);
+        }
+
+        if emulator.waiting_for_vblank() || emulator.cycles() >= frame_end {
+            emulator.tick_timers();
+            frame_end = emulator.cycles() + CYCLES_PER_FRAME;
+        }
+    }
+}
+
+/// Measures `emulator` from its current state: each iteration starts from a
+/// copy of it.
+fn bench_emulator(c: &mut Criterion, group: &str, name: &str, emulator: Chip8) {
+    let mut group = c.benchmark_group(group);
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    group.bench_function(name, |b| {
+        b.iter_batched_ref(
+            || emulator.clone(),
+            |emulator| run(emulator, INSTRUCTIONS),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+fn games(c: &mut Criterion) {
+    for game in ["PONG", "BLINKY", "INVADERS"] {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("games")
+            .join(game);
+
+        let mut emulator = Chip8::new();
+        emulator.load_rom_file(path.to_str().unwrap()).unwrap();
+        run(&mut emulator, WARM_UP_FRAMES * CYCLES_PER_FRAME);
+
+        bench_emulator(c, "games", game, emulator.clone());
+
+        emulator.set_instruction_cache(true);
+        bench_emulator(c, "games cached", game, emulator);
+    }
+}
+
+/// Draws sprites all over the screen, with wrapping at the edges.
+const DRAW_LOOP: &str = "
+    LD I, sprite
+loop:
+    DRW V0, V1, 15
+    ADD V0, 7
+    ADD V1, 3
+    JP loop
+
+sprite:
+    db 0xFF, 0x81, 0xBD, 0xA5, 0xA5, 0xBD, 0x81, 0xFF, 0x18, 0x3C, 0x7E, 0xFF, 0x7E, 0x3C, 0x18
+";
+
+/// Draws 16x16 sprites all over the high resolution screen.
+const HIRES_DRAW_LOOP: &str = "
+    HIGH
+    LD I, sprite
+loop:
+    DRW V0, V1, 0
+    ADD V0, 13
+    ADD V1, 5
+    JP loop
+
+sprite:
+    db 0xFF, 0xFF, 0xC0, 0x03, 0xC0, 0x03, 0xCF, 0xF3, 0xCF, 0xF3, 0xCC, 0x33, 0xCC, 0x33, 0xCF, 0xF3
+    db 0xCF, 0xF3, 0xCC, 0x33, 0xCC, 0x33, 0xCF, 0xF3, 0xCF, 0xF3, 0xC0, 0x03, 0xC0, 0x03, 0xFF, 0xFF
+";
+
+fn draw(c: &mut Criterion) {
+    for (name, source) in [("lores", DRAW_LOOP), ("hires", HIRES_DRAW_LOOP)] {
+        let rom = assemble(source).unwrap_or_else(|err| panic!("{}", err));
+
+        // Without the display wait, which would allow a single sprite per frame.
+        let mut quirks = Quirks::vip();
+        quirks.clip_sprites = false;
+        quirks.display_wait = false;
+
+        let mut emulator = Chip8::with_quirks(quirks);
+        emulator.load_rom(&rom).unwrap();
+
+        bench_emulator(c, "draw", name, emulator);
+    }
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(0x10000));
+    group.bench_function("all opcodes", |b| {
+        b.iter(|| {
+            for opcode in 0..=u16::MAX {
+                let _ = black_box(Instruction::decode(black_box(opcode)));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, games, draw, decode);
+criterion_main!(benches);
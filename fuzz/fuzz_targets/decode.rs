@@ -0,0 +1,21 @@
+//! Decodes every word of the input, and disassembles it as a ROM: known
+//! opcodes must encode back to themselves, and nothing may panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use rs_chip8::{Disassembly, Instruction, PROGRAM_START};
+
+fuzz_target!(|rom: &[u8]| {
+    for word in rom.chunks_exact(2) {
+        let opcode = u16::from_be_bytes([word[0], word[1]]);
+
+        if let Ok(instruction) = Instruction::decode(opcode) {
+            assert_eq!(instruction.encode(), opcode, "{}", instruction);
+            let _ = instruction.to_string();
+        }
+    }
+
+    let _ = Disassembly::new(rom, PROGRAM_START).to_string();
+});
@@ -0,0 +1,37 @@
+//! Loads the input as a ROM and runs it with each preset: whatever the ROM
+//! does, the emulator must fail with a `Chip8Error` rather than panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use rs_chip8::{Chip8, KeyEvent, Quirks};
+
+const FRAMES: u32 = 100;
+const CYCLES_PER_FRAME: u32 = 30;
+
+fn run(mut emulator: Chip8, rom: &[u8]) {
+    if emulator.load_rom(rom).is_err() {
+        return;
+    }
+
+    for frame in 0..FRAMES {
+        // Answer FX0A with a key that changes every frame, so that programs
+        // waiting for one go on.
+        if emulator.waiting_for_key().is_some() {
+            let key = frame as usize % 16;
+            emulator.handle_input(KeyEvent::Down(key));
+            emulator.handle_input(KeyEvent::Up(key));
+        }
+
+        if emulator.run_frame(CYCLES_PER_FRAME).is_err() || emulator.exited {
+            return;
+        }
+    }
+}
+
+fuzz_target!(|rom: &[u8]| {
+    run(Chip8::with_quirks(Quirks::vip()), rom);
+    run(Chip8::with_quirks(Quirks::schip()), rom);
+    run(Chip8::xo_chip(Quirks::xochip()), rom);
+});
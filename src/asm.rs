@@ -0,0 +1,701 @@
+//! A two-pass assembler for CHIP-8 programs, using the mnemonics printed by
+//! [`Instruction`]'s `Display` implementation and by the disassembler.
+//!
+//! # Syntax
+//!
+//! Every line holds at most one statement, optionally preceded by a label and
+//! followed by a comment. Mnemonics, registers and directives are case-insensitive.
+//!
+//! ```text
+//! ; Comments run from a semicolon to the end of the line.
+//! SPEED = 2                   ; constants are defined with `name = expression`
+//!
+//! start:                      ; labels end with a colon
+//!     LD V0, SPEED
+//!     LD I, sprite
+//!     DRW V0, V1, sprite_end - sprite
+//! loop: JP loop               ; a label can share the line with a statement
+//!
+//! sprite:
+//!     db 0b11110000, 0x90, 0x90, 0xF0
+//! sprite_end:
+//!     dw 0x1234               ; 16-bit big-endian words
+//!     db "HI", 0              ; strings emit their ASCII bytes
+//!     include "font.s"        ; inlines another file, relative to this one
+//! ```
+//!
+//! Numbers are decimal, hexadecimal (`0x`) or binary (`0b`), and can be combined
+//! with `+`, `-` and parentheses. Symbols can be used before they are defined.
+//!
+//! The operands follow Cowgod's technical reference: `Vx` registers, `I`, `[I]`
+//! (memory at I), `DT`, `ST`, `K` (key), `F` and `HF` (font sprites), `B` (BCD)
+//! and `R` (RPL flags). `SHR` and `SHL` take an optional second register, which
+//! defaults to the first one. The XO-CHIP long load is written `LD I, LONG addr`.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::chip8::PROGRAM_START;
+use crate::instruction::Instruction;
+use crate::source_map::SourceMap;
+
+/// Maximum depth of nested `include` directives.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// An error found while assembling, at line `line` of `file`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl error::Error for AsmError {}
+
+/// Assembles the file at `path`, resolving includes relative to it.
+/// Returns the program, to be loaded at `PROGRAM_START`.
+pub fn assemble_file(path: &Path) -> Result<Vec<u8>, AsmError> {
+    assemble_file_with_map(path).map(|(rom, _)| rom)
+}
+
+/// Like `assemble_file`, also returning the source line of every instruction.
+pub fn assemble_file_with_map(path: &Path) -> Result<(Vec<u8>, SourceMap), AsmError> {
+    let source = fs::read_to_string(path).map_err(|err| AsmError {
+        file: path.display().to_string(),
+        line: 0,
+        message: err.to_string(),
+    })?;
+
+    Assembler::default().assemble(&path.display().to_string(), &source, path.parent())
+}
+
+/// Assembles `source`, resolving includes relative to the working directory.
+/// Returns the program, to be loaded at `PROGRAM_START`.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    Assembler::default()
+        .assemble("<source>", source, None)
+        .map(|(rom, _)| rom)
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(i64),
+    Symbol(String),
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    V(usize),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(Expr),
+    Value(Expr),
+}
+
+#[derive(Debug, Clone)]
+enum DataItem {
+    Value(Expr),
+    Bytes(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+enum Statement {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<Operand>,
+    },
+    /// `db` (`width` 1) or `dw` (`width` 2) directive.
+    Data { width: usize, items: Vec<DataItem> },
+}
+
+impl Statement {
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instruction { operands, .. } => {
+                if operands.iter().any(|op| matches!(op, Operand::Long(_))) {
+                    4
+                } else {
+                    2
+                }
+            }
+            Statement::Data { width, items } => items
+                .iter()
+                .map(|item| match item {
+                    DataItem::Value(_) => *width,
+                    DataItem::Bytes(bytes) => bytes.len(),
+                })
+                .sum(),
+        }
+    }
+}
+
+/// A statement with the location it comes from, for error reporting.
+#[derive(Debug)]
+struct Located {
+    file: String,
+    line: usize,
+    addr: usize,
+    statement: Statement,
+}
+
+#[derive(Debug)]
+enum Symbol {
+    Label(usize),
+    Constant(Expr),
+}
+
+#[derive(Debug, Default)]
+struct Assembler {
+    statements: Vec<Located>,
+    symbols: HashMap<String, Symbol>,
+    addr: usize,
+}
+
+impl Assembler {
+    fn assemble(
+        mut self,
+        file: &str,
+        source: &str,
+        dir: Option<&Path>,
+    ) -> Result<(Vec<u8>, SourceMap), AsmError> {
+        self.addr = PROGRAM_START as usize;
+        self.parse(file, source, dir, 0)?;
+
+        let mut output = Vec::new();
+        let mut map = SourceMap::new();
+        for located in &self.statements {
+            if let Statement::Instruction { .. } = located.statement {
+                map.insert(located.addr as u16, &located.file, located.line);
+            }
+
+            let bytes = self
+                .encode(&located.statement)
+                .map_err(|message| AsmError {
+                    file: located.file.clone(),
+                    line: located.line,
+                    message,
+                })?;
+            debug_assert_eq!(located.addr, PROGRAM_START as usize + output.len());
+            output.extend(bytes);
+        }
+
+        Ok((output, map))
+    }
+
+    /// First pass: parses every line, defining labels at the address of the
+    /// statement that follows them.
+    fn parse(
+        &mut self,
+        file: &str,
+        source: &str,
+        dir: Option<&Path>,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        for (index, raw_line) in source.lines().enumerate() {
+            let line = index + 1;
+            let error = |message: String| AsmError {
+                file: file.to_string(),
+                line,
+                message,
+            };
+
+            let mut text = strip_comment(raw_line).trim();
+
+            // Leading label.
+            if let Some(colon) = text.find(':') {
+                let name = text[..colon].trim();
+                if is_identifier(name) {
+                    self.define(name, Symbol::Label(self.addr)).map_err(error)?;
+                    text = text[colon + 1..].trim();
+                }
+            }
+
+            if text.is_empty() {
+                continue;
+            }
+
+            // Constant definition.
+            if let Some(equals) = text.find('=') {
+                let name = text[..equals].trim();
+                if is_identifier(name) {
+                    let expr = parse_expr(text[equals + 1..].trim()).map_err(error)?;
+                    self.define(name, Symbol::Constant(expr)).map_err(error)?;
+                    continue;
+                }
+            }
+
+            let (mnemonic, rest) = match text.find(char::is_whitespace) {
+                Some(space) => (&text[..space], text[space..].trim()),
+                None => (text, ""),
+            };
+            let mnemonic = mnemonic.to_ascii_uppercase();
+
+            let statement = match mnemonic.as_str() {
+                "INCLUDE" => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(error("includes are nested too deeply".to_string()));
+                    }
+
+                    let name = parse_string(rest).map_err(error)?;
+                    let path = match dir {
+                        Some(dir) => dir.join(&name),
+                        None => PathBuf::from(&name),
+                    };
+                    let source = fs::read_to_string(&path)
+                        .map_err(|err| error(format!("cannot include {}: {}", name, err)))?;
+
+                    self.parse(
+                        &path.display().to_string(),
+                        &source,
+                        path.parent(),
+                        depth + 1,
+                    )?;
+                    continue;
+                }
+                "DB" | "DW" => Statement::Data {
+                    width: if mnemonic == "DB" { 1 } else { 2 },
+                    items: split_operands(rest)
+                        .into_iter()
+                        .map(parse_data_item)
+                        .collect::<Result<_, _>>()
+                        .map_err(error)?,
+                },
+                _ => Statement::Instruction {
+                    operands: split_operands(rest)
+                        .into_iter()
+                        .map(parse_operand)
+                        .collect::<Result<_, _>>()
+                        .map_err(error)?,
+                    mnemonic,
+                },
+            };
+
+            let size = statement.size();
+            self.statements.push(Located {
+                file: file.to_string(),
+                line,
+                addr: self.addr,
+                statement,
+            });
+            self.addr += size;
+        }
+
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str, symbol: Symbol) -> Result<(), String> {
+        if self.symbols.contains_key(name) {
+            return Err(format!("'{}' is already defined", name));
+        }
+
+        self.symbols.insert(name.to_string(), symbol);
+        Ok(())
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<i64, String> {
+        self.eval_nested(expr, 0)
+    }
+
+    fn eval_nested(&self, expr: &Expr, depth: usize) -> Result<i64, String> {
+        // Constants referring to each other in a loop would never resolve.
+        if depth > self.symbols.len() {
+            return Err("constant is defined in terms of itself".to_string());
+        }
+
+        match expr {
+            Expr::Number(value) => Some(*value),
+            Expr::Symbol(name) => match self.symbols.get(name) {
+                Some(Symbol::Label(addr)) => Some(*addr as i64),
+                Some(Symbol::Constant(expr)) => Some(self.eval_nested(expr, depth + 1)?),
+                None => return Err(format!("undefined symbol '{}'", name)),
+            },
+            Expr::Neg(expr) => self.eval_nested(expr, depth)?.checked_neg(),
+            Expr::Add(a, b) => self
+                .eval_nested(a, depth)?
+                .checked_add(self.eval_nested(b, depth)?),
+            Expr::Sub(a, b) => self
+                .eval_nested(a, depth)?
+                .checked_sub(self.eval_nested(b, depth)?),
+        }
+        .ok_or_else(|| "expression overflows".to_string())
+    }
+
+    /// Evaluates `expr`, checking that it fits in `bits` bits. Negative values
+    /// are accepted down to the minimum of a signed value of the same size.
+    fn eval_bits(&self, expr: &Expr, bits: u32) -> Result<u16, String> {
+        let value = self.eval(expr)?;
+        let max = (1i64 << bits) - 1;
+        let min = -(1i64 << (bits - 1));
+
+        if value < min || value > max {
+            return Err(format!("{} doesn't fit in {} bits", value, bits));
+        }
+
+        Ok((value & max) as u16)
+    }
+
+    /// Evaluates an address of `bits` bits, which can't be negative.
+    fn eval_address(&self, expr: &Expr, bits: u32) -> Result<u16, String> {
+        match self.eval(expr)? {
+            value if value < 0 => Err(format!("{} is not a valid address", value)),
+            _ => self.eval_bits(expr, bits),
+        }
+    }
+
+    /// Second pass: encodes a statement, now that every symbol is defined.
+    fn encode(&self, statement: &Statement) -> Result<Vec<u8>, String> {
+        match statement {
+            Statement::Data { width, items } => {
+                let mut bytes = Vec::new();
+                for item in items {
+                    match item {
+                        DataItem::Bytes(data) => bytes.extend(data),
+                        DataItem::Value(expr) if *width == 1 => {
+                            bytes.push(self.eval_bits(expr, 8)? as u8)
+                        }
+                        DataItem::Value(expr) => {
+                            bytes.extend(self.eval_bits(expr, 16)?.to_be_bytes())
+                        }
+                    }
+                }
+                Ok(bytes)
+            }
+            Statement::Instruction { mnemonic, operands } => {
+                let instruction = self.instruction(mnemonic, operands)?;
+                let mut bytes = instruction.encode().to_be_bytes().to_vec();
+
+                if let Some(Operand::Long(expr)) = operands.last() {
+                    bytes.extend(self.eval_address(expr, 16)?.to_be_bytes());
+                }
+
+                Ok(bytes)
+            }
+        }
+    }
+
+    fn instruction(&self, mnemonic: &str, operands: &[Operand]) -> Result<Instruction, String> {
+        use Instruction::*;
+        use Operand::*;
+
+        let addr = |expr: &Expr| self.eval_address(expr, 12);
+        let byte = |expr: &Expr| self.eval_bits(expr, 8).map(|value| value as u8);
+        let nibble = |expr: &Expr| -> Result<u8, String> {
+            match self.eval(expr)? {
+                value @ 0..=15 => Ok(value as u8),
+                value => Err(format!("{} doesn't fit in 4 bits", value)),
+            }
+        };
+
+        let instruction = match (mnemonic, operands) {
+            ("CLS", []) => ClearScreen,
+            ("RET", []) => Return,
+            ("SCR", []) => ScrollRight,
+            ("SCL", []) => ScrollLeft,
+            ("EXIT", []) => Exit,
+            ("LOW", []) => Lores,
+            ("HIGH", []) => Hires,
+            ("AUDIO", []) => LoadAudio,
+            ("SCD", [Value(n)]) => ScrollDown(nibble(n)?),
+            ("SCU", [Value(n)]) => ScrollUp(nibble(n)?),
+            ("PLANE", [Value(n)]) => SelectPlane(nibble(n)?),
+            ("JP", [Value(a)]) => Jump(addr(a)?),
+            ("JP", [V(0), Value(a)]) => JumpOffset(addr(a)?),
+            ("CALL", [Value(a)]) => Call(addr(a)?),
+            ("SE", [V(x), Value(nn)]) => SkipEqImm {
+                x: *x,
+                nn: byte(nn)?,
+            },
+            ("SE", [V(x), V(y)]) => SkipEqReg { x: *x, y: *y },
+            ("SNE", [V(x), Value(nn)]) => SkipNeImm {
+                x: *x,
+                nn: byte(nn)?,
+            },
+            ("SNE", [V(x), V(y)]) => SkipNeReg { x: *x, y: *y },
+            ("SAVE", [V(x), V(y)]) => SaveRange { x: *x, y: *y },
+            ("LOAD", [V(x), V(y)]) => LoadRange { x: *x, y: *y },
+            ("LD", [V(x), Value(nn)]) => SetImm {
+                x: *x,
+                nn: byte(nn)?,
+            },
+            ("LD", [V(x), V(y)]) => Assign { x: *x, y: *y },
+            ("LD", [I, Value(a)]) => SetI(addr(a)?),
+            ("LD", [I, Long(_)]) => LoadILong,
+            ("LD", [V(x), Dt]) => GetDelay { x: *x },
+            ("LD", [V(x), K]) => WaitKey { x: *x },
+            ("LD", [Dt, V(x)]) => SetDelay { x: *x },
+            ("LD", [St, V(x)]) => SetSound { x: *x },
+            ("LD", [F, V(x)]) => Font { x: *x },
+            ("LD", [Hf, V(x)]) => BigFont { x: *x },
+            ("LD", [B, V(x)]) => Bcd { x: *x },
+            ("LD", [IndirectI, V(x)]) => Store { x: *x },
+            ("LD", [V(x), IndirectI]) => Load { x: *x },
+            ("LD", [R, V(x)]) => SaveFlags { x: *x },
+            ("LD", [V(x), R]) => LoadFlags { x: *x },
+            ("ADD", [V(x), Value(nn)]) => AddImm {
+                x: *x,
+                nn: byte(nn)?,
+            },
+            ("ADD", [V(x), V(y)]) => Add { x: *x, y: *y },
+            ("ADD", [I, V(x)]) => AddI { x: *x },
+            ("OR", [V(x), V(y)]) => Or { x: *x, y: *y },
+            ("AND", [V(x), V(y)]) => And { x: *x, y: *y },
+            ("XOR", [V(x), V(y)]) => Xor { x: *x, y: *y },
+            ("SUB", [V(x), V(y)]) => Sub { x: *x, y: *y },
+            ("SUBN", [V(x), V(y)]) => SubN { x: *x, y: *y },
+            ("SHR", [V(x)]) => ShiftRight { x: *x, y: *x },
+            ("SHR", [V(x), V(y)]) => ShiftRight { x: *x, y: *y },
+            ("SHL", [V(x)]) => ShiftLeft { x: *x, y: *x },
+            ("SHL", [V(x), V(y)]) => ShiftLeft { x: *x, y: *y },
+            ("RND", [V(x), Value(nn)]) => Random {
+                x: *x,
+                nn: byte(nn)?,
+            },
+            ("DRW", [V(x), V(y), Value(n)]) => Draw {
+                x: *x,
+                y: *y,
+                n: nibble(n)?,
+            },
+            ("SKP", [V(x)]) => SkipKeyPressed { x: *x },
+            ("SKNP", [V(x)]) => SkipKeyNotPressed { x: *x },
+            ("PITCH", [V(x)]) => Pitch { x: *x },
+            _ => {
+                return Err(format!(
+                    "invalid instruction or operands for '{}'",
+                    mnemonic
+                ))
+            }
+        };
+
+        Ok(instruction)
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Splits operands on the commas that are not part of a string.
+fn split_operands(text: &str) -> Vec<&str> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+
+    let mut operands = Vec::new();
+    let mut in_string = false;
+    let mut start = 0;
+
+    for (index, c) in text.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                operands.push(text[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+
+    operands.push(text[start..].trim());
+    operands
+}
+
+fn parse_string(text: &str) -> Result<String, String> {
+    let text = text.trim();
+
+    if text.len() >= 2 && text.starts_with('"') && text.ends_with('"') {
+        Ok(text[1..text.len() - 1].to_string())
+    } else {
+        Err(format!("expected a string, found '{}'", text))
+    }
+}
+
+fn parse_data_item(text: &str) -> Result<DataItem, String> {
+    if text.starts_with('"') {
+        let string = parse_string(text)?;
+        if !string.is_ascii() {
+            return Err(format!("'{}' is not an ASCII string", string));
+        }
+        Ok(DataItem::Bytes(string.into_bytes()))
+    } else {
+        parse_expr(text).map(DataItem::Value)
+    }
+}
+
+fn parse_operand(text: &str) -> Result<Operand, String> {
+    let upper = text.to_ascii_uppercase();
+
+    let operand = match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::Dt,
+        "ST" => Operand::St,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "HF" => Operand::Hf,
+        "B" => Operand::B,
+        "R" => Operand::R,
+        _ => {
+            if let Some(register) = parse_register(&upper) {
+                Operand::V(register)
+            } else if let Some(addr) = upper.strip_prefix("LONG ") {
+                Operand::Long(parse_expr(&text[text.len() - addr.len()..])?)
+            } else {
+                Operand::Value(parse_expr(text)?)
+            }
+        }
+    };
+
+    Ok(operand)
+}
+
+fn parse_register(text: &str) -> Option<usize> {
+    let digit = text.strip_prefix('V')?;
+
+    if digit.len() == 1 {
+        usize::from_str_radix(digit, 16).ok()
+    } else {
+        None
+    }
+}
+
+fn parse_expr(text: &str) -> Result<Expr, String> {
+    let mut parser = ExprParser {
+        chars: text.chars().collect(),
+        pos: 0,
+    };
+
+    let expr = parser.expr()?;
+    parser.skip_whitespace();
+
+    if parser.pos < parser.chars.len() {
+        return Err(format!("unexpected characters in expression '{}'", text));
+    }
+
+    Ok(expr)
+}
+
+/// Recursive descent parser for `expr := term (('+' | '-') term)*`.
+struct ExprParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl ExprParser {
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.pos).copied()
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut expr = self.term()?;
+
+        loop {
+            match self.peek() {
+                Some('+') => {
+                    self.pos += 1;
+                    expr = Expr::Add(Box::new(expr), Box::new(self.term()?));
+                }
+                Some('-') => {
+                    self.pos += 1;
+                    expr = Expr::Sub(Box::new(expr), Box::new(self.term()?));
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                Ok(Expr::Neg(Box::new(self.term()?)))
+            }
+            Some('(') => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                if self.peek() != Some(')') {
+                    return Err("missing ')' in expression".to_string());
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            Some(c) if c.is_ascii_alphanumeric() || c == '_' => {
+                let start = self.pos;
+                while self.pos < self.chars.len()
+                    && (self.chars[self.pos].is_ascii_alphanumeric() || self.chars[self.pos] == '_')
+                {
+                    self.pos += 1;
+                }
+
+                let word: String = self.chars[start..self.pos].iter().collect();
+                if c.is_ascii_digit() {
+                    parse_number(&word).map(Expr::Number)
+                } else {
+                    Ok(Expr::Symbol(word))
+                }
+            }
+            Some(c) => Err(format!("unexpected '{}' in expression", c)),
+            None => Err("missing value".to_string()),
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Result<i64, String> {
+    let lower = text.to_ascii_lowercase();
+
+    let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2)
+    } else {
+        lower.parse()
+    };
+
+    parsed.map_err(|_| format!("invalid number '{}'", text))
+}
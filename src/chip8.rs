@@ -1,7 +1,23 @@
+use std::error;
+use std::fmt;
 use std::fs;
 use std::io;
 use std::io::Read;
 
+use crate::instruction::Instruction;
+
+mod builder;
+mod cache;
+mod opcodes;
+mod state;
+mod trace;
+mod watch;
+
+pub use self::builder::Chip8Builder;
+pub use self::state::StateError;
+pub use self::trace::{TraceEntry, TraceFilter, Tracer};
+pub use self::watch::{Access, MemoryAccess, Watchpoint};
+
 static FONT_SET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
     0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
@@ -10,18 +26,178 @@ static FONT_SET: [u8; 80] = [
     0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
 ];
 
-#[derive(Debug)]
+/// SUPER-CHIP 8x10 font for the hex digits, loaded right after `FONT_SET`.
+static BIG_FONT_SET: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x18, 0x78, 0x78, 0x18, 0x18, 0x18,
+    0x18, 0x18, 0xFF, 0xFF, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF,
+    0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03,
+    0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xFF, 0xFF, 0xC0, 0xC0,
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18,
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF,
+    0x03, 0x03, 0xFF, 0xFF, 0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xFC, 0xFC,
+    0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3,
+    0xFF, 0x3C, 0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, 0xFF, 0xFF, 0xC0, 0xC0,
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0,
+];
+
+/// A change in the state of one of the 16 keys of the keypad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KeyEvent {
+    /// The key was released.
     Up(usize),
+    /// The key was pressed.
     Down(usize),
 }
 
+/// Progress of an FX0A instruction, which waits for a key to be pressed and released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyWait {
+    /// Waiting for any key to be pressed, to store it in VX.
+    Press { x: usize },
+    /// Waiting for `key` to be released, to store it in VX.
+    Release { x: usize, key: usize },
+}
+
+/// Behaviour switches for the instructions whose semantics differ between
+/// CHIP-8 implementations.
+///
+/// The presets match the platforms most ROMs are written for, but each
+/// switch can be toggled individually.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VY and store the result in VX, instead of shifting VX in place.
+    pub shift_uses_vy: bool,
+    /// FX55/FX65 leave I pointing right after the last register stored or loaded.
+    pub load_store_increments_i: bool,
+    /// BNNN jumps to XNN + VX instead of NNN + V0.
+    pub jump_uses_vx: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub logic_resets_vf: bool,
+    /// Sprites are clipped at the screen edges instead of wrapping around.
+    pub clip_sprites: bool,
+    /// DXYN waits for the next vertical blank (timer tick) after drawing a sprite.
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    /// Behaviour of the original interpreter on the COSMAC VIP.
+    pub fn vip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            logic_resets_vf: true,
+            clip_sprites: true,
+            display_wait: true,
+        }
+    }
+
+    /// Behaviour of SUPER-CHIP 1.1 on the HP48 calculators.
+    pub fn schip() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: true,
+            logic_resets_vf: false,
+            clip_sprites: true,
+            display_wait: false,
+        }
+    }
+
+    /// Behaviour of XO-CHIP, as implemented by Octo.
+    pub fn xochip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            logic_resets_vf: false,
+            clip_sprites: false,
+            display_wait: false,
+        }
+    }
+
+    /// Every switch off: the behaviour most CHIP-8 documentation describes,
+    /// useful as a base to turn on individual quirks.
+    pub fn none() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: false,
+            logic_resets_vf: false,
+            clip_sprites: false,
+            display_wait: false,
+        }
+    }
+
+    /// Looks up a preset by name (`vip`, `schip` or `xochip`).
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        match name {
+            "vip" => Some(Quirks::vip()),
+            "schip" => Some(Quirks::schip()),
+            "xochip" => Some(Quirks::xochip()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::vip()
+    }
+}
+
+/// Errors that halt the emulator, caused by the program it's running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Error {
+    /// The `opcode` at address `addr` is not a known instruction.
+    UnknownOpcode { opcode: u16, addr: u16 },
+    /// The subroutine call at address `addr` exceeded the depth of the stack.
+    StackOverflow { addr: u16 },
+    /// The return at address `addr` was executed with an empty stack.
+    StackUnderflow { addr: u16 },
+    /// The instruction at address `addr` accessed memory at `target`, which is out of range.
+    MemoryOutOfRange { addr: u16, target: u16 },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode { opcode, addr } => {
+                write!(f, "unknown opcode ${:04x} at ${:04x}", opcode, addr)
+            }
+            Chip8Error::StackOverflow { addr } => write!(f, "stack overflow at ${:04x}", addr),
+            Chip8Error::StackUnderflow { addr } => write!(f, "stack underflow at ${:04x}", addr),
+            Chip8Error::MemoryOutOfRange { addr, target } => write!(
+                f,
+                "memory access out of range (${:04x}) at ${:04x}",
+                target, addr
+            ),
+        }
+    }
+}
+
+impl error::Error for Chip8Error {}
+
+/// Rate (in Hz) of the timers and of the vertical blank, i.e. how many times
+/// per second `Chip8::run_frame` should be called.
+pub const FRAME_RATE: u32 = 60;
+
+/// Address where programs are loaded, and start executing from.
+pub const PROGRAM_START: u16 = 0x200;
+
 const STACK_SIZE: usize = 16;
 const REGISTERS: usize = 16;
 const MEMORY_SIZE: usize = 4096;
-const VRAM_SIZE: usize = 64 * 32;
-
-#[derive(Debug)]
+const XO_MEMORY_SIZE: usize = 0x10000;
+const AUDIO_PATTERN_SIZE: usize = 16;
+const RPL_FLAGS: usize = 16;
+const LORES_WIDTH: usize = 64;
+const LORES_HEIGHT: usize = 32;
+const HIRES_WIDTH: usize = 128;
+const HIRES_HEIGHT: usize = 64;
+const BIG_FONT_ADDR: usize = 0x50;
+
+#[derive(Debug, Clone)]
 pub struct Chip8 {
     i: u16,
     pc: u16,
@@ -30,47 +206,122 @@ pub struct Chip8 {
     v: Vec<u8>,
     memory: Vec<u8>,
     vram: Vec<u8>,
+    hires: bool,
+    plane: u8,
+    rpl: Vec<u8>,
+    audio_pattern: Option<Vec<u8>>,
+    pitch: u8,
     key_pressed: Vec<bool>,
     delay_t: u8,
     sound_t: u8,
-    opcode: u16,
+    vblank_wait: bool,
+    pub quirks: Quirks,
     pub draw_flag: bool,
-    pub stopped: bool,
+    key_wait: Option<KeyWait>,
+    pub exited: bool,
+    error: Option<Chip8Error>,
+    /// Hash of the loaded ROM, to match save states with it.
+    rom_hash: u64,
+    watchpoints: Vec<Watchpoint>,
+    /// Watched accesses not yet taken by `take_accesses`.
+    accesses: Vec<MemoryAccess>,
+    /// Instructions executed so far.
+    cycles: u64,
+    trace: Option<trace::Trace>,
+    /// Decoded instructions by address, when cached. See `set_instruction_cache`.
+    decoded: Option<Vec<Option<Instruction>>>,
 }
 
 impl Chip8 {
     pub fn new() -> Chip8 {
+        Chip8::with_quirks(Quirks::default())
+    }
+
+    /// Creates an emulator that uses the given `quirks` for ambiguous instructions.
+    pub fn with_quirks(quirks: Quirks) -> Chip8 {
+        Chip8::build(quirks, false)
+    }
+
+    /// Creates an XO-CHIP emulator, with 64 KiB of memory, using the given `quirks`
+    /// for ambiguous instructions.
+    pub fn xo_chip(quirks: Quirks) -> Chip8 {
+        Chip8::build(quirks, true)
+    }
+
+    fn build(quirks: Quirks, xo_chip: bool) -> Chip8 {
+        let memory_size = if xo_chip { XO_MEMORY_SIZE } else { MEMORY_SIZE };
+
         let mut c8 = Chip8 {
             i: 0,
-            pc: 0x200,
+            pc: PROGRAM_START,
             sp: 0,
             stack: vec![0; STACK_SIZE],
             v: vec![0; REGISTERS],
-            memory: vec![0; MEMORY_SIZE],
-            vram: vec![0; VRAM_SIZE],
+            memory: vec![0; memory_size],
+            vram: vec![0; LORES_WIDTH * LORES_HEIGHT],
+            hires: false,
+            plane: 1,
+            rpl: vec![0; RPL_FLAGS],
+            audio_pattern: None,
+            pitch: 64,
             key_pressed: vec![false; 16],
             delay_t: 0,
             sound_t: 0,
-            opcode: 0,
+            vblank_wait: false,
+            quirks,
             draw_flag: false,
-            stopped: false,
+            key_wait: None,
+            exited: false,
+            error: None,
+            rom_hash: state::rom_hash(&[]),
+            watchpoints: Vec::new(),
+            accesses: Vec::new(),
+            cycles: 0,
+            trace: None,
+            decoded: None,
         };
 
-        for i in 0..FONT_SET.len() {
-            c8.memory[i] = FONT_SET[i];
-        }
+        c8.memory[..FONT_SET.len()].copy_from_slice(&FONT_SET);
+        c8.memory[BIG_FONT_ADDR..BIG_FONT_ADDR + BIG_FONT_SET.len()].copy_from_slice(&BIG_FONT_SET);
 
         c8
     }
 
-    /// Reads a byte from memory at the specified address `addr`.
-    pub fn read(&self, addr: u16) -> u8 {
-        self.memory[addr as usize]
-    }
-
-    /// Writes `data` in memory at the specified address `addr`.
-    pub fn write(&mut self, addr: u16, data: u8) {
-        self.memory[addr as usize] = data;
+    /// Reads a byte from memory at the specified address `addr`, reporting it to
+    /// the watchpoints.
+    pub fn read(&mut self, addr: u16) -> Result<u8, Chip8Error> {
+        let value = self.fetch_byte(addr)?;
+        self.record(addr, Access::Read, value);
+        Ok(value)
+    }
+
+    /// Reads a byte of an instruction, which watchpoints don't see.
+    fn fetch_byte(&self, addr: u16) -> Result<u8, Chip8Error> {
+        self.memory
+            .get(addr as usize)
+            .copied()
+            .ok_or(Chip8Error::MemoryOutOfRange {
+                addr: self.pc,
+                target: addr,
+            })
+    }
+
+    /// Writes `data` in memory at the specified address `addr`, reporting it to
+    /// the watchpoints.
+    pub fn write(&mut self, addr: u16, data: u8) -> Result<(), Chip8Error> {
+        let pc = self.pc;
+        let byte = self
+            .memory
+            .get_mut(addr as usize)
+            .ok_or(Chip8Error::MemoryOutOfRange {
+                addr: pc,
+                target: addr,
+            })?;
+
+        *byte = data;
+        self.invalidate(addr);
+        self.record(addr, Access::Write, data);
+        Ok(())
     }
 
     // Loads a game from a file at the specfied `path`.
@@ -80,483 +331,256 @@ impl Chip8 {
 
         file.read_to_end(&mut buffer)?;
 
-        for i in 0..buffer.len() {
-            self.memory[0x200 + i] = buffer[i];
-        }
-
-        Ok(())
+        self.load_rom(&buffer)
     }
 
-    pub fn step(&mut self) {
-        if self.stopped {
-            return;
-        }
-
-        self.opcode = combine_bytes(self.read(self.pc + 1), self.read(self.pc));
+    /// Loads a game already in memory, e.g. one assembled with `assemble`.
+    pub fn load_rom(&mut self, rom: &[u8]) -> io::Result<()> {
+        let start = PROGRAM_START as usize;
 
-        // decode
-        let func = opcodes::decode(self.opcode);
-
-        // exec
-        func(self);
-
-        // update timers
-        if self.delay_t > 0 {
-            self.delay_t -= 1;
+        if rom.len() > self.memory.len() - start {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "ROM is {} bytes, but at most {} fit in memory",
+                    rom.len(),
+                    self.memory.len() - start
+                ),
+            ));
         }
 
-        if self.sound_t > 0 {
-            if self.sound_t == 1 {
-                println!("BOOP!");
-            }
-            self.sound_t -= 1;
-        }
+        self.memory[start..start + rom.len()].copy_from_slice(rom);
+        self.rom_hash = state::rom_hash(rom);
+        self.clear_instruction_cache();
+
+        Ok(())
     }
 
-    pub fn handle_input(&mut self, key_event: KeyEvent) {
-        if self.stopped {
-            self.stopped = false;
+    /// Executes a single instruction. Timers are not affected, see `tick_timers`.
+    ///
+    /// If the instruction fails, the emulator is halted: the error is returned by
+    /// this and every following call, without executing anything else, and
+    /// is also available through `error`.
+    pub fn step(&mut self) -> Result<(), Chip8Error> {
+        if let Some(err) = self.error {
+            return Err(err);
         }
 
-        match key_event {
-            KeyEvent::Up(key) => self.key_pressed[key] = true,
-            KeyEvent::Down(key) => self.key_pressed[key] = false,
+        if self.key_wait.is_some() || self.exited || self.vblank_wait {
+            return Ok(());
         }
-    }
-
-    pub fn get_framebuffer(&self) -> &[u8] {
-        &self.vram
-    }
 
-    pub fn should_draw(&self) -> bool {
-        self.draw_flag
-    }
-
-    pub fn should_beep(&self) -> bool {
-        self.sound_t == 1
-    }
-}
-
-fn combine_bytes(low: u8, high: u8) -> u16 {
-    (high as u16) << 8 | low as u16
-}
-
-mod opcodes {
-    use crate::chip8::Chip8;
-    use rand;
-    use rand::Rng;
-
-    pub fn decode(opcode: u16) -> fn(&mut Chip8) {
-        match opcode {
-            0x00E0 => clear_screen,
-            0x00EE => return_from_sub,
-            0x1000..=0x1FFF => jump_addr,
-            0x2000..=0x2FFF => call_sub_at_nnn,
-            0x3000..=0x3FFF => skip_if_vx_equal_to_nn,
-            0x4000..=0x4FFF => skip_if_vx_not_equal_to_nn,
-            0x5000..=0x5FFF => skip_if_vx_equal_to_vy,
-            0x6000..=0x6FFF => set_vx_to_immediate,
-            0x7000..=0x7FFF => add_nn_to_vx,
-            0x8000..=0x8FFF => match opcode & 0xF {
-                0x0 => assign_vy_to_vx,
-                0x1 => vx_or_vy,
-                0x2 => vx_and_vy,
-                0x3 => vx_xor_vy,
-                0x4 => add_vy_to_vx,
-                0x5 => sub_vy_to_vx,
-                0x6 => shift_vx_right,
-                0x7 => sub_vx_to_vy,
-                0xE => shift_vx_left,
-                _ => panic!("Unknown opcode ${:04x}", opcode),
-            },
-            0x9000..=0x9FFF => skip_if_vx_not_equal_to_vy,
-            0xA000..=0xAFFF => set_memory_nnn,
-            0xB000..=0xBFFF => jump_addr_sum,
-            0xC000..=0xCFFF => rand_to_vx,
-            0xD000..=0xDFFF => draw,
-            0xE000..=0xEFFF => match opcode & 0xFF {
-                0x9E => skip_if_key_pressed,
-                0xA1 => skip_if_key_not_pressed,
-                _ => panic!("Unknown opcode ${:04x}", opcode),
-            },
-            0xF000..=0xFFFF => match opcode & 0xFF {
-                0x07 => set_vx_to_delay,
-                0x0A => wait_for_key_press,
-                0x15 => set_delay_to_vx,
-                0x18 => set_sound_to_vx,
-                0x1E => add_vx_to_i,
-                0x29 => set_i_to_sprite_addr,
-                0x33 => set_bcd,
-                0x55 => dump_registers,
-                0x65 => load_registers,
-                _ => panic!("Unknown opcode ${:04x}", opcode),
-            },
-            _ => panic!("Unknown opcode ${:04x}", opcode),
+        if let Err(err) = self.execute() {
+            self.error = Some(err);
+            return Err(err);
         }
-    }
 
-    /// Extracts the X and Y parameters from a 16-bit opcode in the format 0x_XY_
-    fn get_opcode_args(opcode: u16) -> (usize, usize) {
-        (
-            ((opcode & 0x0F00) >> 8) as usize,
-            ((opcode & 0x00F0) >> 4) as usize,
-        )
+        Ok(())
     }
 
-    /// Extracts an 8-bit immediate value (NN)
-    fn get_immediate_value(opcode: u16) -> u8 {
-        (opcode & 0x00FF) as u8
+    /// Decrements the delay and sound timers. Must be called at 60 Hz, which is
+    /// also the rate of the vertical blank: a draw waiting on it is released.
+    pub fn tick_timers(&mut self) {
+        self.delay_t = self.delay_t.saturating_sub(1);
+        self.sound_t = self.sound_t.saturating_sub(1);
+        self.vblank_wait = false;
     }
 
-    /// opcode 6XNN
-    fn set_vx_to_immediate(c8: &mut Chip8) {
-        let (x, _) = get_opcode_args(c8.opcode);
-        let nn = get_immediate_value(c8.opcode);
-
-        c8.v[x] = nn;
-        c8.pc = c8.pc.wrapping_add(2);
-    }
-
-    /// opcode 00E0.
-    /// Resets the screen pixel values
-    fn clear_screen(c8: &mut Chip8) {
-        for i in 0..c8.vram.len() {
-            c8.vram[i] = 0;
+    /// Emulates a 60 Hz frame: executes `cycles_per_frame` instructions, then ticks the timers.
+    /// Stops early if an instruction fails, without ticking the timers.
+    pub fn run_frame(&mut self, cycles_per_frame: u32) -> Result<(), Chip8Error> {
+        for _ in 0..cycles_per_frame {
+            self.step()?;
         }
 
-        c8.draw_flag = true;
-        c8.pc = c8.pc.wrapping_add(2);
+        self.tick_timers();
+        Ok(())
     }
 
-    /// opcode 00EE.
-    /// Returns from a subroutine, meaning it will set the PC to the last stack value.
-    fn return_from_sub(c8: &mut Chip8) {
-        c8.sp -= 1;
-        c8.pc = c8.stack[c8.sp as usize].wrapping_add(2);
-    }
+    fn execute(&mut self) -> Result<(), Chip8Error> {
+        // fetch and decode
+        let instruction = self.fetch()?;
 
-    /// opcode 1NNN.
-    /// Sets the program counter to NNN.
-    fn jump_addr(c8: &mut Chip8) {
-        c8.pc = c8.opcode & 0x0FFF;
-    }
+        if self.trace.is_some() {
+            self.trace(instruction.encode(), &instruction);
+        }
 
-    /// opcode 2NNN.
-    /// It will call the subroutine at address NNN, i.e. move the PC to it.
-    fn call_sub_at_nnn(c8: &mut Chip8) {
-        c8.stack[c8.sp as usize] = c8.pc;
-        c8.sp += 1;
-        c8.pc = c8.opcode & 0x0FFF;
+        // exec
+        self.cycles += 1;
+        opcodes::execute(self, instruction)
     }
 
-    /// opcode 3XNN.
-    /// It will skip the next instruction if Vx == NN.
-    fn skip_if_vx_equal_to_nn(c8: &mut Chip8) {
-        let (x, _) = get_opcode_args(c8.opcode);
-        let nn = get_immediate_value(c8.opcode);
-
-        c8.pc = c8.pc.wrapping_add(if c8.v[x] == nn { 4 } else { 2 });
+    /// Returns the error that halted the emulator, if any.
+    pub fn error(&self) -> Option<Chip8Error> {
+        self.error
     }
 
-    /// opcode 4XNN.
-    /// It will skip the next instruction if Vx != NN.
-    fn skip_if_vx_not_equal_to_nn(c8: &mut Chip8) {
-        let (x, _) = get_opcode_args(c8.opcode);
-        let nn = get_immediate_value(c8.opcode);
-
-        c8.pc = c8.pc.wrapping_add(if c8.v[x] != nn { 4 } else { 2 });
+    /// Returns whether execution is blocked until the next vertical blank (timer tick),
+    /// after drawing a sprite with the `display_wait` quirk.
+    pub fn waiting_for_vblank(&self) -> bool {
+        self.vblank_wait
     }
 
-    /// opcode 5XY0.
-    /// It will skip the next instruction if Vx == Vy.
-    fn skip_if_vx_equal_to_vy(c8: &mut Chip8) {
-        let (x, y) = get_opcode_args(c8.opcode);
-
-        c8.pc = c8.pc.wrapping_add(if c8.v[x] == c8.v[y] { 4 } else { 2 });
+    /// Returns the address of the next instruction.
+    pub fn pc(&self) -> u16 {
+        self.pc
     }
 
-    /// opcode 7XNN
-    /// It will add NN to the Vx register
-    fn add_nn_to_vx(c8: &mut Chip8) {
-        let (x, _) = get_opcode_args(c8.opcode);
-        let nn = get_immediate_value(c8.opcode);
-        c8.v[x] = c8.v[x].wrapping_add(nn);
-        c8.pc = c8.pc.wrapping_add(2);
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
     }
 
-    /// opcode 8XY0
-    /// Assigns the value of Vy to Vx
-    fn assign_vy_to_vx(c8: &mut Chip8) {
-        let (x, y) = get_opcode_args(c8.opcode);
-        c8.v[x] = c8.v[y];
-        c8.pc = c8.pc.wrapping_add(2);
+    /// Returns the I register.
+    pub fn i(&self) -> u16 {
+        self.i
     }
 
-    /// opcode 8XY1
-    /// Assigns the value of Vx | Vy to Vx
-    fn vx_or_vy(c8: &mut Chip8) {
-        let (x, y) = get_opcode_args(c8.opcode);
-        c8.v[x] |= c8.v[y];
-        c8.pc = c8.pc.wrapping_add(2);
+    pub fn set_i(&mut self, i: u16) {
+        self.i = i;
     }
 
-    /// opcode 8XY2
-    /// Assigns the value of Vx & Vy to Vx
-    fn vx_and_vy(c8: &mut Chip8) {
-        let (x, y) = get_opcode_args(c8.opcode);
-        c8.v[x] &= c8.v[y];
-        c8.pc = c8.pc.wrapping_add(2);
+    /// Returns the register VX (0 to 15).
+    pub fn v(&self, x: usize) -> u8 {
+        self.v[x]
     }
 
-    /// opcode 8XY3
-    /// Assigns the value of Vx xor Vy to Vx
-    fn vx_xor_vy(c8: &mut Chip8) {
-        let (x, y) = get_opcode_args(c8.opcode);
-        c8.v[x] ^= c8.v[y];
-        c8.pc = c8.pc.wrapping_add(2);
+    pub fn set_v(&mut self, x: usize, value: u8) {
+        self.v[x] = value;
     }
 
-    /// opcode 8XY4
-    /// Math	Vx += Vy	Adds VY to VX. VF is set to 1 when there's a carry, and to 0 when there isn't.
-    fn add_vy_to_vx(c8: &mut Chip8) {
-        let (x, y) = get_opcode_args(c8.opcode);
-
-        c8.v[x] = c8.v[x].wrapping_add(c8.v[y]);
-
-        c8.v[0xF] = if let None = u8::checked_add(c8.v[x], c8.v[y]) {
-            1
-        } else {
-            0
-        };
-
-        c8.pc = c8.pc.wrapping_add(2);
+    /// Returns the stack pointer, i.e. the number of return addresses on the stack.
+    pub fn sp(&self) -> u16 {
+        self.sp
     }
 
-    /// opcode 8XY5
-    /// Math	Vx -= Vy	VY is subtracted from VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
-    fn sub_vy_to_vx(c8: &mut Chip8) {
-        let (x, y) = get_opcode_args(c8.opcode);
-
-        c8.v[x] = c8.v[x].wrapping_sub(c8.v[y]);
-
-        c8.v[0xF] = if let None = u8::checked_sub(c8.v[x], c8.v[y]) {
-            0
-        } else {
-            1
-        };
-
-        c8.pc = c8.pc.wrapping_add(2);
+    /// Sets the stack pointer, which must be at most the depth of the stack (16).
+    pub fn set_sp(&mut self, sp: u16) {
+        assert!(sp as usize <= STACK_SIZE, "stack pointer out of range");
+        self.sp = sp;
     }
 
-    /// opcode 8XY6
-    /// BitOp	Vx >> 1	Shifts VX right by one. VF is set to the value of the least significant bit of VX before the shift.[2]
-    fn shift_vx_right(c8: &mut Chip8) {
-        let (x, _) = get_opcode_args(c8.opcode);
-
-        c8.v[0xF] = c8.v[x] & 1;
-        c8.v[x] >>= 1;
-
-        c8.pc = c8.pc.wrapping_add(2);
+    /// Returns the return addresses on the stack, the innermost last.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
     }
 
-    /// opcode 8XY7
-    /// Math	Vx=Vy-Vx	Sets VX to VY minus VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
-    fn sub_vx_to_vy(c8: &mut Chip8) {
-        let (x, y) = get_opcode_args(c8.opcode);
-
-        c8.v[x] = c8.v[y].wrapping_sub(c8.v[x]);
-        c8.v[0xF] = if let None = u8::checked_sub(c8.v[y], c8.v[x]) {
-            0
-        } else {
-            1
-        };
-
-        c8.pc = c8.pc.wrapping_add(2);
+    /// Sets the return address at `index` (0 to 15) of the stack.
+    pub fn set_stack(&mut self, index: usize, addr: u16) {
+        self.stack[index] = addr;
     }
 
-    /// opcode 8XYE
-    /// BitOp	Vx << 1	Shifts VX left by one. VF is set to the value of the most significant bit of VX before the shift.[2]
-    fn shift_vx_left(c8: &mut Chip8) {
-        let (x, _) = get_opcode_args(c8.opcode);
-
-        c8.v[0xF] = (c8.v[x] >> 7) & 0x1;
-        c8.v[x] <<= 1;
-
-        c8.pc = c8.pc.wrapping_add(2);
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_t
     }
 
-    /// opcode 9XY0
-    /// Cond	if(Vx!=Vy)	Skips the next instruction if VX doesn't equal VY.
-    fn skip_if_vx_not_equal_to_vy(c8: &mut Chip8) {
-        let (x, y) = get_opcode_args(c8.opcode);
-
-        c8.pc = c8.pc.wrapping_add(if c8.v[x] != c8.v[y] { 4 } else { 2 });
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_t = value;
     }
 
-    /// opcode ANNN
-    /// MEM	I = NNN	Sets I to the address NNN.
-    fn set_memory_nnn(c8: &mut Chip8) {
-        c8.i = c8.opcode & 0x0FFF;
-        c8.pc = c8.pc.wrapping_add(2);
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_t
     }
 
-    /// opcode BNNN
-    /// Flow PC=V0+NNN	Jumps to the address NNN plus V0.
-    fn jump_addr_sum(c8: &mut Chip8) {
-        c8.pc = (c8.opcode & 0x0FFF).wrapping_add(c8.v[0] as u16);
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_t = value;
     }
 
-    /// opcode CXNN
-    /// Rand Vx=rand()&NN	Sets VX to the result of a bitwise and operation on a random number (Typically: 0 to 255) and NN.
-    fn rand_to_vx(c8: &mut Chip8) {
-        let (x, _) = get_opcode_args(c8.opcode);
-        let nn = get_immediate_value(c8.opcode);
-        c8.v[x] = rand::thread_rng().gen_range(0..=255) & nn;
-        c8.pc = c8.pc.wrapping_add(2);
+    /// Returns the whole memory: 4 KiB, or 64 KiB for XO-CHIP.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
     }
 
-    /// opcode DXYN
-    /// Disp	draw(Vx,Vy,N)	Draws a sprite at coordinate (VX, VY)
-    fn draw(c8: &mut Chip8) {
-        let (x, y) = get_opcode_args(c8.opcode);
-        let (x, y) = (c8.v[x] as usize, c8.v[y] as usize);
-        let height = (c8.opcode & 0xF) as usize;
-
-        c8.v[0xF] = 0;
+    /// Updates the state of the keypad. Events that don't change the state of
+    /// a key (e.g. `Down` for a key that's already pressed) are ignored, so
+    /// frontends can simply report the state of every key on every frame.
+    pub fn handle_input(&mut self, key_event: KeyEvent) {
+        match key_event {
+            KeyEvent::Down(key) => {
+                if self.key_pressed[key] {
+                    return;
+                }
 
-        for row in 0..height {
-            let pixel_row = c8.memory[(c8.i as usize) + row];
+                self.key_pressed[key] = true;
 
-            for col in 0..8 {
-                // check if pixel went from 0 to 1
-                let col_mask = 0x80 >> col;
-                let pixel_updated = col_mask & pixel_row != 0;
-                let pixel_address = x + col + ((y + row) * 64);
+                if let Some(KeyWait::Press { x }) = self.key_wait {
+                    self.key_wait = Some(KeyWait::Release { x, key });
+                }
+            }
+            KeyEvent::Up(key) => {
+                if !self.key_pressed[key] {
+                    return;
+                }
 
-                if pixel_updated {
-                    // if pixel was already 1, there's a collision
-                    let collision = c8.vram[pixel_address] == 1;
+                self.key_pressed[key] = false;
 
-                    if collision {
-                        c8.v[0xF] = 1;
+                if let Some(KeyWait::Release { x, key: waited }) = self.key_wait {
+                    if waited == key {
+                        self.v[x] = key as u8;
+                        self.key_wait = None;
                     }
-
-                    // flip the pixel
-                    c8.vram[pixel_address] ^= 1;
                 }
             }
         }
-
-        c8.draw_flag = true;
-        c8.pc = c8.pc.wrapping_add(2);
     }
 
-    /// opcode EX9E
-    /// KeyOp	if(key()==Vx)	Skips the next instruction if the key stored in VX is pressed. (Usually the next instruction is a jump to skip a code block)
-    fn skip_if_key_pressed(c8: &mut Chip8) {
-        let (x, _) = get_opcode_args(c8.opcode);
-        let pressed = c8.key_pressed[c8.v[x] as usize];
-        c8.pc = c8.pc.wrapping_add(if pressed { 4 } else { 2 });
+    /// Returns the register VX that an FX0A instruction will store the key in,
+    /// while execution is blocked waiting for a key to be pressed and released.
+    /// Timers keep running while waiting.
+    pub fn waiting_for_key(&self) -> Option<usize> {
+        match self.key_wait {
+            Some(KeyWait::Press { x }) | Some(KeyWait::Release { x, .. }) => Some(x),
+            None => None,
+        }
     }
 
-    /// opcode EXA1
-    /// KeyOp	if(key()!=Vx)	Skips the next instruction if the key stored in VX isn't pressed. (Usually the next instruction is a jump to skip a code block)
-    fn skip_if_key_not_pressed(c8: &mut Chip8) {
-        let (x, _) = get_opcode_args(c8.opcode);
-        let not_pressed = !c8.key_pressed[c8.v[x] as usize];
-        c8.pc = c8.pc.wrapping_add(if not_pressed { 4 } else { 2 });
+    /// Returns the framebuffer, one byte per pixel in row-major order.
+    /// Its dimensions are given by `display_size`.
+    pub fn get_framebuffer(&self) -> &[u8] {
+        &self.vram
     }
 
-    /// opcode FX07
-    /// Timer	Vx = get_delay()	Sets VX to the value of the delay timer.
-    fn set_vx_to_delay(c8: &mut Chip8) {
-        let (x, _) = get_opcode_args(c8.opcode);
-        c8.v[x] = c8.delay_t;
-        c8.pc = c8.pc.wrapping_add(2);
+    /// Returns the (width, height) of the active resolution: 64x32 in lores
+    /// mode and 128x64 in the SUPER-CHIP hires mode.
+    pub fn display_size(&self) -> (usize, usize) {
+        if self.hires {
+            (HIRES_WIDTH, HIRES_HEIGHT)
+        } else {
+            (LORES_WIDTH, LORES_HEIGHT)
+        }
     }
 
-    /// opcode FX0A
-    /// KeyOp	Vx = get_key()	A key press is awaited, and then stored in VX. (Blocking Operation. All instruction halted until next key event)
-    fn wait_for_key_press(c8: &mut Chip8) {
-        c8.stopped = true;
-        c8.pc = c8.pc.wrapping_add(2);
+    /// Returns the XO-CHIP audio pattern (16 bytes, played back one bit at a time)
+    /// and its playback rate in bits per second, if a program has loaded one.
+    /// Without a pattern, the sound timer plays a plain beep.
+    pub fn audio_pattern(&self) -> Option<(&[u8], f32)> {
+        let rate = 4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0);
+        self.audio_pattern.as_deref().map(|pattern| (pattern, rate))
     }
 
-    /// opcode FX15
-    /// Timer	delay_timer(Vx)	Sets the delay timer to VX.
-    fn set_delay_to_vx(c8: &mut Chip8) {
-        let (x, _) = get_opcode_args(c8.opcode);
-        c8.delay_t = c8.v[x];
-        c8.pc = c8.pc.wrapping_add(2);
-    }
+    /// Switches between lores and hires mode, clearing the screen.
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
 
-    /// opcode FX18
-    /// Sound	sound_timer(Vx)	Sets the sound timer to VX.
-    fn set_sound_to_vx(c8: &mut Chip8) {
-        let (x, _) = get_opcode_args(c8.opcode);
-        c8.sound_t = c8.v[x];
-        c8.pc = c8.pc.wrapping_add(2);
+        let (width, height) = self.display_size();
+        self.vram = vec![0; width * height];
+        self.draw_flag = true;
     }
 
-    /// opcode FX1E
-    /// MEM	I +=Vx	Adds VX to I.[3]
-    fn add_vx_to_i(c8: &mut Chip8) {
-        let (x, _) = get_opcode_args(c8.opcode);
-
-        c8.v[0xF] = if (c8.i + c8.v[x] as u16) > 0x0FFF {
-            1
-        } else {
-            0
-        };
-        c8.i += c8.v[x] as u16;
-        c8.pc = c8.pc.wrapping_add(2);
-    }
-
-    /// opcode FX29
-    /// MEM	I=sprite_addr[Vx]	Sets I to the location of the sprite for the character in VX. Characters 0-F (in hexadecimal) are represented by a 4x5 font.
-    fn set_i_to_sprite_addr(c8: &mut Chip8) {
-        let (x, _) = get_opcode_args(c8.opcode);
-        c8.i = (c8.v[x] as u16) * 5;
-        c8.pc = c8.pc.wrapping_add(2);
+    pub fn should_draw(&self) -> bool {
+        self.draw_flag
     }
 
-    /// opcode FX33
-    /// BCD	set_BCD(Vx);
-    fn set_bcd(c8: &mut Chip8) {
-        let (x, _) = get_opcode_args(c8.opcode);
-        let bcd_value = c8.v[x];
-        let addr = c8.i;
-
-        c8.write(addr, bcd_value / 100);
-        c8.write(addr + 1, (bcd_value % 100) / 10);
-        c8.write(addr + 2, (bcd_value % 100) % 10);
-
-        c8.pc = c8.pc.wrapping_add(2);
+    pub fn should_beep(&self) -> bool {
+        self.sound_t > 0
     }
+}
 
-    /// opcode FX55
-    /// MEM	reg_dump(Vx,&I)	Stores V0 to VX (including VX) in memory starting at address I.[4]
-    fn dump_registers(c8: &mut Chip8) {
-        let (x, _) = get_opcode_args(c8.opcode);
-
-        for i in 0..x {
-            let data = c8.v[i];
-            let addr = c8.i + i as u16;
-            c8.write(addr, data);
-        }
-
-        c8.pc = c8.pc.wrapping_add(2);
+impl Default for Chip8 {
+    fn default() -> Chip8 {
+        Chip8::new()
     }
+}
 
-    /// opcode FX65
-    /// MEM	reg_load(Vx,&I)	Fills V0 to VX (including VX) with values from memory starting at address I.[4]
-    fn load_registers(c8: &mut Chip8) {
-        let (x, _) = get_opcode_args(c8.opcode);
-
-        for i in 0..x {
-            c8.v[i] = c8.read(c8.i + i as u16);
-        }
-
-        c8.pc = c8.pc.wrapping_add(2);
-    }
+fn combine_bytes(low: u8, high: u8) -> u16 {
+    (high as u16) << 8 | low as u16
 }
@@ -0,0 +1,137 @@
+use super::{Chip8, Quirks, PROGRAM_START, REGISTERS, STACK_SIZE};
+
+/// Builds a `Chip8` in a given state, e.g. to test an instruction:
+///
+/// ```
+/// use rs_chip8::Chip8;
+///
+/// let mut emulator = Chip8::builder()
+///     .v(0x1, 0xF0)
+///     .v(0x2, 0x20)
+///     .memory(0x200, &[0x81, 0x24]) // ADD V1, V2
+///     .build();
+///
+/// emulator.step().unwrap();
+/// assert_eq!((emulator.v(0x1), emulator.v(0xF)), (0x10, 1));
+/// ```
+///
+/// The state not set keeps the value of a new emulator.
+#[derive(Debug, Clone)]
+pub struct Chip8Builder {
+    quirks: Quirks,
+    xo_chip: bool,
+    v: [u8; REGISTERS],
+    i: u16,
+    pc: u16,
+    stack: Vec<u16>,
+    memory: Vec<(u16, Vec<u8>)>,
+    delay_timer: u8,
+    sound_timer: u8,
+    keys: Vec<usize>,
+}
+
+impl Chip8Builder {
+    /// Uses the given `quirks` for ambiguous instructions.
+    pub fn quirks(mut self, quirks: Quirks) -> Chip8Builder {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Builds an XO-CHIP emulator, with 64 KiB of memory.
+    pub fn xo_chip(mut self) -> Chip8Builder {
+        self.xo_chip = true;
+        self
+    }
+
+    /// Sets the register VX (0 to 15).
+    pub fn v(mut self, x: usize, value: u8) -> Chip8Builder {
+        self.v[x] = value;
+        self
+    }
+
+    pub fn i(mut self, i: u16) -> Chip8Builder {
+        self.i = i;
+        self
+    }
+
+    pub fn pc(mut self, pc: u16) -> Chip8Builder {
+        self.pc = pc;
+        self
+    }
+
+    /// Sets the return addresses on the stack, the innermost last. There can be
+    /// at most 16.
+    pub fn stack(mut self, addrs: &[u16]) -> Chip8Builder {
+        assert!(addrs.len() <= STACK_SIZE, "too many return addresses");
+        self.stack = addrs.to_vec();
+        self
+    }
+
+    /// Writes `bytes` in memory from `addr` on, after the fonts are loaded.
+    pub fn memory(mut self, addr: u16, bytes: &[u8]) -> Chip8Builder {
+        self.memory.push((addr, bytes.to_vec()));
+        self
+    }
+
+    pub fn delay_timer(mut self, value: u8) -> Chip8Builder {
+        self.delay_timer = value;
+        self
+    }
+
+    pub fn sound_timer(mut self, value: u8) -> Chip8Builder {
+        self.sound_timer = value;
+        self
+    }
+
+    /// Holds down `key` (0 to 15).
+    pub fn key_pressed(mut self, key: usize) -> Chip8Builder {
+        self.keys.push(key);
+        self
+    }
+
+    /// Creates the emulator. Panics if some memory bytes are out of range.
+    pub fn build(self) -> Chip8 {
+        let mut c8 = Chip8::build(self.quirks, self.xo_chip);
+
+        c8.v.copy_from_slice(&self.v);
+        c8.i = self.i;
+        c8.pc = self.pc;
+        c8.stack[..self.stack.len()].copy_from_slice(&self.stack);
+        c8.sp = self.stack.len() as u16;
+        c8.delay_t = self.delay_timer;
+        c8.sound_t = self.sound_timer;
+
+        for (addr, bytes) in &self.memory {
+            let start = *addr as usize;
+            assert!(
+                start + bytes.len() <= c8.memory.len(),
+                "memory bytes out of range"
+            );
+            c8.memory[start..start + bytes.len()].copy_from_slice(bytes);
+        }
+
+        for &key in &self.keys {
+            c8.key_pressed[key] = true;
+        }
+
+        c8
+    }
+}
+
+impl Chip8 {
+    /// Returns a builder for an emulator in a given state, see `Chip8Builder`.
+    pub fn builder() -> Chip8Builder {
+        Chip8Builder {
+            quirks: Quirks::default(),
+            xo_chip: false,
+            v: [0; REGISTERS],
+            i: 0,
+            pc: PROGRAM_START,
+            stack: Vec::new(),
+            memory: Vec::new(),
+            delay_timer: 0,
+            sound_timer: 0,
+            keys: Vec::new(),
+        }
+    }
+}
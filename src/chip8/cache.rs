@@ -0,0 +1,74 @@
+use super::{combine_bytes, Chip8, Chip8Error};
+use crate::instruction::Instruction;
+
+impl Chip8 {
+    /// Enables or disables the cache of decoded instructions.
+    ///
+    /// With the cache, the instruction at an address is fetched and decoded the
+    /// first time it's executed only. Writes to memory, through `write` or by
+    /// FX33, FX55 and 5XY2, drop the instructions they overlap, so programs
+    /// modifying their own code behave the same. This speeds up long runs,
+    /// e.g. in batch, at the cost of a cache entry per byte of memory.
+    pub fn set_instruction_cache(&mut self, enabled: bool) {
+        self.decoded = if enabled {
+            Some(vec![None; self.memory.len()])
+        } else {
+            None
+        };
+    }
+
+    /// Returns whether decoded instructions are cached, see `set_instruction_cache`.
+    pub fn instruction_cache(&self) -> bool {
+        self.decoded.is_some()
+    }
+
+    /// Fetches and decodes the instruction at the PC, unless it's cached.
+    pub(super) fn fetch(&mut self) -> Result<Instruction, Chip8Error> {
+        let pc = self.pc as usize;
+        let cached = self
+            .decoded
+            .as_ref()
+            .and_then(|decoded| decoded.get(pc).copied().flatten());
+        if let Some(instruction) = cached {
+            return Ok(instruction);
+        }
+
+        let opcode = combine_bytes(
+            self.fetch_byte(self.pc.wrapping_add(1))?,
+            self.fetch_byte(self.pc)?,
+        );
+        let instruction = Instruction::decode(opcode).map_err(|_| Chip8Error::UnknownOpcode {
+            opcode,
+            addr: self.pc,
+        })?;
+
+        if let Some(entry) = self
+            .decoded
+            .as_mut()
+            .and_then(|decoded| decoded.get_mut(pc))
+        {
+            *entry = Some(instruction);
+        }
+
+        Ok(instruction)
+    }
+
+    /// Drops the cached instructions including the byte at `addr`: the one
+    /// starting there, and the one starting at the previous byte.
+    pub(super) fn invalidate(&mut self, addr: u16) {
+        if let Some(decoded) = &mut self.decoded {
+            let addr = addr as usize;
+            decoded[addr] = None;
+            if addr > 0 {
+                decoded[addr - 1] = None;
+            }
+        }
+    }
+
+    /// Drops every cached instruction, after the whole memory changed.
+    pub(super) fn clear_instruction_cache(&mut self) {
+        if self.decoded.is_some() {
+            self.set_instruction_cache(true);
+        }
+    }
+}
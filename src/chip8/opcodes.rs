@@ -0,0 +1,719 @@
+use crate::chip8::Chip8;
+use crate::chip8::{
+    combine_bytes, Chip8Error, KeyWait, AUDIO_PATTERN_SIZE, BIG_FONT_ADDR, STACK_SIZE,
+};
+use crate::instruction::Instruction;
+use rand::Rng;
+
+/// Executes `instruction`, which was fetched from the address in the PC.
+pub fn execute(c8: &mut Chip8, instruction: Instruction) -> Result<(), Chip8Error> {
+    match instruction {
+        Instruction::ScrollDown(n) => scroll_down(c8, n),
+        Instruction::ScrollUp(n) => scroll_up(c8, n),
+        Instruction::ClearScreen => clear_screen(c8),
+        Instruction::Return => return_from_sub(c8),
+        Instruction::ScrollRight => scroll_right(c8),
+        Instruction::ScrollLeft => scroll_left(c8),
+        Instruction::Exit => exit(c8),
+        Instruction::Lores => lores(c8),
+        Instruction::Hires => hires(c8),
+        Instruction::Jump(addr) => jump_addr(c8, addr),
+        Instruction::Call(addr) => call_sub_at_nnn(c8, addr),
+        Instruction::SkipEqImm { x, nn } => skip_if_vx_equal_to_nn(c8, x, nn),
+        Instruction::SkipNeImm { x, nn } => skip_if_vx_not_equal_to_nn(c8, x, nn),
+        Instruction::SkipEqReg { x, y } => skip_if_vx_equal_to_vy(c8, x, y),
+        Instruction::SaveRange { x, y } => save_vx_to_vy(c8, x, y),
+        Instruction::LoadRange { x, y } => load_vx_to_vy(c8, x, y),
+        Instruction::SetImm { x, nn } => set_vx_to_immediate(c8, x, nn),
+        Instruction::AddImm { x, nn } => add_nn_to_vx(c8, x, nn),
+        Instruction::Assign { x, y } => assign_vy_to_vx(c8, x, y),
+        Instruction::Or { x, y } => vx_or_vy(c8, x, y),
+        Instruction::And { x, y } => vx_and_vy(c8, x, y),
+        Instruction::Xor { x, y } => vx_xor_vy(c8, x, y),
+        Instruction::Add { x, y } => add_vy_to_vx(c8, x, y),
+        Instruction::Sub { x, y } => sub_vy_to_vx(c8, x, y),
+        Instruction::ShiftRight { x, y } => shift_vx_right(c8, x, y),
+        Instruction::SubN { x, y } => sub_vx_to_vy(c8, x, y),
+        Instruction::ShiftLeft { x, y } => shift_vx_left(c8, x, y),
+        Instruction::SkipNeReg { x, y } => skip_if_vx_not_equal_to_vy(c8, x, y),
+        Instruction::SetI(addr) => set_memory_nnn(c8, addr),
+        Instruction::JumpOffset(addr) => jump_addr_sum(c8, addr),
+        Instruction::Random { x, nn } => rand_to_vx(c8, x, nn),
+        Instruction::Draw { x, y, n } => draw(c8, x, y, n),
+        Instruction::SkipKeyPressed { x } => skip_if_key_pressed(c8, x),
+        Instruction::SkipKeyNotPressed { x } => skip_if_key_not_pressed(c8, x),
+        Instruction::LoadILong => load_i_long(c8),
+        Instruction::SelectPlane(n) => select_plane(c8, n),
+        Instruction::LoadAudio => load_audio_pattern(c8),
+        Instruction::GetDelay { x } => set_vx_to_delay(c8, x),
+        Instruction::WaitKey { x } => wait_for_key_press(c8, x),
+        Instruction::SetDelay { x } => set_delay_to_vx(c8, x),
+        Instruction::SetSound { x } => set_sound_to_vx(c8, x),
+        Instruction::AddI { x } => add_vx_to_i(c8, x),
+        Instruction::Font { x } => set_i_to_sprite_addr(c8, x),
+        Instruction::BigFont { x } => set_i_to_big_sprite_addr(c8, x),
+        Instruction::Bcd { x } => set_bcd(c8, x),
+        Instruction::Pitch { x } => set_pitch(c8, x),
+        Instruction::Store { x } => dump_registers(c8, x),
+        Instruction::Load { x } => load_registers(c8, x),
+        Instruction::SaveFlags { x } => save_rpl_flags(c8, x),
+        Instruction::LoadFlags { x } => load_rpl_flags(c8, x),
+    }
+}
+
+/// Moves the PC past the next instruction if `condition` holds, or to it otherwise.
+/// The XO-CHIP F000 NNNN instruction is 4 bytes long, so it's skipped entirely.
+fn skip_next_if(c8: &mut Chip8, condition: bool) -> Result<(), Chip8Error> {
+    let next = c8.pc.wrapping_add(2);
+
+    c8.pc = if !condition {
+        next
+    } else if combine_bytes(c8.fetch_byte(next.wrapping_add(1))?, c8.fetch_byte(next)?) == 0xF000 {
+        next.wrapping_add(4)
+    } else {
+        next.wrapping_add(2)
+    };
+
+    Ok(())
+}
+
+/// Moves the pixels of the selected planes by (`dx`, `dy`), filling the gaps with 0s.
+fn scroll(c8: &mut Chip8, dx: isize, dy: isize) {
+    let (width, height) = c8.display_size();
+    let planes = c8.plane;
+    let old = c8.vram.clone();
+
+    for y in 0..height {
+        for x in 0..width {
+            let (src_x, src_y) = (x as isize - dx, y as isize - dy);
+            let in_bounds =
+                (0..width as isize).contains(&src_x) && (0..height as isize).contains(&src_y);
+            let moved = if in_bounds {
+                old[src_y as usize * width + src_x as usize] & planes
+            } else {
+                0
+            };
+
+            let pixel = y * width + x;
+            c8.vram[pixel] = (old[pixel] & !planes) | moved;
+        }
+    }
+
+    c8.draw_flag = true;
+    c8.pc = c8.pc.wrapping_add(2);
+}
+
+/// opcode 6XNN
+fn set_vx_to_immediate(c8: &mut Chip8, x: usize, nn: u8) -> Result<(), Chip8Error> {
+    c8.v[x] = nn;
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode 00E0.
+/// Resets the screen pixel values of the selected planes
+fn clear_screen(c8: &mut Chip8) -> Result<(), Chip8Error> {
+    let planes = c8.plane;
+
+    for pixel in c8.vram.iter_mut() {
+        *pixel &= !planes;
+    }
+
+    c8.draw_flag = true;
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode 00CN.
+/// Scrolls the selected planes down by N pixels.
+fn scroll_down(c8: &mut Chip8, n: u8) -> Result<(), Chip8Error> {
+    scroll(c8, 0, n as isize);
+
+    Ok(())
+}
+
+/// opcode 00DN.
+/// Scrolls the selected planes up by N pixels (XO-CHIP).
+fn scroll_up(c8: &mut Chip8, n: u8) -> Result<(), Chip8Error> {
+    scroll(c8, 0, -(n as isize));
+
+    Ok(())
+}
+
+/// opcode 00FB.
+/// Scrolls the selected planes right by 4 pixels.
+fn scroll_right(c8: &mut Chip8) -> Result<(), Chip8Error> {
+    scroll(c8, 4, 0);
+
+    Ok(())
+}
+
+/// opcode 00FC.
+/// Scrolls the selected planes left by 4 pixels.
+fn scroll_left(c8: &mut Chip8) -> Result<(), Chip8Error> {
+    scroll(c8, -4, 0);
+
+    Ok(())
+}
+
+/// opcode 00FD.
+/// Exits the interpreter, no further instructions are executed.
+fn exit(c8: &mut Chip8) -> Result<(), Chip8Error> {
+    c8.exited = true;
+
+    Ok(())
+}
+
+/// opcode 00FE.
+/// Switches to the 64x32 lores mode.
+fn lores(c8: &mut Chip8) -> Result<(), Chip8Error> {
+    c8.set_hires(false);
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode 00FF.
+/// Switches to the 128x64 hires mode.
+fn hires(c8: &mut Chip8) -> Result<(), Chip8Error> {
+    c8.set_hires(true);
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode 00EE.
+/// Returns from a subroutine, meaning it will set the PC to the last stack value.
+fn return_from_sub(c8: &mut Chip8) -> Result<(), Chip8Error> {
+    if c8.sp == 0 {
+        return Err(Chip8Error::StackUnderflow { addr: c8.pc });
+    }
+
+    c8.sp -= 1;
+    c8.pc = c8.stack[c8.sp as usize].wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode 1NNN.
+/// Sets the program counter to NNN.
+fn jump_addr(c8: &mut Chip8, addr: u16) -> Result<(), Chip8Error> {
+    c8.pc = addr;
+
+    Ok(())
+}
+
+/// opcode 2NNN.
+/// It will call the subroutine at address NNN, i.e. move the PC to it.
+fn call_sub_at_nnn(c8: &mut Chip8, addr: u16) -> Result<(), Chip8Error> {
+    if c8.sp as usize >= STACK_SIZE {
+        return Err(Chip8Error::StackOverflow { addr: c8.pc });
+    }
+
+    c8.stack[c8.sp as usize] = c8.pc;
+    c8.sp += 1;
+    c8.pc = addr;
+
+    Ok(())
+}
+
+/// opcode 3XNN.
+/// It will skip the next instruction if Vx == NN.
+fn skip_if_vx_equal_to_nn(c8: &mut Chip8, x: usize, nn: u8) -> Result<(), Chip8Error> {
+    skip_next_if(c8, c8.v[x] == nn)
+}
+
+/// opcode 4XNN.
+/// It will skip the next instruction if Vx != NN.
+fn skip_if_vx_not_equal_to_nn(c8: &mut Chip8, x: usize, nn: u8) -> Result<(), Chip8Error> {
+    skip_next_if(c8, c8.v[x] != nn)
+}
+
+/// opcode 5XY0.
+/// It will skip the next instruction if Vx == Vy.
+fn skip_if_vx_equal_to_vy(c8: &mut Chip8, x: usize, y: usize) -> Result<(), Chip8Error> {
+    skip_next_if(c8, c8.v[x] == c8.v[y])
+}
+
+/// opcode 5XY2
+/// Stores VX to VY (in either order) in memory starting at address I, leaving I unchanged (XO-CHIP).
+fn save_vx_to_vy(c8: &mut Chip8, x: usize, y: usize) -> Result<(), Chip8Error> {
+    let registers: Vec<usize> = if x <= y {
+        (x..=y).collect()
+    } else {
+        (y..=x).rev().collect()
+    };
+
+    for (offset, reg) in registers.into_iter().enumerate() {
+        let addr = c8.i.wrapping_add(offset as u16);
+        c8.write(addr, c8.v[reg])?;
+    }
+
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode 5XY3
+/// Fills VX to VY (in either order) from memory starting at address I, leaving I unchanged (XO-CHIP).
+fn load_vx_to_vy(c8: &mut Chip8, x: usize, y: usize) -> Result<(), Chip8Error> {
+    let registers: Vec<usize> = if x <= y {
+        (x..=y).collect()
+    } else {
+        (y..=x).rev().collect()
+    };
+
+    for (offset, reg) in registers.into_iter().enumerate() {
+        c8.v[reg] = c8.read(c8.i.wrapping_add(offset as u16))?;
+    }
+
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode 7XNN
+/// It will add NN to the Vx register
+fn add_nn_to_vx(c8: &mut Chip8, x: usize, nn: u8) -> Result<(), Chip8Error> {
+    c8.v[x] = c8.v[x].wrapping_add(nn);
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode 8XY0
+/// Assigns the value of Vy to Vx
+fn assign_vy_to_vx(c8: &mut Chip8, x: usize, y: usize) -> Result<(), Chip8Error> {
+    c8.v[x] = c8.v[y];
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode 8XY1
+/// Assigns the value of Vx | Vy to Vx
+fn vx_or_vy(c8: &mut Chip8, x: usize, y: usize) -> Result<(), Chip8Error> {
+    c8.v[x] |= c8.v[y];
+    if c8.quirks.logic_resets_vf {
+        c8.v[0xF] = 0;
+    }
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode 8XY2
+/// Assigns the value of Vx & Vy to Vx
+fn vx_and_vy(c8: &mut Chip8, x: usize, y: usize) -> Result<(), Chip8Error> {
+    c8.v[x] &= c8.v[y];
+    if c8.quirks.logic_resets_vf {
+        c8.v[0xF] = 0;
+    }
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode 8XY3
+/// Assigns the value of Vx xor Vy to Vx
+fn vx_xor_vy(c8: &mut Chip8, x: usize, y: usize) -> Result<(), Chip8Error> {
+    c8.v[x] ^= c8.v[y];
+    if c8.quirks.logic_resets_vf {
+        c8.v[0xF] = 0;
+    }
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode 8XY4
+/// Math Vx += Vy Adds VY to VX. VF is set to 1 when there's a carry, and to 0 when there isn't.
+/// The flag is written last, so it overwrites the result when X is F.
+fn add_vy_to_vx(c8: &mut Chip8, x: usize, y: usize) -> Result<(), Chip8Error> {
+    let (sum, carry) = c8.v[x].overflowing_add(c8.v[y]);
+
+    c8.v[x] = sum;
+    c8.v[0xF] = carry as u8;
+
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode 8XY5
+/// Math Vx -= Vy VY is subtracted from VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
+/// The flag is written last, so it overwrites the result when X is F.
+fn sub_vy_to_vx(c8: &mut Chip8, x: usize, y: usize) -> Result<(), Chip8Error> {
+    let (difference, borrow) = c8.v[x].overflowing_sub(c8.v[y]);
+
+    c8.v[x] = difference;
+    c8.v[0xF] = !borrow as u8;
+
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode 8XY6
+/// BitOp Vx >> 1 Shifts VX right by one. VF is set to the value of the least significant bit of VX before the shift.[2]
+/// With the `shift_uses_vy` quirk VY is shifted instead, and the result stored in VX.
+fn shift_vx_right(c8: &mut Chip8, x: usize, y: usize) -> Result<(), Chip8Error> {
+    let value = if c8.quirks.shift_uses_vy {
+        c8.v[y]
+    } else {
+        c8.v[x]
+    };
+
+    c8.v[x] = value >> 1;
+    c8.v[0xF] = value & 1;
+
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode 8XY7
+/// Math Vx=Vy-Vx Sets VX to VY minus VX. VF is set to 0 when there's a borrow, and 1 when there isn't.
+/// The flag is written last, so it overwrites the result when X is F.
+fn sub_vx_to_vy(c8: &mut Chip8, x: usize, y: usize) -> Result<(), Chip8Error> {
+    let (difference, borrow) = c8.v[y].overflowing_sub(c8.v[x]);
+
+    c8.v[x] = difference;
+    c8.v[0xF] = !borrow as u8;
+
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode 8XYE
+/// BitOp Vx << 1 Shifts VX left by one. VF is set to the value of the most significant bit of VX before the shift.[2]
+/// With the `shift_uses_vy` quirk VY is shifted instead, and the result stored in VX.
+fn shift_vx_left(c8: &mut Chip8, x: usize, y: usize) -> Result<(), Chip8Error> {
+    let value = if c8.quirks.shift_uses_vy {
+        c8.v[y]
+    } else {
+        c8.v[x]
+    };
+
+    c8.v[x] = value << 1;
+    c8.v[0xF] = (value >> 7) & 0x1;
+
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode 9XY0
+/// Cond if(Vx!=Vy) Skips the next instruction if VX doesn't equal VY.
+fn skip_if_vx_not_equal_to_vy(c8: &mut Chip8, x: usize, y: usize) -> Result<(), Chip8Error> {
+    skip_next_if(c8, c8.v[x] != c8.v[y])
+}
+
+/// opcode ANNN
+/// MEM I = NNN Sets I to the address NNN.
+fn set_memory_nnn(c8: &mut Chip8, addr: u16) -> Result<(), Chip8Error> {
+    c8.i = addr;
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode BNNN
+/// Flow PC=V0+NNN Jumps to the address NNN plus V0.
+/// With the `jump_uses_vx` quirk the offset is taken from VX instead of V0.
+fn jump_addr_sum(c8: &mut Chip8, addr: u16) -> Result<(), Chip8Error> {
+    let x = ((addr >> 8) & 0xF) as usize;
+    let offset = if c8.quirks.jump_uses_vx {
+        c8.v[x]
+    } else {
+        c8.v[0]
+    };
+    c8.pc = addr.wrapping_add(offset as u16);
+
+    Ok(())
+}
+
+/// opcode CXNN
+/// Rand Vx=rand()&NN Sets VX to the result of a bitwise and operation on a random number (Typically: 0 to 255) and NN.
+fn rand_to_vx(c8: &mut Chip8, x: usize, nn: u8) -> Result<(), Chip8Error> {
+    c8.v[x] = rand::thread_rng().gen_range(0..=255) & nn;
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode DXYN
+/// Disp draw(Vx,Vy,N) Draws a sprite at coordinate (VX, VY)
+/// The starting coordinate always wraps around the screen, while the parts of
+/// the sprite that go past an edge are clipped or wrapped depending on the `clip_sprites` quirk.
+/// When N is 0, a 16x16 sprite made of 2 bytes per row is drawn instead (SUPER-CHIP).
+/// With both XO-CHIP planes selected, the sprite for the second plane follows the first one in memory.
+fn draw(c8: &mut Chip8, x: usize, y: usize, n: u8) -> Result<(), Chip8Error> {
+    let (width, height) = c8.display_size();
+    let (x, y) = (c8.v[x] as usize % width, c8.v[y] as usize % height);
+    let (sprite_width, sprite_height) = match n as usize {
+        0 => (16, 16),
+        n => (8, n),
+    };
+    let bytes_per_row = sprite_width / 8;
+    let mut sprite_addr = c8.i;
+
+    c8.v[0xF] = 0;
+
+    for plane in [1u8, 2] {
+        if c8.plane & plane == 0 {
+            continue;
+        }
+
+        for row in 0..sprite_height {
+            let py = y + row;
+
+            if py >= height && c8.quirks.clip_sprites {
+                break;
+            }
+
+            // the row's pixels, left aligned in 16 bits
+            let mut pixel_row = 0u16;
+            for byte in 0..bytes_per_row {
+                let offset = (row * bytes_per_row + byte) as u16;
+                pixel_row |= (c8.read(sprite_addr.wrapping_add(offset))? as u16) << (8 - byte * 8);
+            }
+
+            for col in 0..sprite_width {
+                let px = x + col;
+
+                if px >= width && c8.quirks.clip_sprites {
+                    break;
+                }
+
+                // check if pixel went from 0 to 1
+                let col_mask = 0x8000 >> col;
+                let pixel_updated = col_mask & pixel_row != 0;
+                let pixel_address = (px % width) + ((py % height) * width);
+
+                if pixel_updated {
+                    // if pixel was already set on this plane, there's a collision
+                    let collision = c8.vram[pixel_address] & plane != 0;
+
+                    if collision {
+                        c8.v[0xF] = 1;
+                    }
+
+                    // flip the pixel
+                    c8.vram[pixel_address] ^= plane;
+                }
+            }
+        }
+
+        sprite_addr = sprite_addr.wrapping_add((sprite_height * bytes_per_row) as u16);
+    }
+
+    if c8.quirks.display_wait {
+        c8.vblank_wait = true;
+    }
+
+    c8.draw_flag = true;
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode EX9E
+/// KeyOp if(key()==Vx) Skips the next instruction if the key stored in VX is pressed. (Usually the next instruction is a jump to skip a code block)
+fn skip_if_key_pressed(c8: &mut Chip8, x: usize) -> Result<(), Chip8Error> {
+    let pressed = c8.key_pressed[(c8.v[x] & 0xF) as usize];
+    skip_next_if(c8, pressed)
+}
+
+/// opcode EXA1
+/// KeyOp if(key()!=Vx) Skips the next instruction if the key stored in VX isn't pressed. (Usually the next instruction is a jump to skip a code block)
+fn skip_if_key_not_pressed(c8: &mut Chip8, x: usize) -> Result<(), Chip8Error> {
+    let not_pressed = !c8.key_pressed[(c8.v[x] & 0xF) as usize];
+    skip_next_if(c8, not_pressed)
+}
+
+/// opcode F000 NNNN
+/// MEM I = NNNN Sets I to the 16-bit address stored after the opcode (XO-CHIP).
+fn load_i_long(c8: &mut Chip8) -> Result<(), Chip8Error> {
+    let addr = c8.pc.wrapping_add(2);
+    c8.i = combine_bytes(c8.fetch_byte(addr.wrapping_add(1))?, c8.fetch_byte(addr)?);
+    c8.pc = c8.pc.wrapping_add(4);
+
+    Ok(())
+}
+
+/// opcode FN01
+/// Selects the drawing planes from the bitmask N (XO-CHIP).
+fn select_plane(c8: &mut Chip8, n: u8) -> Result<(), Chip8Error> {
+    c8.plane = n & 0x3;
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode F002
+/// Sound Loads the 16-byte audio pattern starting at address I (XO-CHIP).
+fn load_audio_pattern(c8: &mut Chip8) -> Result<(), Chip8Error> {
+    let pattern = (0..AUDIO_PATTERN_SIZE)
+        .map(|offset| c8.read(c8.i.wrapping_add(offset as u16)))
+        .collect::<Result<_, _>>()?;
+
+    c8.audio_pattern = Some(pattern);
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode FX07
+/// Timer Vx = get_delay() Sets VX to the value of the delay timer.
+fn set_vx_to_delay(c8: &mut Chip8, x: usize) -> Result<(), Chip8Error> {
+    c8.v[x] = c8.delay_t;
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode FX0A
+/// KeyOp Vx = get_key() A key press is awaited, and then stored in VX. (Blocking Operation. All instruction halted until next key event)
+/// Execution resumes after the key is released, see `Chip8::handle_input`.
+fn wait_for_key_press(c8: &mut Chip8, x: usize) -> Result<(), Chip8Error> {
+    c8.key_wait = Some(KeyWait::Press { x });
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode FX15
+/// Timer delay_timer(Vx) Sets the delay timer to VX.
+fn set_delay_to_vx(c8: &mut Chip8, x: usize) -> Result<(), Chip8Error> {
+    c8.delay_t = c8.v[x];
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode FX18
+/// Sound sound_timer(Vx) Sets the sound timer to VX.
+fn set_sound_to_vx(c8: &mut Chip8, x: usize) -> Result<(), Chip8Error> {
+    c8.sound_t = c8.v[x];
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode FX1E
+/// MEM I +=Vx Adds VX to I.[3]
+/// VF is set to 1 when I goes past 0xFFF, after VX is read so that X can be F.
+fn add_vx_to_i(c8: &mut Chip8, x: usize) -> Result<(), Chip8Error> {
+    let value = c8.v[x] as u16;
+
+    c8.v[0xF] = if (c8.i as u32 + value as u32) > 0x0FFF {
+        1
+    } else {
+        0
+    };
+    c8.i = c8.i.wrapping_add(value);
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode FX29
+/// MEM I=sprite_addr[Vx] Sets I to the location of the sprite for the character in VX. Characters 0-F (in hexadecimal) are represented by a 4x5 font.
+/// Only the low nibble of VX is used, as on the VIP.
+fn set_i_to_sprite_addr(c8: &mut Chip8, x: usize) -> Result<(), Chip8Error> {
+    c8.i = ((c8.v[x] & 0xF) as u16) * 5;
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode FX30
+/// MEM I=big_sprite_addr[Vx] Sets I to the location of the 8x10 sprite for the digit in VX (SUPER-CHIP).
+fn set_i_to_big_sprite_addr(c8: &mut Chip8, x: usize) -> Result<(), Chip8Error> {
+    c8.i = (BIG_FONT_ADDR as u16) + ((c8.v[x] & 0xF) as u16) * 10;
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode FX33
+/// BCD set_BCD(Vx);
+fn set_bcd(c8: &mut Chip8, x: usize) -> Result<(), Chip8Error> {
+    let bcd_value = c8.v[x];
+    let addr = c8.i;
+
+    c8.write(addr, bcd_value / 100)?;
+    c8.write(addr.wrapping_add(1), (bcd_value % 100) / 10)?;
+    c8.write(addr.wrapping_add(2), (bcd_value % 100) % 10)?;
+
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode FX3A
+/// Sound Sets the audio pattern playback pitch to VX (XO-CHIP).
+fn set_pitch(c8: &mut Chip8, x: usize) -> Result<(), Chip8Error> {
+    c8.pitch = c8.v[x];
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode FX55
+/// MEM reg_dump(Vx,&I) Stores V0 to VX (including VX) in memory starting at address I.[4]
+/// With the `load_store_increments_i` quirk, I is left pointing after the last stored value.
+fn dump_registers(c8: &mut Chip8, x: usize) -> Result<(), Chip8Error> {
+    for i in 0..=x {
+        let data = c8.v[i];
+        let addr = c8.i.wrapping_add(i as u16);
+        c8.write(addr, data)?;
+    }
+
+    if c8.quirks.load_store_increments_i {
+        c8.i = c8.i.wrapping_add(x as u16 + 1);
+    }
+
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode FX65
+/// MEM reg_load(Vx,&I) Fills V0 to VX (including VX) with values from memory starting at address I.[4]
+/// With the `load_store_increments_i` quirk, I is left pointing after the last loaded value.
+fn load_registers(c8: &mut Chip8, x: usize) -> Result<(), Chip8Error> {
+    for i in 0..=x {
+        c8.v[i] = c8.read(c8.i.wrapping_add(i as u16))?;
+    }
+
+    if c8.quirks.load_store_increments_i {
+        c8.i = c8.i.wrapping_add(x as u16 + 1);
+    }
+
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode FX75
+/// Stores V0 to VX (including VX) in the RPL user flags (SUPER-CHIP).
+fn save_rpl_flags(c8: &mut Chip8, x: usize) -> Result<(), Chip8Error> {
+    c8.rpl[..=x].copy_from_slice(&c8.v[..=x]);
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
+
+/// opcode FX85
+/// Fills V0 to VX (including VX) from the RPL user flags (SUPER-CHIP).
+fn load_rpl_flags(c8: &mut Chip8, x: usize) -> Result<(), Chip8Error> {
+    c8.v[..=x].copy_from_slice(&c8.rpl[..=x]);
+    c8.pc = c8.pc.wrapping_add(2);
+
+    Ok(())
+}
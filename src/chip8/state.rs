@@ -0,0 +1,400 @@
+//! Save states: a snapshot of the whole machine in a versioned binary format.
+//!
+//! A state starts with a header:
+//!
+//! | bytes | content                                                       |
+//! |-------|---------------------------------------------------------------|
+//! | 4     | magic, `C8SS`                                                 |
+//! | 2     | version of the format that wrote the state                    |
+//! | 2     | oldest version able to read the state                         |
+//! | 8     | hash of the ROM the state was saved from                      |
+//!
+//! followed by chunks, each made of a 4 bytes tag, a 4 bytes length and the payload.
+//! All numbers are big-endian.
+//!
+//! Newer versions can add chunks, or append fields at the end of a chunk, while staying
+//! readable by older ones: unknown chunks and trailing bytes are skipped, and the oldest
+//! compatible version is only bumped when existing fields change meaning. When reading
+//! a state written by an older version, chunks that didn't exist yet leave the
+//! corresponding part of the machine unchanged, then `migrate` brings the machine up
+//! to date, e.g. computing what that version didn't save.
+
+use std::error;
+use std::fmt;
+
+use super::{
+    Chip8, Chip8Error, KeyWait, Quirks, AUDIO_PATTERN_SIZE, HIRES_HEIGHT, HIRES_WIDTH,
+    LORES_HEIGHT, LORES_WIDTH, MEMORY_SIZE, REGISTERS, RPL_FLAGS, STACK_SIZE, XO_MEMORY_SIZE,
+};
+
+const MAGIC: &[u8; 4] = b"C8SS";
+
+/// Version of the format written by `save_state`.
+const VERSION: u16 = 1;
+
+/// Oldest version able to read the states written by `save_state`.
+const MIN_READER_VERSION: u16 = 1;
+
+const HEADER_SIZE: usize = 16;
+
+const CPU_CHUNK: &[u8; 4] = b"CPU ";
+const MEMORY_CHUNK: &[u8; 4] = b"MEM ";
+const VIDEO_CHUNK: &[u8; 4] = b"VID ";
+const AUDIO_CHUNK: &[u8; 4] = b"SND ";
+const RPL_CHUNK: &[u8; 4] = b"RPL ";
+const KEYS_CHUNK: &[u8; 4] = b"KEYS";
+const QUIRKS_CHUNK: &[u8; 4] = b"QRKS";
+
+/// Errors that prevent a save state from being loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    /// The data doesn't start with the save state header.
+    BadMagic,
+    /// The state was written by version `version` of the format, which needs a newer emulator.
+    UnsupportedVersion { version: u16 },
+    /// The state was saved while running a different ROM.
+    RomMismatch,
+    /// The data ends in the middle of the chunk `tag`, or of the header.
+    Truncated { tag: [u8; 4] },
+    /// The chunk `tag` holds values that don't fit the machine.
+    Invalid { tag: [u8; 4] },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion { version } => write!(
+                f,
+                "save state version {} is not supported, at most {} is",
+                version, VERSION
+            ),
+            StateError::RomMismatch => write!(f, "save state is for a different ROM"),
+            StateError::Truncated { tag } => write!(
+                f,
+                "save state is truncated in chunk '{}'",
+                String::from_utf8_lossy(tag)
+            ),
+            StateError::Invalid { tag } => write!(
+                f,
+                "save state has invalid values in chunk '{}'",
+                String::from_utf8_lossy(tag)
+            ),
+        }
+    }
+}
+
+impl error::Error for StateError {}
+
+/// FNV-1a hash, identifying the ROM a state belongs to.
+pub(super) fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Reads the fields of a chunk (or of the header) in order.
+struct Reader<'a> {
+    data: &'a [u8],
+    tag: [u8; 4],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::Truncated { tag: self.tag });
+        }
+
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, StateError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, StateError> {
+        let mut value = [0; 8];
+        value.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_be_bytes(value))
+    }
+
+    fn bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn invalid(&self) -> StateError {
+        StateError::Invalid { tag: self.tag }
+    }
+}
+
+fn push_chunk(out: &mut Vec<u8>, tag: &[u8; 4], payload: &[u8]) {
+    out.extend_from_slice(tag);
+    out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    out.extend_from_slice(payload);
+}
+
+fn encode_quirks(quirks: &Quirks) -> u8 {
+    [
+        quirks.shift_uses_vy,
+        quirks.load_store_increments_i,
+        quirks.jump_uses_vx,
+        quirks.logic_resets_vf,
+        quirks.clip_sprites,
+        quirks.display_wait,
+    ]
+    .iter()
+    .enumerate()
+    .fold(0, |bits, (index, &set)| bits | (set as u8) << index)
+}
+
+fn decode_quirks(bits: u8) -> Quirks {
+    let set = |index: u8| bits & (1 << index) != 0;
+
+    Quirks {
+        shift_uses_vy: set(0),
+        load_store_increments_i: set(1),
+        jump_uses_vx: set(2),
+        logic_resets_vf: set(3),
+        clip_sprites: set(4),
+        display_wait: set(5),
+    }
+}
+
+/// Encodes `error` as a kind (0 for no error) followed by two addresses.
+fn encode_error(out: &mut Vec<u8>, error: Option<Chip8Error>) {
+    let (kind, a, b) = match error {
+        None => (0, 0, 0),
+        Some(Chip8Error::UnknownOpcode { opcode, addr }) => (1, opcode, addr),
+        Some(Chip8Error::StackOverflow { addr }) => (2, addr, 0),
+        Some(Chip8Error::StackUnderflow { addr }) => (3, addr, 0),
+        Some(Chip8Error::MemoryOutOfRange { addr, target }) => (4, addr, target),
+    };
+
+    out.push(kind);
+    out.extend_from_slice(&u16::to_be_bytes(a));
+    out.extend_from_slice(&u16::to_be_bytes(b));
+}
+
+fn decode_error(reader: &mut Reader) -> Result<Option<Chip8Error>, StateError> {
+    let kind = reader.u8()?;
+    let a = reader.u16()?;
+    let b = reader.u16()?;
+
+    match kind {
+        0 => Ok(None),
+        1 => Ok(Some(Chip8Error::UnknownOpcode { opcode: a, addr: b })),
+        2 => Ok(Some(Chip8Error::StackOverflow { addr: a })),
+        3 => Ok(Some(Chip8Error::StackUnderflow { addr: a })),
+        4 => Ok(Some(Chip8Error::MemoryOutOfRange { addr: a, target: b })),
+        _ => Err(reader.invalid()),
+    }
+}
+
+/// Steps updating a machine loaded from a state written by an older version of
+/// the format: `MIGRATIONS[n]` takes it from version `n + 1` to `n + 2`. Version 1
+/// is the first one, so there are none yet.
+const MIGRATIONS: [fn(&mut Chip8); VERSION as usize - 1] = [];
+
+/// Updates a machine loaded from a state written by `version` of the format,
+/// applying the migrations from that version on.
+fn migrate(machine: &mut Chip8, version: u16) {
+    for step in MIGRATIONS.iter().skip(version as usize - 1) {
+        step(machine);
+    }
+}
+
+impl Chip8 {
+    /// Takes a snapshot of the whole machine, that `load_state` can restore.
+    /// See the `state` module for the format.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_SIZE + self.memory.len() + self.vram.len() + 256);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_be_bytes());
+        out.extend_from_slice(&MIN_READER_VERSION.to_be_bytes());
+        out.extend_from_slice(&self.rom_hash.to_be_bytes());
+
+        let mut cpu = Vec::new();
+        for value in [self.i, self.pc, self.sp] {
+            cpu.extend_from_slice(&value.to_be_bytes());
+        }
+        for value in &self.stack {
+            cpu.extend_from_slice(&value.to_be_bytes());
+        }
+        cpu.extend_from_slice(&self.v);
+        cpu.extend_from_slice(&[
+            self.delay_t,
+            self.sound_t,
+            self.vblank_wait as u8,
+            self.exited as u8,
+        ]);
+        // FX0A progress: 0 when not waiting, 1 waiting for a press, 2 for a release.
+        cpu.extend_from_slice(&match self.key_wait {
+            None => [0, 0, 0],
+            Some(KeyWait::Press { x }) => [1, x as u8, 0],
+            Some(KeyWait::Release { x, key }) => [2, x as u8, key as u8],
+        });
+        encode_error(&mut cpu, self.error);
+        push_chunk(&mut out, CPU_CHUNK, &cpu);
+
+        push_chunk(&mut out, MEMORY_CHUNK, &self.memory);
+
+        let mut video = vec![self.hires as u8, self.plane, self.draw_flag as u8];
+        video.extend_from_slice(&self.vram);
+        push_chunk(&mut out, VIDEO_CHUNK, &video);
+
+        let mut audio = vec![self.pitch, self.audio_pattern.is_some() as u8];
+        audio.extend_from_slice(self.audio_pattern.as_deref().unwrap_or(&[]));
+        push_chunk(&mut out, AUDIO_CHUNK, &audio);
+
+        push_chunk(&mut out, RPL_CHUNK, &self.rpl);
+
+        let keys: Vec<u8> = self.key_pressed.iter().map(|&key| key as u8).collect();
+        push_chunk(&mut out, KEYS_CHUNK, &keys);
+
+        push_chunk(&mut out, QUIRKS_CHUNK, &[encode_quirks(&self.quirks)]);
+
+        out
+    }
+
+    /// Restores a snapshot taken by `save_state`, from the same ROM.
+    ///
+    /// On error, the machine is left untouched.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut header = Reader {
+            data: state,
+            tag: *b"HEAD",
+        };
+
+        if header
+            .bytes(MAGIC.len())
+            .map_err(|_| StateError::BadMagic)?
+            != MAGIC
+        {
+            return Err(StateError::BadMagic);
+        }
+
+        let version = header.u16()?;
+        let min_reader_version = header.u16()?;
+        if version == 0 || min_reader_version > VERSION {
+            return Err(StateError::UnsupportedVersion { version });
+        }
+
+        if header.u64()? != self.rom_hash {
+            return Err(StateError::RomMismatch);
+        }
+
+        let mut machine = self.clone();
+        let mut chunks = header.data;
+
+        while !chunks.is_empty() {
+            let mut chunk_header = Reader {
+                data: chunks,
+                tag: *b"    ",
+            };
+            let mut tag = [0; 4];
+            tag.copy_from_slice(chunk_header.bytes(4)?);
+            chunk_header.tag = tag;
+
+            let len = chunk_header.u32()? as usize;
+            let payload = chunk_header.bytes(len)?;
+            chunks = chunk_header.data;
+
+            machine.load_chunk(Reader { data: payload, tag })?;
+        }
+
+        migrate(&mut machine, version);
+        *self = machine;
+        self.clear_instruction_cache();
+        Ok(())
+    }
+
+    fn load_chunk(&mut self, mut chunk: Reader) -> Result<(), StateError> {
+        match &chunk.tag {
+            CPU_CHUNK => {
+                self.i = chunk.u16()?;
+                self.pc = chunk.u16()?;
+                self.sp = chunk.u16()?;
+                if self.sp as usize > STACK_SIZE {
+                    return Err(chunk.invalid());
+                }
+
+                for value in self.stack.iter_mut() {
+                    *value = chunk.u16()?;
+                }
+                self.v.copy_from_slice(chunk.bytes(REGISTERS)?);
+
+                self.delay_t = chunk.u8()?;
+                self.sound_t = chunk.u8()?;
+                self.vblank_wait = chunk.bool()?;
+                self.exited = chunk.bool()?;
+
+                let kind = chunk.u8()?;
+                let x = chunk.u8()? as usize;
+                let key = chunk.u8()? as usize;
+                if x >= REGISTERS || key >= self.key_pressed.len() {
+                    return Err(chunk.invalid());
+                }
+                self.key_wait = match kind {
+                    0 => None,
+                    1 => Some(KeyWait::Press { x }),
+                    2 => Some(KeyWait::Release { x, key }),
+                    _ => return Err(chunk.invalid()),
+                };
+
+                self.error = decode_error(&mut chunk)?;
+            }
+            MEMORY_CHUNK => {
+                let len = chunk.data.len();
+                if len != MEMORY_SIZE && len != XO_MEMORY_SIZE {
+                    return Err(chunk.invalid());
+                }
+
+                self.memory = chunk.bytes(len)?.to_vec();
+            }
+            VIDEO_CHUNK => {
+                self.hires = chunk.bool()?;
+                self.plane = chunk.u8()? & 0x3;
+                self.draw_flag = chunk.bool()?;
+
+                let (width, height) = if self.hires {
+                    (HIRES_WIDTH, HIRES_HEIGHT)
+                } else {
+                    (LORES_WIDTH, LORES_HEIGHT)
+                };
+                self.vram = chunk.bytes(width * height)?.to_vec();
+            }
+            AUDIO_CHUNK => {
+                self.pitch = chunk.u8()?;
+                self.audio_pattern = if chunk.bool()? {
+                    Some(chunk.bytes(AUDIO_PATTERN_SIZE)?.to_vec())
+                } else {
+                    None
+                };
+            }
+            RPL_CHUNK => self.rpl.copy_from_slice(chunk.bytes(RPL_FLAGS)?),
+            KEYS_CHUNK => {
+                let len = self.key_pressed.len();
+                for (pressed, &key) in self.key_pressed.iter_mut().zip(chunk.bytes(len)?) {
+                    *pressed = key != 0;
+                }
+            }
+            QUIRKS_CHUNK => self.quirks = decode_quirks(chunk.u8()?),
+            // Written by a newer version, which can still be read without it.
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use super::{Chip8, REGISTERS};
+use crate::instruction::Instruction;
+
+/// The state of the machine right before an instruction is executed, as reported
+/// to a `Tracer`.
+///
+/// It's displayed as one line: the cycle count, the address, the opcode and the
+/// disassembly of the instruction, then V0 to VF, I, SP, DT and ST, e.g.
+///
+/// ```text
+///       12 020A DAB6 DRW VA, VB, 6      V=00 00 00 00 00 00 00 00 00 00 02 0C 3F 0C 00 00 I=02EA SP=0 DT=00 ST=00
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// Number of instructions executed before this one.
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub instruction: Instruction,
+    pub v: [u8; REGISTERS],
+    pub i: u16,
+    pub sp: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>8} {:04X} {:04X} {:<18} V=",
+            self.cycle,
+            self.pc,
+            self.opcode,
+            self.instruction.to_string()
+        )?;
+
+        for (x, value) in self.v.iter().enumerate() {
+            let separator = if x + 1 < REGISTERS { " " } else { "" };
+            write!(f, "{:02X}{}", value, separator)?;
+        }
+
+        write!(
+            f,
+            " I={:04X} SP={:X} DT={:02X} ST={:02X}",
+            self.i, self.sp, self.delay_timer, self.sound_timer
+        )
+    }
+}
+
+/// Receives the instructions executed by a `Chip8`, see `Chip8::set_tracer`.
+pub trait Tracer {
+    fn trace(&mut self, entry: &TraceEntry);
+}
+
+/// Selects the instructions that are traced. Empty lists don't filter anything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// Inclusive address ranges the instructions must be in.
+    pub ranges: Vec<(u16, u16)>,
+    /// Mnemonics the instructions must have (e.g. `DRW`), case-insensitive.
+    pub mnemonics: Vec<String>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, addr: u16, instruction: &Instruction) -> bool {
+        let in_range = self.ranges.is_empty()
+            || self
+                .ranges
+                .iter()
+                .any(|&(start, end)| (start..=end).contains(&addr));
+        if !in_range {
+            return false;
+        }
+
+        if self.mnemonics.is_empty() {
+            return true;
+        }
+
+        let text = instruction.to_string();
+        let mnemonic = text.split(' ').next().unwrap_or_default();
+        self.mnemonics
+            .iter()
+            .any(|wanted| wanted.eq_ignore_ascii_case(mnemonic))
+    }
+}
+
+/// A tracer with its filter. The tracer is shared by the copies of the machine,
+/// e.g. the one `load_state` restores into.
+#[derive(Clone)]
+pub(super) struct Trace {
+    tracer: Arc<Mutex<dyn Tracer + Send>>,
+    filter: TraceFilter,
+}
+
+impl fmt::Debug for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Trace")
+            .field("filter", &self.filter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Chip8 {
+    /// Reports every instruction matching `filter` to `tracer`, right before it's
+    /// executed. Replaces the previous tracer, if any.
+    pub fn set_tracer(&mut self, tracer: impl Tracer + Send + 'static, filter: TraceFilter) {
+        self.trace = Some(Trace {
+            tracer: Arc::new(Mutex::new(tracer)),
+            filter,
+        });
+    }
+
+    /// Stops tracing, dropping the tracer.
+    pub fn clear_tracer(&mut self) {
+        self.trace = None;
+    }
+
+    /// Returns the number of instructions executed since the emulator was created.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Reports the instruction about to be executed to the tracer, if it's selected.
+    pub(super) fn trace(&self, opcode: u16, instruction: &Instruction) {
+        let trace = match &self.trace {
+            Some(trace) if trace.filter.matches(self.pc, instruction) => trace,
+            _ => return,
+        };
+
+        let mut v = [0; REGISTERS];
+        v.copy_from_slice(&self.v);
+
+        let entry = TraceEntry {
+            cycle: self.cycles,
+            pc: self.pc,
+            opcode,
+            instruction: *instruction,
+            v,
+            i: self.i,
+            sp: self.sp,
+            delay_timer: self.delay_t,
+            sound_timer: self.sound_t,
+        };
+
+        trace
+            .tracer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .trace(&entry);
+    }
+}
@@ -0,0 +1,86 @@
+use super::Chip8;
+
+/// The kind of a memory access made by an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// A range of memory whose accesses are reported, see `Chip8::add_watchpoint`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    /// First address of the range.
+    pub addr: u16,
+    /// Number of bytes in the range, at least 1.
+    pub len: u16,
+    /// Whether reads are reported.
+    pub read: bool,
+    /// Whether writes are reported.
+    pub write: bool,
+}
+
+impl Watchpoint {
+    /// Returns whether `access` to `addr` is watched.
+    pub fn matches(&self, addr: u16, access: Access) -> bool {
+        let wanted = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+        };
+
+        wanted && addr.wrapping_sub(self.addr) < self.len.max(1)
+    }
+}
+
+/// An access to watched memory, made by the instruction at `pc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub pc: u16,
+    pub addr: u16,
+    pub access: Access,
+    /// The byte that was read, or written.
+    pub value: u8,
+}
+
+impl Chip8 {
+    /// Starts reporting the memory accesses matching `watchpoint`.
+    ///
+    /// Calls to `read` and `write` are watched, as well as the data accesses made
+    /// by instructions (DXYN, FX33, FX55, FX65 and the XO-CHIP 5XY2, 5XY3 and
+    /// F002), but not instruction fetches. They are collected until `take_accesses`.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Removes a watchpoint added by `add_watchpoint`. Returns `false` if there was none.
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|other| other != watchpoint);
+        self.watchpoints.len() != count
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Returns the watched accesses made since the last call, oldest first.
+    pub fn take_accesses(&mut self) -> Vec<MemoryAccess> {
+        std::mem::take(&mut self.accesses)
+    }
+
+    /// Collects the access to `addr` if it's watched.
+    pub(super) fn record(&mut self, addr: u16, access: Access, value: u8) {
+        if self
+            .watchpoints
+            .iter()
+            .any(|watchpoint| watchpoint.matches(addr, access))
+        {
+            self.accesses.push(MemoryAccess {
+                pc: self.pc,
+                addr,
+                access,
+                value,
+            });
+        }
+    }
+}
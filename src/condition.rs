@@ -0,0 +1,303 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::chip8::Chip8;
+
+/// A boolean expression over the state of the emulator, e.g. `v3 == 0x10 && i > 0x300`,
+/// used to make breakpoints conditional.
+///
+/// Values are the registers `v0` to `vf`, `i`, `pc`, `sp`, `dt` and `st`, numbers
+/// (decimal, `0x` hexadecimal or `0b` binary) and memory bytes, written `[addr]`.
+/// They are combined with `+`, `-`, `&`, `|`, the comparisons `==`, `!=`, `<`, `<=`,
+/// `>` and `>=`, and the logical `&&`, `||` and `!`. Nonzero values are true.
+/// Names are case-insensitive, and parentheses group subexpressions.
+#[derive(Debug, Clone)]
+pub struct Condition {
+    text: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        let mut parser = Parser {
+            chars: text.chars().collect(),
+            pos: 0,
+        };
+
+        let expr = parser.or()?;
+        if let Some(c) = parser.peek() {
+            return Err(format!("unexpected '{}' in condition", c));
+        }
+
+        Ok(Condition {
+            text: text.trim().to_string(),
+            expr,
+        })
+    }
+
+    /// Returns whether the condition holds in the current state of `emulator`.
+    pub fn evaluate(&self, emulator: &Chip8) -> bool {
+        self.expr.eval(emulator) != 0
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Register {
+    V(usize),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BinaryOp {
+    Add,
+    Sub,
+    And,
+    Or,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    LogicalAnd,
+    LogicalOr,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(i64),
+    Register(Register),
+    Memory(Box<Expr>),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, c8: &Chip8) -> i64 {
+        match self {
+            Expr::Number(n) => *n,
+            Expr::Register(register) => match register {
+                Register::V(x) => c8.v(*x) as i64,
+                Register::I => c8.i() as i64,
+                Register::Pc => c8.pc() as i64,
+                Register::Sp => c8.sp() as i64,
+                Register::Dt => c8.delay_timer() as i64,
+                Register::St => c8.sound_timer() as i64,
+            },
+            Expr::Memory(addr) => {
+                let addr = addr.eval(c8);
+                usize::try_from(addr)
+                    .ok()
+                    .and_then(|addr| c8.memory().get(addr))
+                    .map_or(0, |&byte| byte as i64)
+            }
+            Expr::Neg(expr) => expr.eval(c8).wrapping_neg(),
+            Expr::Not(expr) => (expr.eval(c8) == 0) as i64,
+            Expr::Binary(BinaryOp::LogicalAnd, left, right) => {
+                (left.eval(c8) != 0 && right.eval(c8) != 0) as i64
+            }
+            Expr::Binary(BinaryOp::LogicalOr, left, right) => {
+                (left.eval(c8) != 0 || right.eval(c8) != 0) as i64
+            }
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (left.eval(c8), right.eval(c8));
+
+                match op {
+                    BinaryOp::Add => left.wrapping_add(right),
+                    BinaryOp::Sub => left.wrapping_sub(right),
+                    BinaryOp::And => left & right,
+                    BinaryOp::Or => left | right,
+                    BinaryOp::Eq => (left == right) as i64,
+                    BinaryOp::Ne => (left != right) as i64,
+                    BinaryOp::Lt => (left < right) as i64,
+                    BinaryOp::Le => (left <= right) as i64,
+                    BinaryOp::Gt => (left > right) as i64,
+                    BinaryOp::Ge => (left >= right) as i64,
+                    BinaryOp::LogicalAnd | BinaryOp::LogicalOr => unreachable!(),
+                }
+            }
+        }
+    }
+}
+
+/// Recursive descent parser, from the loosest binding operator to the tightest:
+/// `||`, `&&`, comparisons, then `+`, `-`, `&` and `|`.
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&mut self) -> Option<char> {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+
+        self.chars.get(self.pos).copied()
+    }
+
+    /// Consumes `token` if it comes next, but not if it's the start of `longer`.
+    fn eat(&mut self, token: &str, longer: Option<&str>) -> bool {
+        self.peek();
+
+        let rest: String = self.chars[self.pos..].iter().collect();
+        if !rest.starts_with(token) || matches!(longer, Some(longer) if rest.starts_with(longer)) {
+            return false;
+        }
+
+        self.pos += token.chars().count();
+        true
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+
+        while self.eat("||", None) {
+            expr = Expr::Binary(BinaryOp::LogicalOr, Box::new(expr), Box::new(self.and()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.comparison()?;
+
+        while self.eat("&&", None) {
+            let right = self.comparison()?;
+            expr = Expr::Binary(BinaryOp::LogicalAnd, Box::new(expr), Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.sum()?;
+
+        // Two-character operators first, so that `<=` isn't taken for `<`.
+        let operators = [
+            ("==", BinaryOp::Eq),
+            ("!=", BinaryOp::Ne),
+            ("<=", BinaryOp::Le),
+            (">=", BinaryOp::Ge),
+            ("<", BinaryOp::Lt),
+            (">", BinaryOp::Gt),
+        ];
+
+        for (token, op) in operators {
+            if self.eat(token, None) {
+                return Ok(Expr::Binary(op, Box::new(left), Box::new(self.sum()?)));
+            }
+        }
+
+        Ok(left)
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+
+        loop {
+            let op = if self.eat("+", None) {
+                BinaryOp::Add
+            } else if self.eat("-", None) {
+                BinaryOp::Sub
+            } else if self.eat("&", Some("&&")) {
+                BinaryOp::And
+            } else if self.eat("|", Some("||")) {
+                BinaryOp::Or
+            } else {
+                return Ok(expr);
+            };
+
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                Ok(Expr::Neg(Box::new(self.unary()?)))
+            }
+            Some('!') => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            Some('(') => {
+                self.pos += 1;
+                let expr = self.or()?;
+                if !self.eat(")", None) {
+                    return Err("missing ')' in condition".to_string());
+                }
+                Ok(expr)
+            }
+            Some('[') => {
+                self.pos += 1;
+                let expr = self.or()?;
+                if !self.eat("]", None) {
+                    return Err("missing ']' in condition".to_string());
+                }
+                Ok(Expr::Memory(Box::new(expr)))
+            }
+            Some(c) if c.is_ascii_alphanumeric() => {
+                let start = self.pos;
+                while self.pos < self.chars.len() && self.chars[self.pos].is_ascii_alphanumeric() {
+                    self.pos += 1;
+                }
+
+                let word: String = self.chars[start..self.pos].iter().collect();
+                if c.is_ascii_digit() {
+                    parse_number(&word).map(Expr::Number)
+                } else {
+                    parse_register(&word).map(Expr::Register)
+                }
+            }
+            Some(c) => Err(format!("unexpected '{}' in condition", c)),
+            None => Err("missing value in condition".to_string()),
+        }
+    }
+}
+
+fn parse_register(name: &str) -> Result<Register, String> {
+    let lower = name.to_ascii_lowercase();
+
+    let register = match lower.as_str() {
+        "i" => Register::I,
+        "pc" => Register::Pc,
+        "sp" => Register::Sp,
+        "dt" => Register::Dt,
+        "st" => Register::St,
+        _ => lower
+            .strip_prefix('v')
+            .filter(|x| x.len() == 1)
+            .and_then(|x| usize::from_str_radix(x, 16).ok())
+            .map(Register::V)
+            .ok_or_else(|| format!("unknown register '{}'", name))?,
+    };
+
+    Ok(register)
+}
+
+fn parse_number(text: &str) -> Result<i64, String> {
+    let lower = text.to_ascii_lowercase();
+
+    let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2)
+    } else {
+        lower.parse()
+    };
+
+    parsed.map_err(|_| format!("invalid number '{}'", text))
+}
@@ -0,0 +1,920 @@
+//! The `dap` subcommand: a Debug Adapter Protocol server over the emulator core,
+//! for debugging from editors.
+//!
+//! The adapter runs a single thread, whose registers and stack are exposed as
+//! variables. Breakpoints can be set on source lines when a source map is
+//! given, or on instruction addresses. Keys are pressed and released by
+//! evaluating `press KEY` and `release KEY`, e.g. from the debug console.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use rs_chip8::{Access, Chip8, Condition, Debugger, Instruction, KeyEvent, SourceMap, Stop};
+
+/// Instructions executed between checks for new requests, while running.
+const RUN_CHUNK: u64 = 10_000;
+
+/// The largest message body accepted from a client, in bytes.
+const MAX_MESSAGE_SIZE: usize = 4 << 20;
+
+const THREAD_ID: u64 = 1;
+const REGISTERS_REFERENCE: u64 = 1;
+const STACK_REFERENCE: u64 = 2;
+
+const REGISTER_NAMES: [&str; 21] = [
+    "V0", "V1", "V2", "V3", "V4", "V5", "V6", "V7", "V8", "V9", "VA", "VB", "VC", "VD", "VE", "VF",
+    "I", "PC", "SP", "DT", "ST",
+];
+
+/// Serves a client over stdin/stdout, or over `port` of localhost if given,
+/// until it disconnects.
+pub fn run(debugger: Debugger, map: SourceMap, port: Option<u16>) -> anyhow::Result<()> {
+    let (reader, writer): (Box<dyn Read + Send>, Box<dyn Write>) = match port {
+        Some(port) => {
+            let listener = TcpListener::bind(("127.0.0.1", port))?;
+            eprintln!("Waiting for a client on 127.0.0.1:{}", port);
+            let (stream, _) = listener.accept()?;
+            (Box::new(stream.try_clone()?), Box::new(stream))
+        }
+        None => (Box::new(io::stdin()), Box::new(io::stdout())),
+    };
+
+    // Requests are read on their own thread, so that they can be received while running.
+    let (sender, requests) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        while let Ok(Some(message)) = read_message(&mut reader) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    Session::new(debugger, map, writer).serve(requests)
+}
+
+/// Reads a message, made of `Content-Length` header and a JSON body.
+/// Returns `None` at the end of the input.
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    if length > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message of {} bytes is too large", length),
+        ));
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// How execution goes on, while the program is running.
+#[derive(Debug, Clone, Copy)]
+enum Running {
+    /// Until a breakpoint, or a pause request.
+    Continue,
+    /// Until the subroutine called at the start returns to `addr`.
+    StepOver { addr: u16, sp: u16 },
+    /// Until the current subroutine returns, i.e. the stack gets shallower than `sp`.
+    StepOut { sp: u16 },
+}
+
+struct Session {
+    debugger: Debugger,
+    map: SourceMap,
+    writer: Box<dyn Write>,
+    seq: u64,
+    running: Option<Running>,
+    stop_on_entry: bool,
+    /// Addresses and conditions of the breakpoints set on the lines of each source file.
+    source_breakpoints: HashMap<String, Vec<(u16, Option<Condition>)>>,
+    instruction_breakpoints: Vec<(u16, Option<Condition>)>,
+}
+
+/// Parses the optional `condition` of a breakpoint.
+fn parse_condition(breakpoint: &Value) -> Result<Option<Condition>, String> {
+    match breakpoint["condition"].as_str() {
+        Some(text) if !text.trim().is_empty() => Condition::parse(text).map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+    let text = text.trim();
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+impl Session {
+    fn new(debugger: Debugger, map: SourceMap, writer: Box<dyn Write>) -> Session {
+        Session {
+            debugger,
+            map,
+            writer,
+            seq: 0,
+            running: None,
+            stop_on_entry: false,
+            source_breakpoints: HashMap::new(),
+            instruction_breakpoints: Vec::new(),
+        }
+    }
+
+    fn serve(&mut self, requests: Receiver<Value>) -> anyhow::Result<()> {
+        loop {
+            let request = if self.running.is_some() {
+                match requests.try_recv() {
+                    Ok(request) => Some(request),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            } else {
+                match requests.recv() {
+                    Ok(request) => Some(request),
+                    Err(_) => return Ok(()),
+                }
+            };
+
+            match request {
+                Some(request) => {
+                    if !self.handle(&request)? {
+                        return Ok(());
+                    }
+                }
+                None => self.run()?,
+            }
+        }
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+
+        let body = message.to_string();
+        write!(
+            self.writer,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.writer.flush()
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<String>) -> io::Result<()> {
+        self.running = None;
+        self.event(
+            "stopped",
+            json!({
+                "reason": reason,
+                "threadId": THREAD_ID,
+                "allThreadsStopped": true,
+                "text": text,
+            }),
+        )
+    }
+
+    /// Handles a request, returning `false` once the client disconnected.
+    fn handle(&mut self, request: &Value) -> io::Result<bool> {
+        let command = request["command"].as_str().unwrap_or_default().to_string();
+        let args = &request["arguments"];
+
+        let result = match command.as_str() {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsSetVariable": true,
+                "supportsConditionalBreakpoints": true,
+                "supportsInstructionBreakpoints": true,
+            })),
+            "launch" | "attach" => {
+                self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+                Ok(Value::Null)
+            }
+            "configurationDone" => Ok(Value::Null),
+            "setBreakpoints" => Ok(self.set_breakpoints(args)),
+            "setInstructionBreakpoints" => Ok(self.set_instruction_breakpoints(args)),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(json!({
+                "scopes": [
+                    { "name": "Registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                    { "name": "Stack", "variablesReference": STACK_REFERENCE, "expensive": false },
+                ]
+            })),
+            "variables" => Ok(self.variables(args["variablesReference"].as_u64())),
+            "setVariable" => self.set_variable(args),
+            "evaluate" => self.evaluate(args["expression"].as_str().unwrap_or_default()),
+            "continue" => {
+                self.running = Some(Running::Continue);
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" => {
+                self.running = self.step_over();
+                Ok(Value::Null)
+            }
+            "stepIn" => {
+                self.running = None;
+                Ok(Value::Null)
+            }
+            "stepOut" => {
+                let sp = self.debugger.emulator().sp();
+                self.running = Some(Running::StepOut { sp });
+                Ok(Value::Null)
+            }
+            "pause" => Ok(Value::Null),
+            "disconnect" | "terminate" => Ok(Value::Null),
+            _ => Err(format!("unsupported request '{}'", command)),
+        };
+
+        let response = match result {
+            Ok(body) => json!({ "success": true, "body": body }),
+            Err(message) => json!({ "success": false, "message": message }),
+        };
+        let mut response = response;
+        response["type"] = json!("response");
+        response["request_seq"] = request["seq"].clone();
+        response["command"] = json!(command);
+        self.send(response)?;
+
+        // Events that follow a request are sent after its response.
+        match command.as_str() {
+            "initialize" => self.event("initialized", json!({}))?,
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    self.stopped("entry", None)?;
+                } else {
+                    self.running = Some(Running::Continue);
+                }
+            }
+            "stepIn" | "next" if self.running.is_none() => {
+                let stop = self.debugger.step(1);
+                self.report(stop, "step")?;
+            }
+            "pause" => self.stopped("pause", None)?,
+            "disconnect" | "terminate" => {
+                self.event("terminated", json!({}))?;
+                return Ok(false);
+            }
+            _ => {}
+        }
+
+        Ok(true)
+    }
+
+    /// Reports why execution stopped, using `reason` when it simply completed.
+    fn report(&mut self, stop: Stop, reason: &str) -> io::Result<()> {
+        match stop {
+            Stop::Done => self.stopped(reason, None),
+            Stop::Breakpoint(_) => self.stopped("breakpoint", None),
+            Stop::Watchpoint(access) => {
+                let text = match access.access {
+                    Access::Read => {
+                        format!("Read 0x{:02X} from 0x{:03X}", access.value, access.addr)
+                    }
+                    Access::Write => {
+                        format!("Wrote 0x{:02X} to 0x{:03X}", access.value, access.addr)
+                    }
+                };
+                self.stopped("data breakpoint", Some(text))
+            }
+            Stop::Halted(err) => self.stopped("exception", Some(err.to_string())),
+            Stop::WaitingForKey => self.stopped(
+                "pause",
+                Some("Waiting for a key, evaluate 'press KEY' and 'release KEY'".to_string()),
+            ),
+            Stop::Exited => {
+                self.running = None;
+                self.event("exited", json!({ "exitCode": 0 }))?;
+                self.event("terminated", json!({}))
+            }
+        }
+    }
+
+    /// Executes a chunk of instructions while running, reporting when execution stops.
+    fn run(&mut self) -> io::Result<()> {
+        let running = match self.running {
+            Some(running) => running,
+            None => return Ok(()),
+        };
+
+        let stop = match running {
+            Running::Continue => match self.debugger.resume(RUN_CHUNK) {
+                Stop::Done => None,
+                stop => Some(stop),
+            },
+            Running::StepOver { addr, sp } => {
+                self.run_until(|emulator| emulator.pc() == addr && emulator.sp() == sp)
+            }
+            Running::StepOut { sp } => self.run_until(|emulator| emulator.sp() < sp),
+        };
+
+        match stop {
+            Some(stop) => self.report(stop, "step"),
+            None => Ok(()),
+        }
+    }
+
+    /// Steps until `done` holds, stopping early at breakpoints. Returns `None`
+    /// if neither happened within a chunk of instructions.
+    fn run_until(&mut self, done: impl Fn(&Chip8) -> bool) -> Option<Stop> {
+        for _ in 0..RUN_CHUNK {
+            match self.debugger.step(1) {
+                Stop::Done => {}
+                stop => return Some(stop),
+            }
+
+            let pc = self.debugger.emulator().pc();
+            if done(self.debugger.emulator()) {
+                return Some(Stop::Done);
+            }
+            if self.debugger.breaks_at(pc) {
+                return Some(Stop::Breakpoint(pc));
+            }
+        }
+
+        None
+    }
+
+    /// Returns how to step over the current instruction: calls run until the
+    /// subroutine returns, anything else is a single step.
+    fn step_over(&self) -> Option<Running> {
+        let emulator = self.debugger.emulator();
+        let pc = emulator.pc();
+
+        match self.instruction_at(pc) {
+            Some(Instruction::Call(_)) => Some(Running::StepOver {
+                addr: pc.wrapping_add(2),
+                sp: emulator.sp(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn instruction_at(&self, addr: u16) -> Option<Instruction> {
+        let memory = self.debugger.emulator().memory();
+        let high = *memory.get(addr as usize)?;
+        let low = *memory.get(addr.wrapping_add(1) as usize)?;
+        Instruction::decode((high as u16) << 8 | low as u16).ok()
+    }
+
+    /// Replaces the debugger's breakpoints with the ones set by the client.
+    fn sync_breakpoints(&mut self) {
+        let current: Vec<u16> = self.debugger.breakpoints().collect();
+        for addr in current {
+            self.debugger.remove_breakpoint(addr);
+        }
+
+        let requested = self
+            .source_breakpoints
+            .values()
+            .flatten()
+            .chain(&self.instruction_breakpoints);
+        for (addr, condition) in requested {
+            self.debugger.set_breakpoint(*addr, condition.clone());
+        }
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Value {
+        let path = args["source"]["path"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let mut addresses = Vec::new();
+        let mut breakpoints = Vec::new();
+
+        for breakpoint in args["breakpoints"].as_array().into_iter().flatten() {
+            let line = breakpoint["line"].as_u64().unwrap_or_default() as usize;
+            match (
+                self.map.address_of(&path, line),
+                parse_condition(breakpoint),
+            ) {
+                (Some(addr), Ok(condition)) => {
+                    addresses.push((addr, condition));
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "line": line,
+                        "instructionReference": format!("0x{:03X}", addr),
+                    }));
+                }
+                (None, _) => breakpoints.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "No instruction at this line in the source map",
+                })),
+                (_, Err(message)) => breakpoints.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": message,
+                })),
+            }
+        }
+
+        self.source_breakpoints.insert(path, addresses);
+        self.sync_breakpoints();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Value {
+        let mut breakpoints = Vec::new();
+        self.instruction_breakpoints.clear();
+
+        for breakpoint in args["breakpoints"].as_array().into_iter().flatten() {
+            let reference = breakpoint["instructionReference"]
+                .as_str()
+                .unwrap_or_default();
+            let offset = breakpoint["offset"].as_i64().unwrap_or_default();
+
+            match (parse_number(reference), parse_condition(breakpoint)) {
+                (Some(addr), Ok(condition)) => {
+                    let addr = (addr as i64 + offset) as u16;
+                    self.instruction_breakpoints.push((addr, condition));
+                    breakpoints.push(json!({ "verified": true }));
+                }
+                (None, _) => breakpoints.push(json!({ "verified": false })),
+                (_, Err(message)) => {
+                    breakpoints.push(json!({ "verified": false, "message": message }))
+                }
+            }
+        }
+
+        self.sync_breakpoints();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn frame(&self, id: usize, addr: u16) -> Value {
+        let name = match self.instruction_at(addr) {
+            Some(instruction) => format!("0x{:03X}: {}", addr, instruction),
+            None => format!("0x{:03X}", addr),
+        };
+
+        let mut frame = json!({
+            "id": id,
+            "name": name,
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": format!("0x{:03X}", addr),
+        });
+
+        if let Some(source) = self.map.line_at(addr) {
+            frame["line"] = json!(source.line);
+            frame["column"] = json!(1);
+            frame["source"] = json!({ "path": source.file });
+        }
+
+        frame
+    }
+
+    /// The current instruction, then the calls on the stack, innermost first.
+    fn stack_trace(&self) -> Value {
+        let emulator = self.debugger.emulator();
+        let mut frames = vec![self.frame(0, emulator.pc())];
+
+        for (depth, &addr) in emulator.stack().iter().rev().enumerate() {
+            frames.push(self.frame(depth + 1, addr));
+        }
+
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    fn register(&self, name: &str) -> Option<u16> {
+        let emulator = self.debugger.emulator();
+        let index = REGISTER_NAMES
+            .iter()
+            .position(|&register| register == name)?;
+
+        Some(match index {
+            0..=15 => emulator.v(index) as u16,
+            16 => emulator.i(),
+            17 => emulator.pc(),
+            18 => emulator.sp(),
+            19 => emulator.delay_timer() as u16,
+            _ => emulator.sound_timer() as u16,
+        })
+    }
+
+    fn variables(&self, reference: Option<u64>) -> Value {
+        let variables: Vec<Value> = match reference {
+            Some(REGISTERS_REFERENCE) => REGISTER_NAMES
+                .iter()
+                .map(|&name| {
+                    let value = self.register(name).unwrap_or_default();
+                    json!({ "name": name, "value": format!("0x{:02X}", value), "variablesReference": 0 })
+                })
+                .collect(),
+            Some(STACK_REFERENCE) => self
+                .debugger
+                .emulator()
+                .stack()
+                .iter()
+                .enumerate()
+                .map(|(depth, addr)| {
+                    json!({ "name": format!("#{}", depth), "value": format!("0x{:03X}", addr), "variablesReference": 0 })
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        json!({ "variables": variables })
+    }
+
+    fn set_variable(&mut self, args: &Value) -> Result<Value, String> {
+        let name = args["name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_ascii_uppercase();
+        let text = args["value"].as_str().unwrap_or_default();
+        let value = parse_number(text).ok_or_else(|| format!("'{}' is not a number", text))?;
+
+        let byte = || {
+            if value <= 0xFF {
+                Ok(value as u8)
+            } else {
+                Err(format!("{} doesn't fit in a byte", value))
+            }
+        };
+        let word = || {
+            if value <= 0xFFFF {
+                Ok(value as u16)
+            } else {
+                Err(format!("{} doesn't fit in 16 bits", value))
+            }
+        };
+
+        match args["variablesReference"].as_u64() {
+            Some(REGISTERS_REFERENCE) => {
+                let index = REGISTER_NAMES
+                    .iter()
+                    .position(|&register| register == name)
+                    .ok_or_else(|| format!("unknown register '{}'", name))?;
+                let emulator = self.debugger.emulator_mut();
+
+                match index {
+                    0..=15 => emulator.set_v(index, byte()?),
+                    16 => emulator.set_i(word()?),
+                    17 => emulator.set_pc(word()?),
+                    18 if value <= 16 => emulator.set_sp(value as u16),
+                    18 => return Err("the stack pointer is at most 16".to_string()),
+                    19 => emulator.set_delay_timer(byte()?),
+                    _ => emulator.set_sound_timer(byte()?),
+                }
+            }
+            Some(STACK_REFERENCE) => {
+                let depth = name
+                    .strip_prefix('#')
+                    .and_then(|depth| depth.parse::<usize>().ok())
+                    .filter(|&depth| depth < self.debugger.emulator().stack().len())
+                    .ok_or_else(|| format!("unknown stack entry '{}'", name))?;
+                self.debugger.emulator_mut().set_stack(depth, word()?);
+            }
+            _ => return Err("unknown variables".to_string()),
+        }
+
+        Ok(json!({ "value": text }))
+    }
+
+    /// Evaluates a register name, or a `press KEY`/`release KEY` command.
+    fn evaluate(&mut self, expression: &str) -> Result<Value, String> {
+        let mut words = expression.split_whitespace();
+        let result = match (words.next(), words.next()) {
+            (Some(action @ "press"), Some(key)) | (Some(action @ "release"), Some(key)) => {
+                let key = usize::from_str_radix(key, 16)
+                    .ok()
+                    .filter(|&key| key < 16)
+                    .ok_or_else(|| format!("'{}' is not a key (0-F)", key))?;
+
+                let event = if action == "press" {
+                    KeyEvent::Down(key)
+                } else {
+                    KeyEvent::Up(key)
+                };
+                self.debugger.emulator_mut().handle_input(event);
+                String::new()
+            }
+            (Some(name), None) => {
+                let value = self
+                    .register(&name.to_ascii_uppercase())
+                    .ok_or_else(|| format!("unknown register '{}'", name))?;
+                format!("0x{:02X}", value)
+            }
+            _ => return Err(format!("cannot evaluate '{}'", expression)),
+        };
+
+        Ok(json!({ "result": result, "variablesReference": 0 }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// The messages sent by a session, shared with the test.
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Output {
+        /// Takes the messages sent since the last call.
+        fn messages(&self) -> Vec<Value> {
+            let sent = self.0.replace(Vec::new());
+            let mut reader = &sent[..];
+            let mut messages = Vec::new();
+            while let Some(message) = read_message(&mut reader).unwrap() {
+                messages.push(message);
+            }
+            messages
+        }
+    }
+
+    /// A loop incrementing V0 with a call to a subroutine, and a map of its source.
+    fn connect() -> (Session, Output) {
+        // ADD V0, 1; CALL 0x206; JP 0x200; RET
+        let emulator = Chip8::builder()
+            .memory(0x200, &[0x70, 0x01, 0x22, 0x06, 0x12, 0x00, 0x00, 0xEE])
+            .i(0x345)
+            .build();
+        let mut map = SourceMap::new();
+        for (line, addr) in [(1, 0x200), (2, 0x202), (3, 0x204), (5, 0x206)] {
+            map.insert(addr, "game.s", line);
+        }
+
+        let output = Output::default();
+        let session = Session::new(Debugger::new(emulator, 10), map, Box::new(output.clone()));
+        (session, output)
+    }
+
+    /// Handles a request, returning the messages sent in reply.
+    fn request(
+        session: &mut Session,
+        output: &Output,
+        command: &str,
+        arguments: Value,
+    ) -> Vec<Value> {
+        let request =
+            json!({ "seq": 7, "type": "request", "command": command, "arguments": arguments });
+        assert!(
+            session.handle(&request).unwrap(),
+            "{} disconnected",
+            command
+        );
+        output.messages()
+    }
+
+    /// Checks that `messages` start with a successful response to `command`, returning its body.
+    fn body<'a>(messages: &'a [Value], command: &str) -> &'a Value {
+        let response = &messages[0];
+        assert_eq!(response["type"], "response");
+        assert_eq!(response["command"], command);
+        assert_eq!(response["request_seq"], 7);
+        assert_eq!(response["success"], true, "{}", response);
+        &response["body"]
+    }
+
+    fn message_error(input: &str) -> String {
+        let err = read_message(&mut input.as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        err.to_string()
+    }
+
+    #[test]
+    fn framing() {
+        let input = "Content-Length: 13\r\n\r\n{\"seq\": 1}   \
+                     content-length:2\r\nContent-Type: application/json\r\n\r\n[]";
+        let mut reader = input.as_bytes();
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some(json!({ "seq": 1 }))
+        );
+        assert_eq!(read_message(&mut reader).unwrap(), Some(json!([])));
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+
+        assert_eq!(message_error("\r\n{}"), "missing Content-Length header");
+        assert_eq!(
+            message_error("Content-Length: 4194305\r\n\r\n{}"),
+            "message of 4194305 bytes is too large"
+        );
+        message_error("Content-Length: 2\r\n\r\n{]");
+
+        let (mut session, output) = connect();
+        session.send(json!({ "type": "event" })).unwrap();
+        session.send(json!({ "type": "event" })).unwrap();
+        assert_eq!(
+            String::from_utf8(output.0.borrow().clone()).unwrap(),
+            "Content-Length: 24\r\n\r\n{\"seq\":1,\"type\":\"event\"}\
+             Content-Length: 24\r\n\r\n{\"seq\":2,\"type\":\"event\"}"
+        );
+    }
+
+    #[test]
+    fn initialize() {
+        let (mut session, output) = connect();
+
+        let messages = request(&mut session, &output, "initialize", json!({}));
+        assert_eq!(
+            body(&messages, "initialize")["supportsConditionalBreakpoints"],
+            true
+        );
+        assert_eq!(messages[1]["event"], "initialized");
+        assert_eq!(messages.len(), 2);
+
+        let messages = request(&mut session, &output, "frobnicate", json!({}));
+        assert_eq!(messages[0]["success"], false);
+        assert_eq!(messages[0]["message"], "unsupported request 'frobnicate'");
+    }
+
+    #[test]
+    fn set_breakpoints() {
+        let (mut session, output) = connect();
+
+        let arguments = json!({
+            "source": { "path": "/home/me/game.s" },
+            "breakpoints": [
+                { "line": 2 },
+                { "line": 4 },
+                { "line": 5, "condition": "v0 ==" },
+                { "line": 3, "condition": "v0 == 3" },
+            ],
+        });
+        let messages = request(&mut session, &output, "setBreakpoints", arguments);
+        let breakpoints = &body(&messages, "setBreakpoints")["breakpoints"];
+        assert_eq!(breakpoints[0]["verified"], true);
+        assert_eq!(breakpoints[0]["instructionReference"], "0x202");
+        assert_eq!(breakpoints[1]["verified"], false);
+        assert_eq!(breakpoints[2]["verified"], false);
+        assert_eq!(breakpoints[2]["message"], "missing value in condition");
+        assert_eq!(breakpoints[3]["verified"], true);
+
+        let mut addresses: Vec<u16> = session.debugger.breakpoints().collect();
+        addresses.sort_unstable();
+        assert_eq!(addresses, [0x202, 0x204]);
+
+        // Setting the breakpoints of a file replaces its previous ones.
+        let arguments = json!({ "source": { "path": "/home/me/game.s" }, "breakpoints": [] });
+        request(&mut session, &output, "setBreakpoints", arguments);
+        assert_eq!(session.debugger.breakpoints().count(), 0);
+    }
+
+    #[test]
+    fn continue_until_breakpoint() {
+        let (mut session, output) = connect();
+
+        let arguments = json!({
+            "source": { "path": "game.s" },
+            "breakpoints": [{ "line": 5, "condition": "v0 == 3" }],
+        });
+        request(&mut session, &output, "setBreakpoints", arguments);
+
+        let messages = request(&mut session, &output, "continue", json!({}));
+        assert_eq!(body(&messages, "continue")["allThreadsContinued"], true);
+        assert_eq!(messages.len(), 1);
+
+        session.run().unwrap();
+        let messages = output.messages();
+        assert_eq!(messages[0]["event"], "stopped");
+        assert_eq!(messages[0]["body"]["reason"], "breakpoint");
+        assert!(session.running.is_none());
+        assert_eq!(session.debugger.emulator().pc(), 0x206);
+        assert_eq!(session.debugger.emulator().v(0x0), 3);
+    }
+
+    #[test]
+    fn stack_trace() {
+        let (mut session, output) = connect();
+        session.debugger.emulator_mut().set_pc(0x206);
+        session.debugger.emulator_mut().set_stack(0, 0x204);
+        session.debugger.emulator_mut().set_sp(1);
+
+        let messages = request(
+            &mut session,
+            &output,
+            "stackTrace",
+            json!({ "threadId": 1 }),
+        );
+        let trace = body(&messages, "stackTrace");
+        assert_eq!(trace["totalFrames"], 2);
+
+        let frames = &trace["stackFrames"];
+        assert_eq!(frames[0]["name"], "0x206: RET");
+        assert_eq!(frames[0]["line"], 5);
+        assert_eq!(frames[0]["source"]["path"], "game.s");
+        assert_eq!(frames[1]["name"], "0x204: JP 0x200");
+        assert_eq!(frames[1]["instructionPointerReference"], "0x204");
+        assert_eq!(frames[1]["line"], 3);
+    }
+
+    #[test]
+    fn variables() {
+        let (mut session, output) = connect();
+        session.debugger.emulator_mut().set_stack(0, 0x204);
+        session.debugger.emulator_mut().set_sp(1);
+
+        let arguments = json!({ "variablesReference": REGISTERS_REFERENCE });
+        let messages = request(&mut session, &output, "variables", arguments);
+        let registers = &body(&messages, "variables")["variables"];
+        assert_eq!(registers.as_array().unwrap().len(), REGISTER_NAMES.len());
+        assert_eq!(
+            registers[16],
+            json!({ "name": "I", "value": "0x345", "variablesReference": 0 })
+        );
+        assert_eq!(registers[17]["value"], "0x200");
+
+        let arguments = json!({ "variablesReference": STACK_REFERENCE });
+        let messages = request(&mut session, &output, "variables", arguments);
+        assert_eq!(
+            body(&messages, "variables")["variables"],
+            json!([{ "name": "#0", "value": "0x204", "variablesReference": 0 }])
+        );
+
+        let arguments =
+            json!({ "variablesReference": REGISTERS_REFERENCE, "name": "v3", "value": "0x1F" });
+        let messages = request(&mut session, &output, "setVariable", arguments);
+        assert_eq!(body(&messages, "setVariable")["value"], "0x1F");
+        assert_eq!(session.debugger.emulator().v(0x3), 0x1F);
+
+        let arguments =
+            json!({ "variablesReference": REGISTERS_REFERENCE, "name": "v3", "value": "256" });
+        let messages = request(&mut session, &output, "setVariable", arguments);
+        assert_eq!(messages[0]["message"], "256 doesn't fit in a byte");
+    }
+
+    #[test]
+    fn disconnect() {
+        let (mut session, output) = connect();
+
+        let (sender, requests) = mpsc::channel();
+        for command in ["initialize", "disconnect", "threads"] {
+            sender
+                .send(json!({ "seq": 7, "type": "request", "command": command }))
+                .unwrap();
+        }
+        session.serve(requests).unwrap();
+
+        // The requests after disconnecting aren't handled.
+        let messages = output.messages();
+        let sent: Vec<_> = messages
+            .iter()
+            .map(|message| {
+                (
+                    message["seq"].clone(),
+                    message["command"].clone(),
+                    message["event"].clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            sent,
+            [
+                (json!(1), json!("initialize"), Value::Null),
+                (json!(2), Value::Null, json!("initialized")),
+                (json!(3), json!("disconnect"), Value::Null),
+                (json!(4), Value::Null, json!("terminated")),
+            ]
+        );
+    }
+}
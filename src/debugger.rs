@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+
+use crate::chip8::{Chip8, Chip8Error, MemoryAccess};
+use crate::condition::Condition;
+
+/// Why execution stopped, after `Debugger::step` or `Debugger::resume`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// All the requested instructions were executed.
+    Done,
+    /// The next instruction, at this address, has a breakpoint whose condition holds.
+    Breakpoint(u16),
+    /// The last instruction accessed watched memory, see `Chip8::add_watchpoint`.
+    Watchpoint(MemoryAccess),
+    /// An instruction failed, halting the emulator.
+    Halted(Chip8Error),
+    /// The program executed 00FD.
+    Exited,
+    /// An FX0A instruction is waiting for a key, which no step can provide.
+    WaitingForKey,
+}
+
+/// Drives a `Chip8` one instruction at a time, stopping at breakpoints and
+/// after accesses to watched memory.
+///
+/// Timers are ticked every `cycles_per_frame` instructions, so that programs
+/// see the same timings as when running at that speed in real time.
+#[derive(Debug)]
+pub struct Debugger {
+    emulator: Chip8,
+    /// Breakpoints, with their optional condition.
+    breakpoints: BTreeMap<u16, Option<Condition>>,
+    cycles_per_frame: u32,
+    /// Instructions executed since the timers were last ticked.
+    cycles: u32,
+}
+
+impl Debugger {
+    pub fn new(emulator: Chip8, cycles_per_frame: u32) -> Debugger {
+        Debugger {
+            emulator,
+            breakpoints: BTreeMap::new(),
+            cycles_per_frame: cycles_per_frame.max(1),
+            cycles: 0,
+        }
+    }
+
+    pub fn emulator(&self) -> &Chip8 {
+        &self.emulator
+    }
+
+    pub fn emulator_mut(&mut self) -> &mut Chip8 {
+        &mut self.emulator
+    }
+
+    /// Adds a breakpoint at `addr`. Returns `false` if there already was one.
+    pub fn add_breakpoint(&mut self, addr: u16) -> bool {
+        self.set_breakpoint(addr, None)
+    }
+
+    /// Adds a breakpoint at `addr` that only stops execution when `condition` holds,
+    /// or always without a condition. An existing breakpoint at `addr` is replaced,
+    /// and `false` is returned.
+    pub fn set_breakpoint(&mut self, addr: u16, condition: Option<Condition>) -> bool {
+        self.breakpoints.insert(addr, condition).is_none()
+    }
+
+    /// Removes the breakpoint at `addr`. Returns `false` if there was none.
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr).is_some()
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.keys().copied()
+    }
+
+    /// Returns the condition of the breakpoint at `addr`, if it has one.
+    pub fn condition(&self, addr: u16) -> Option<&Condition> {
+        self.breakpoints.get(&addr).and_then(Option::as_ref)
+    }
+
+    /// Returns whether execution should stop at `addr`: it has a breakpoint,
+    /// and its condition holds.
+    pub fn breaks_at(&self, addr: u16) -> bool {
+        match self.breakpoints.get(&addr) {
+            Some(Some(condition)) => condition.evaluate(&self.emulator),
+            Some(None) => true,
+            None => false,
+        }
+    }
+
+    /// Executes a single instruction, ticking the timers at the end of a frame.
+    fn cycle(&mut self) -> Result<(), Chip8Error> {
+        self.emulator.step()?;
+
+        self.cycles += 1;
+        // The rest of the frame would be spent waiting, skip right to its end.
+        if self.emulator.waiting_for_vblank() {
+            self.cycles = self.cycles_per_frame;
+        }
+
+        if self.cycles == self.cycles_per_frame {
+            self.cycles = 0;
+            self.emulator.tick_timers();
+        }
+
+        Ok(())
+    }
+
+    /// Returns the reason execution can't go on, if any.
+    fn blocked(&self) -> Option<Stop> {
+        if let Some(err) = self.emulator.error() {
+            Some(Stop::Halted(err))
+        } else if self.emulator.exited {
+            Some(Stop::Exited)
+        } else if self.emulator.waiting_for_key().is_some() {
+            Some(Stop::WaitingForKey)
+        } else {
+            None
+        }
+    }
+
+    /// Executes up to `count` instructions, stopping early at breakpoints and
+    /// watchpoints. Breakpoint conditions are evaluated before each instruction.
+    pub fn step(&mut self, count: u64) -> Stop {
+        for executed in 0..count {
+            if let Some(stop) = self.blocked() {
+                return stop;
+            }
+
+            let pc = self.emulator.pc();
+            if executed > 0 && self.breaks_at(pc) {
+                return Stop::Breakpoint(pc);
+            }
+
+            // Drop the accesses made between steps, e.g. by the user editing memory.
+            self.emulator.take_accesses();
+            if let Err(err) = self.cycle() {
+                return Stop::Halted(err);
+            }
+
+            if let Some(&access) = self.emulator.take_accesses().first() {
+                return Stop::Watchpoint(access);
+            }
+        }
+
+        Stop::Done
+    }
+
+    /// Executes instructions until a breakpoint or watchpoint is hit, or execution can't go on.
+    /// The breakpoint on the current instruction, if any, is ignored. Gives up
+    /// with `Stop::Done` after `limit` instructions.
+    pub fn resume(&mut self, limit: u64) -> Stop {
+        match self.step(limit) {
+            Stop::Done => {
+                let pc = self.emulator.pc();
+                if self.breaks_at(pc) {
+                    Stop::Breakpoint(pc)
+                } else {
+                    Stop::Done
+                }
+            }
+            stop => stop,
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::instruction::Instruction;
+
+/// Maximum number of data bytes printed on a single `db` line.
+const DATA_BYTES_PER_LINE: usize = 8;
+
+/// Column at which the address and raw bytes comments start.
+const COMMENT_COLUMN: usize = 56;
+
+/// The disassembly of a ROM, separating code from data by following the
+/// control flow from its first instruction.
+///
+/// It's displayed as source that the assembler accepts, with labels for the targets
+/// of jumps, calls and I loads, and the address and raw bytes of every line as a comment.
+#[derive(Debug)]
+pub struct Disassembly {
+    origin: u16,
+    rom: Vec<u8>,
+    /// Instructions found while following the control flow, by address.
+    code: BTreeMap<u16, Instruction>,
+    labels: BTreeSet<u16>,
+}
+
+impl Disassembly {
+    /// Disassembles `rom`, as loaded at address `origin` and starting execution there.
+    pub fn new(rom: &[u8], origin: u16) -> Disassembly {
+        let mut disassembly = Disassembly {
+            origin,
+            rom: rom.to_vec(),
+            code: BTreeMap::new(),
+            labels: BTreeSet::new(),
+        };
+
+        disassembly.trace(origin);
+        disassembly
+    }
+
+    /// Returns the instruction at `addr`, if it was reached while following the control flow.
+    pub fn instruction_at(&self, addr: u16) -> Option<Instruction> {
+        self.code.get(&addr).copied()
+    }
+
+    /// Returns the addresses that have a label, i.e. that are the target of
+    /// a jump, a call or an I load.
+    pub fn labels(&self) -> impl Iterator<Item = u16> + '_ {
+        self.labels.iter().copied()
+    }
+
+    fn contains(&self, addr: u16) -> bool {
+        addr >= self.origin && ((addr - self.origin) as usize) < self.rom.len()
+    }
+
+    fn byte_at(&self, addr: u16) -> u8 {
+        self.rom[(addr - self.origin) as usize]
+    }
+
+    /// Reads the big-endian word at `addr`, if both of its bytes are in the ROM.
+    fn word_at(&self, addr: u16) -> Option<u16> {
+        let next = addr.checked_add(1)?;
+        if self.contains(addr) && self.contains(next) {
+            Some((self.byte_at(addr) as u16) << 8 | self.byte_at(next) as u16)
+        } else {
+            None
+        }
+    }
+
+    fn add_label(&mut self, addr: u16) {
+        if self.contains(addr) {
+            self.labels.insert(addr);
+        }
+    }
+
+    /// Follows every path of execution from `start`, recording the instructions found.
+    fn trace(&mut self, start: u16) {
+        let mut pending = vec![start];
+
+        while let Some(mut addr) = pending.pop() {
+            while !self.code.contains_key(&addr) {
+                let instruction = match self.word_at(addr).map(Instruction::decode) {
+                    Some(Ok(instruction)) => instruction,
+                    _ => break,
+                };
+
+                if instruction == Instruction::LoadILong {
+                    match self.word_at(addr.wrapping_add(2)) {
+                        Some(target) => self.add_label(target),
+                        None => break,
+                    }
+                }
+
+                self.code.insert(addr, instruction);
+                let next = addr.wrapping_add(instruction.size());
+
+                match instruction {
+                    Instruction::Jump(target) => {
+                        self.add_label(target);
+                        pending.push(target);
+                        break;
+                    }
+                    // The offset is only known at runtime, follow the base address as a best effort.
+                    Instruction::JumpOffset(target) => {
+                        self.add_label(target);
+                        pending.push(target);
+                        break;
+                    }
+                    Instruction::Call(target) => {
+                        self.add_label(target);
+                        pending.push(target);
+                    }
+                    Instruction::SetI(target) => self.add_label(target),
+                    Instruction::Return | Instruction::Exit => break,
+                    _ if instruction.is_skip() => {
+                        let skipped = match self.word_at(next) {
+                            Some(0xF000) => 4,
+                            _ => 2,
+                        };
+                        pending.push(next.wrapping_add(skipped));
+                    }
+                    _ => {}
+                }
+
+                addr = next;
+            }
+        }
+    }
+
+    /// Formats `instruction`, referring to its target address by label when there is one.
+    fn format_instruction(&self, addr: u16, instruction: Instruction) -> String {
+        let label = |target: u16| {
+            if self.labels.contains(&target) {
+                label_name(target)
+            } else {
+                format!("0x{:03X}", target)
+            }
+        };
+
+        match instruction {
+            Instruction::Jump(target) => format!("JP {}", label(target)),
+            Instruction::Call(target) => format!("CALL {}", label(target)),
+            Instruction::SetI(target) => format!("LD I, {}", label(target)),
+            Instruction::JumpOffset(target) => format!("JP V0, {}", label(target)),
+            Instruction::LoadILong => {
+                let target = self.word_at(addr.wrapping_add(2)).unwrap_or_default();
+                format!("{} {}", instruction, label(target))
+            }
+            _ => instruction.to_string(),
+        }
+    }
+}
+
+fn label_name(addr: u16) -> String {
+    format!("L{:03X}", addr)
+}
+
+/// Writes `source` followed by a comment with the address and the raw bytes it assembles to.
+fn write_line(f: &mut fmt::Formatter, source: &str, addr: u16, bytes: &[u8]) -> fmt::Result {
+    let raw: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    writeln!(
+        f,
+        "    {:<width$}; {:04X}  {}",
+        source,
+        addr,
+        raw.join(" "),
+        width = COMMENT_COLUMN - 4
+    )
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let end = self.origin as usize + self.rom.len();
+        let mut addr = self.origin as usize;
+        let mut hidden_labels = Vec::new();
+        let mut lines = Vec::new();
+
+        // Instructions can overlap, when the control flow reaches the same bytes with a
+        // different alignment. The first one wins, and labels pointing inside it become constants.
+        while addr < end {
+            let current = addr as u16;
+
+            if let Some(&instruction) = self.code.get(&current) {
+                let size = instruction.size() as usize;
+                for inner in addr + 1..(addr + size).min(end) {
+                    if self.labels.contains(&(inner as u16)) {
+                        hidden_labels.push(inner as u16);
+                    }
+                }
+
+                lines.push((current, Some(instruction), size.min(end - addr)));
+                addr += size;
+                continue;
+            }
+
+            // Group data bytes, up to the next label or instruction.
+            let mut size = 1;
+            while size < DATA_BYTES_PER_LINE && addr + size < end {
+                let next = (addr + size) as u16;
+                if self.labels.contains(&next) || self.code.contains_key(&next) {
+                    break;
+                }
+                size += 1;
+            }
+
+            lines.push((current, None, size));
+            addr += size;
+        }
+
+        for hidden in &hidden_labels {
+            writeln!(f, "{} = 0x{:03X}", label_name(*hidden), hidden)?;
+        }
+        if !hidden_labels.is_empty() {
+            writeln!(f)?;
+        }
+
+        for (addr, instruction, size) in lines {
+            if self.labels.contains(&addr) {
+                writeln!(f, "{}:", label_name(addr))?;
+            }
+
+            let start = (addr - self.origin) as usize;
+            let bytes = &self.rom[start..start + size];
+
+            let source = match instruction {
+                Some(instruction) => self.format_instruction(addr, instruction),
+                None => {
+                    let data: Vec<String> =
+                        bytes.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+                    format!("db {}", data.join(", "))
+                }
+            };
+
+            write_line(f, &source, addr, bytes)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+//! The window frontend, built on ggez (graphics and input) and cpal (sound).
+
+mod sound;
+
+use std::fs;
+
+use ggez::{
+    conf::{WindowMode, WindowSetup},
+    event::{self, EventHandler, KeyCode, KeyMods},
+    graphics::{self, Color, DrawParam, Image},
+    input, timer, Context, ContextBuilder, GameResult,
+};
+
+use rs_chip8::{Chip8, KeyEvent, Rewind, FRAME_RATE};
+use sound::{Beeper, Pattern};
+
+const WIDTH: f32 = 64.0;
+const HEIGHT: f32 = 32.0;
+
+/// Factor of scale for the size of the window in pixels.
+const SCALE: f32 = 12.0;
+
+const MIN_HEIGHT: f32 = HEIGHT * SCALE;
+const MIN_WIDTH: f32 = WIDTH * SCALE;
+
+/// Colours (RGB) for each pixel value. Pixels only use the first two, unless
+/// an XO-CHIP program draws on the second plane.
+const PALETTE: [[u8; 3]; 4] = [
+    [0x00, 0x00, 0x00],
+    [0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55],
+];
+
+/// Number of save state slots, selected with F6/F7.
+const STATE_SLOTS: u32 = 10;
+
+/// Key to hold to go back in time.
+const REWIND_KEY: KeyCode = KeyCode::Back;
+
+const KEYS: [KeyCode; 16] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Q,
+    KeyCode::W,
+    KeyCode::E,
+    KeyCode::R,
+    KeyCode::A,
+    KeyCode::S,
+    KeyCode::D,
+    KeyCode::F,
+    KeyCode::Z,
+    KeyCode::X,
+    KeyCode::C,
+    KeyCode::V,
+];
+
+fn keycode_to_event(key: KeyCode) -> usize {
+    match key {
+        KeyCode::Key1 => 0,
+        KeyCode::Key2 => 1,
+        KeyCode::Key3 => 2,
+        KeyCode::Key4 => 3,
+        KeyCode::Q => 4,
+        KeyCode::W => 5,
+        KeyCode::E => 6,
+        KeyCode::R => 7,
+        KeyCode::A => 8,
+        KeyCode::S => 9,
+        KeyCode::D => 10,
+        KeyCode::F => 11,
+        KeyCode::Z => 12,
+        KeyCode::X => 13,
+        KeyCode::C => 14,
+        KeyCode::V => 15,
+        _ => 0,
+    }
+}
+
+/// Holds the state of the main program, i.e. all the things needed
+/// while the emulator is updating and doing I/O.
+struct EmulatorState {
+    emulator: Chip8,
+    beeper: Option<Beeper>,
+    fb: Vec<u8>,
+    resolution: (usize, usize),
+    cycles_per_frame: u32,
+    waiting_for_key: bool,
+    /// Path of the ROM, which save states are stored next to.
+    rom_path: String,
+    state_slot: u32,
+    rewind: Rewind,
+}
+
+impl EmulatorState {
+    fn new(emulator: Chip8, options: &Options) -> Self {
+        Self {
+            emulator,
+            beeper: Beeper::new().ok(),
+            fb: vec![0; WIDTH as usize * HEIGHT as usize * 4],
+            resolution: (WIDTH as usize, HEIGHT as usize),
+            cycles_per_frame: options.cycles_per_frame,
+            waiting_for_key: false,
+            rom_path: options.rom_path.clone(),
+            state_slot: 0,
+            rewind: Rewind::with_seconds(options.rewind_seconds),
+        }
+    }
+
+    /// Path of the save state file for the current slot, e.g. `games/PONG.ss0`.
+    fn state_path(&self) -> String {
+        format!("{}.ss{}", self.rom_path, self.state_slot)
+    }
+
+    fn save_state(&self) {
+        let path = self.state_path();
+        match fs::write(&path, self.emulator.save_state()) {
+            Ok(()) => println!("Saved state to {}", path),
+            Err(err) => eprintln!("Could not save state to {}: {}", path, err),
+        }
+    }
+
+    fn load_state(&mut self) {
+        let path = self.state_path();
+        let result = fs::read(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|state| Ok(self.emulator.load_state(&state)?));
+
+        match result {
+            Ok(()) => {
+                println!("Loaded state from {}", path);
+                self.emulator.draw_flag = true;
+            }
+            Err(err) => eprintln!("Could not load state from {}: {}", path, err),
+        }
+    }
+}
+
+impl EventHandler for EmulatorState {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        for key in KEYS {
+            let input = if input::keyboard::is_key_pressed(ctx, key) {
+                KeyEvent::Down(keycode_to_event(key))
+            } else {
+                KeyEvent::Up(keycode_to_event(key))
+            };
+
+            self.emulator.handle_input(input);
+        }
+
+        if let Some(beeper) = &self.beeper {
+            beeper.set_pattern(
+                self.emulator
+                    .audio_pattern()
+                    .map(|(pattern, rate)| Pattern::new(pattern, rate)),
+            );
+
+            if self.emulator.should_beep() {
+                beeper.play();
+            } else {
+                beeper.pause();
+            }
+        }
+
+        let rewinding = input::keyboard::is_key_pressed(ctx, REWIND_KEY);
+
+        while timer::check_update_time(ctx, FRAME_RATE) {
+            // Go back one frame per frame, i.e. at the speed the game was played.
+            if rewinding {
+                self.rewind.rewind(&mut self.emulator);
+                continue;
+            }
+
+            // Once halted the emulator keeps returning the same error, only report it once.
+            if self.emulator.error().is_some() {
+                continue;
+            }
+
+            if let Err(err) = self.emulator.run_frame(self.cycles_per_frame) {
+                eprintln!("Emulator halted: {}", err);
+            }
+            self.rewind.push(&self.emulator);
+        }
+
+        let waiting = self.emulator.waiting_for_key().is_some();
+        if waiting != self.waiting_for_key {
+            self.waiting_for_key = waiting;
+            graphics::set_window_title(
+                ctx,
+                if waiting {
+                    "Chip8 (waiting for a key)"
+                } else {
+                    "Chip8"
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    fn key_down_event(&mut self, ctx: &mut Context, keycode: KeyCode, _: KeyMods, repeat: bool) {
+        if repeat {
+            return;
+        }
+
+        match keycode {
+            KeyCode::Escape => event::quit(ctx),
+            KeyCode::F5 => self.save_state(),
+            KeyCode::F8 => self.load_state(),
+            KeyCode::F6 => {
+                self.state_slot = (self.state_slot + STATE_SLOTS - 1) % STATE_SLOTS;
+                println!("Selected save state slot {}", self.state_slot);
+            }
+            KeyCode::F7 => {
+                self.state_slot = (self.state_slot + 1) % STATE_SLOTS;
+                println!("Selected save state slot {}", self.state_slot);
+            }
+            _ => {}
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        if !self.emulator.should_draw() {
+            return Ok(());
+        }
+
+        graphics::clear(ctx, Color::WHITE);
+
+        // Follow the emulator when it switches between lores and hires mode,
+        // so that the framebuffer keeps being stretched across the entire window.
+        let resolution = self.emulator.display_size();
+        if resolution != self.resolution {
+            let (width, height) = resolution;
+            self.resolution = resolution;
+            self.fb = vec![0; width * height * 4];
+            graphics::set_screen_coordinates(
+                ctx,
+                graphics::Rect::new(0.0, 0.0, width as f32, height as f32),
+            )?;
+        }
+
+        let framebuffer = self.emulator.get_framebuffer();
+
+        // Convert the internal framebuffer (1 number per pixel, indexing the palette) to
+        // an RGBA framebuffer (4 numbers per pixel).
+        for (i, &pixel) in framebuffer.iter().enumerate() {
+            let [r, g, b] = PALETTE[pixel as usize & 0x3];
+            self.fb[i * 4] = r;
+            self.fb[(i * 4) + 1] = g;
+            self.fb[(i * 4) + 2] = b;
+            // Nothing is ever transparent, so alpha is fixed to maximum (255).
+            self.fb[(i * 4) + 3] = 255;
+        }
+
+        let (width, height) = self.resolution;
+        let img = Image::from_rgba8(ctx, width as u16, height as u16, &self.fb)?;
+        graphics::draw(ctx, &img, DrawParam::default())?;
+
+        graphics::present(ctx)
+    }
+}
+
+/// Settings of the window frontend.
+pub struct Options {
+    /// Instructions executed per frame.
+    pub cycles_per_frame: u32,
+    /// Path of the ROM, which save states are stored next to.
+    pub rom_path: String,
+    /// How far back the rewind key can go.
+    pub rewind_seconds: u32,
+}
+
+/// Opens a window and runs `emulator` in it, until the window is closed.
+pub fn run(emulator: Chip8, options: &Options) -> anyhow::Result<()> {
+    // Make a Context.
+    let (mut ctx, event_loop) = ContextBuilder::new("rs-chip8", "Valerio")
+        .window_setup(WindowSetup {
+            title: "Chip8".to_owned(),
+            ..WindowSetup::default()
+        })
+        .build()?;
+
+    let state = EmulatorState::new(emulator, options);
+
+    graphics::set_mode(
+        &mut ctx,
+        WindowMode {
+            width: MIN_WIDTH,
+            height: MIN_HEIGHT,
+            maximized: false,
+            min_width: MIN_WIDTH,
+            min_height: MIN_HEIGHT,
+            resizable: true,
+            ..WindowMode::default()
+        },
+    )?;
+
+    // Set default filter mode to nearest-neighbor.
+    // This makes low resolution images look crisp as they are upscaled, if left to default
+    // Chip8 graphics would be smoothened out and look "blurry".
+    graphics::set_default_filter(&mut ctx, graphics::FilterMode::Nearest);
+
+    // Set screen coordinates to match the size of a Chip8 framebuffer.
+    // This ensures that the framebuffer is stretched across the entire window.
+    graphics::set_screen_coordinates(&mut ctx, graphics::Rect::new(0.0, 0.0, WIDTH, HEIGHT))?;
+
+    event::run(ctx, event_loop, state);
+}
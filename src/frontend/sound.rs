@@ -1,13 +1,44 @@
+use std::sync::{Arc, Mutex};
+
 use cpal::{
     traits::{DeviceTrait, HostTrait, StreamTrait},
     Stream,
 };
 
-/// A simple abstraction used to play a beeping noise (sine wave).
+/// An XO-CHIP audio pattern: 128 1-bit samples, looped at `rate` samples per second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pattern {
+    bits: [u8; 16],
+    rate: f32,
+}
+
+impl Pattern {
+    /// Creates a pattern from (up to) 16 bytes of samples, most significant bit first.
+    pub fn new(bytes: &[u8], rate: f32) -> Self {
+        let mut bits = [0; 16];
+        let len = bytes.len().min(bits.len());
+        bits[..len].copy_from_slice(&bytes[..len]);
+
+        Pattern { bits, rate }
+    }
+
+    fn sample(&self, position: f32) -> f32 {
+        let bit = position as usize % 128;
+        if self.bits[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+            1.0
+        } else {
+            -1.0
+        }
+    }
+}
+
+/// A simple abstraction used to play a beeping noise (sine wave), or an
+/// XO-CHIP audio pattern when one is set.
 ///
 /// Must manually play or pause the noise.
 pub struct Beeper {
     stream: cpal::Stream,
+    pattern: Arc<Mutex<Option<Pattern>>>,
 }
 
 impl Beeper {
@@ -23,15 +54,26 @@ impl Beeper {
             .expect("no supported config")
             .with_max_sample_rate();
 
+        let pattern = Arc::new(Mutex::new(None));
+        let shared = pattern.clone();
+
         let stream = match config.sample_format() {
-            cpal::SampleFormat::I16 => Self::build_stream::<i16>(&device, &config.into())?,
-            cpal::SampleFormat::U16 => Self::build_stream::<u16>(&device, &config.into())?,
-            cpal::SampleFormat::F32 => Self::build_stream::<f32>(&device, &config.into())?,
+            cpal::SampleFormat::I16 => Self::build_stream::<i16>(&device, &config.into(), shared)?,
+            cpal::SampleFormat::U16 => Self::build_stream::<u16>(&device, &config.into(), shared)?,
+            cpal::SampleFormat::F32 => Self::build_stream::<f32>(&device, &config.into(), shared)?,
         };
 
         stream.pause()?;
 
-        Ok(Beeper { stream })
+        Ok(Beeper { stream, pattern })
+    }
+
+    /// Sets the pattern to play instead of the sine wave, or goes back to the
+    /// sine wave if `pattern` is `None`.
+    pub fn set_pattern(&self, pattern: Option<Pattern>) {
+        if let Ok(mut current) = self.pattern.lock() {
+            *current = pattern;
+        }
     }
 
     /// Starts or resumes playback of an annoying beeping noise.
@@ -47,6 +89,7 @@ impl Beeper {
     fn build_stream<T>(
         device: &cpal::Device,
         config: &cpal::StreamConfig,
+        pattern: Arc<Mutex<Option<Pattern>>>,
     ) -> Result<Stream, anyhow::Error>
     where
         T: cpal::Sample,
@@ -54,9 +97,17 @@ impl Beeper {
         let sample_rate = config.sample_rate.0 as f32;
         let channels = config.channels as usize;
 
-        // Produce a sinusoid of maximum amplitude.
+        // Produce a sinusoid of maximum amplitude, or the current pattern if there is one.
         let mut sample_clock = 0f32;
+        let mut pattern_position = 0f32;
         let mut next_value = move || {
+            let current = pattern.lock().ok().and_then(|pattern| *pattern);
+
+            if let Some(pattern) = current {
+                pattern_position = (pattern_position + pattern.rate / sample_rate) % 128.0;
+                return pattern.sample(pattern_position);
+            }
+
             sample_clock = (sample_clock + 1.0) % sample_rate;
             (sample_clock * 440.0 * 2.0 * std::f32::consts::PI / sample_rate).sin()
         };
@@ -71,7 +122,7 @@ impl Beeper {
             err_fn,
         )?;
 
-        return Ok(stream);
+        Ok(stream)
     }
 
     fn write_data<T>(output: &mut [T], channels: usize, next_sample: &mut dyn FnMut() -> f32)
@@ -0,0 +1,740 @@
+//! The `gdb` subcommand: a GDB remote serial protocol stub over the emulator core.
+//!
+//! Registers are numbered V0-VF (0-15, 8 bits), I (16, 16 bits), PC (17, 16 bits),
+//! SP (18, 8 bits), DT (19, 8 bits) and ST (20, 8 bits), and sent big-endian like
+//! CHIP-8 memory. The layout is also described by the `target.xml` target description.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use rs_chip8::{Access, Chip8Error, Debugger, KeyEvent, Stop, Watchpoint};
+
+/// Instructions executed between checks for an interrupt from the debugger, while continuing.
+const CONTINUE_CHUNK: u64 = 10_000;
+
+/// Maximum size of the packets we accept, advertised to the debugger.
+const PACKET_SIZE: usize = 0x4000;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.rs-chip8.cpu">
+    <reg name="v0" bitsize="8" regnum="0"/>
+    <reg name="v1" bitsize="8"/>
+    <reg name="v2" bitsize="8"/>
+    <reg name="v3" bitsize="8"/>
+    <reg name="v4" bitsize="8"/>
+    <reg name="v5" bitsize="8"/>
+    <reg name="v6" bitsize="8"/>
+    <reg name="v7" bitsize="8"/>
+    <reg name="v8" bitsize="8"/>
+    <reg name="v9" bitsize="8"/>
+    <reg name="va" bitsize="8"/>
+    <reg name="vb" bitsize="8"/>
+    <reg name="vc" bitsize="8"/>
+    <reg name="vd" bitsize="8"/>
+    <reg name="ve" bitsize="8"/>
+    <reg name="vf" bitsize="8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8"/>
+    <reg name="dt" bitsize="8"/>
+    <reg name="st" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+const REGISTER_COUNT: usize = 21;
+
+/// Signals reported in stop replies.
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// Waits for a debugger to connect on `port` of localhost, then serves it until it detaches.
+pub fn run(debugger: Debugger, port: u16) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("Waiting for a debugger on 127.0.0.1:{}", port);
+
+    let (stream, addr) = listener.accept()?;
+    println!("Debugger connected from {}", addr);
+    stream.set_nodelay(true)?;
+
+    let mut session = Session {
+        debugger,
+        stream,
+        pending: Vec::new(),
+    };
+    session.serve()?;
+
+    println!("Debugger disconnected");
+    Ok(())
+}
+
+struct Session {
+    debugger: Debugger,
+    stream: TcpStream,
+    /// Bytes received but not consumed yet.
+    pending: Vec<u8>,
+}
+
+/// What to do after handling a packet.
+enum Reply {
+    Packet(String),
+    /// Close the connection, after sending the packet (if any).
+    Close(Option<String>),
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    text.as_bytes()
+        .chunks(2)
+        .map(|digits| match digits {
+            [high, low] => u8::from_str_radix(std::str::from_utf8(&[*high, *low]).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Parses `addr,len`, as used by memory and breakpoint packets.
+fn parse_range(text: &str) -> Option<(u16, usize)> {
+    let (addr, len) = text.split_once(',')?;
+    Some((parse_hex(addr)? as u16, parse_hex(len)? as usize))
+}
+
+impl Session {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if !self.pending.is_empty() {
+            return Ok(Some(self.pending.remove(0)));
+        }
+
+        let mut byte = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    /// Reads the next packet, acknowledging it. Returns `None` when the connection is closed.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // Skip acknowledgements, and interrupts sent while we weren't running.
+            match self.read_byte()? {
+                Some(b'$') => {}
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+
+            let mut data = Vec::new();
+            let mut checksum: u8 = 0;
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => {
+                        checksum = checksum.wrapping_add(byte);
+                        data.push(byte);
+                    }
+                    None => return Ok(None),
+                }
+            }
+
+            let mut expected = [0; 2];
+            for digit in expected.iter_mut() {
+                *digit = match self.read_byte()? {
+                    Some(byte) => byte,
+                    None => return Ok(None),
+                };
+            }
+
+            let expected = std::str::from_utf8(&expected).ok().and_then(parse_hex);
+            if expected != Some(checksum as u32) {
+                self.stream.write_all(b"-")?;
+                continue;
+            }
+            self.stream.write_all(b"+")?;
+
+            // Unescape binary data: `}` is followed by the escaped byte xor 0x20.
+            let mut unescaped = Vec::with_capacity(data.len());
+            let mut bytes = data.into_iter();
+            while let Some(byte) = bytes.next() {
+                match byte {
+                    b'}' => unescaped.push(bytes.next().unwrap_or_default() ^ 0x20),
+                    _ => unescaped.push(byte),
+                }
+            }
+
+            return Ok(Some(String::from_utf8_lossy(&unescaped).into_owned()));
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", data, checksum)?;
+        self.stream.flush()
+    }
+
+    /// Checks, without blocking, whether the debugger sent an interrupt (Ctrl-C).
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut buffer = [0; 64];
+        let result = self.stream.read(&mut buffer);
+        self.stream.set_nonblocking(false)?;
+
+        match result {
+            Ok(len) => {
+                let received = &buffer[..len];
+                let interrupted = received.contains(&0x03);
+                self.pending
+                    .extend(received.iter().filter(|&&byte| byte != 0x03));
+                // A closed connection also stops execution, the next read will notice it.
+                Ok(interrupted || len == 0)
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn serve(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            match self.handle(&packet)? {
+                Reply::Packet(reply) => self.send_packet(&reply)?,
+                Reply::Close(reply) => {
+                    if let Some(reply) = reply {
+                        self.send_packet(&reply)?;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &str) -> io::Result<Reply> {
+        let error = || "E01".to_string();
+        let (command, args) = packet.split_at(packet.len().min(1));
+
+        let reply = match command {
+            "?" => self.stop_reply(Stop::Done),
+            "g" => hex(&self.registers()),
+            "G" => match parse_hex_bytes(args) {
+                Some(bytes) if bytes.len() == self.registers().len() => {
+                    self.set_registers(&bytes);
+                    "OK".to_string()
+                }
+                _ => error(),
+            },
+            "p" => match parse_hex(args).and_then(|n| self.register(n as usize)) {
+                Some(bytes) => hex(&bytes),
+                None => error(),
+            },
+            "P" => {
+                let register = args
+                    .split_once('=')
+                    .and_then(|(n, value)| Some((parse_hex(n)? as usize, parse_hex_bytes(value)?)));
+                match register {
+                    Some((n, value)) if self.set_register(n, &value) => "OK".to_string(),
+                    _ => error(),
+                }
+            }
+            "m" => match parse_range(args) {
+                Some((addr, len)) => self.read_memory(addr, len).unwrap_or_else(error),
+                None => error(),
+            },
+            "M" => {
+                let write = args
+                    .split_once(':')
+                    .and_then(|(range, data)| Some((parse_range(range)?, parse_hex_bytes(data)?)));
+                match write {
+                    Some(((addr, len), data)) if data.len() == len => {
+                        self.write_memory(addr, &data).unwrap_or_else(error)
+                    }
+                    _ => error(),
+                }
+            }
+            "Z" | "z" => self.breakpoint(command == "Z", args).unwrap_or_else(error),
+            "s" => {
+                let stop = self.debugger.step(1);
+                self.stop_reply(stop)
+            }
+            "c" => {
+                let stop = self.resume()?;
+                self.stop_reply(stop)
+            }
+            "H" => "OK".to_string(),
+            "k" => return Ok(Reply::Close(None)),
+            "D" => return Ok(Reply::Close(Some("OK".to_string()))),
+            "q" => self.query(packet),
+            // Unsupported packets get an empty reply.
+            _ => String::new(),
+        };
+
+        Ok(Reply::Packet(reply))
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            format!(
+                "PacketSize={:x};qXfer:features:read+;swbreak+;hwbreak+",
+                PACKET_SIZE
+            )
+        } else if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            match parse_range(range) {
+                Some((offset, len)) => {
+                    let offset = (offset as usize).min(TARGET_XML.len());
+                    let end = (offset + len).min(TARGET_XML.len());
+                    let marker = if end == TARGET_XML.len() { 'l' } else { 'm' };
+                    format!("{}{}", marker, &TARGET_XML[offset..end])
+                }
+                None => "E01".to_string(),
+            }
+        } else if packet == "qAttached" {
+            "1".to_string()
+        } else if packet == "qC" {
+            "QC1".to_string()
+        } else if let Some(command) = packet.strip_prefix("qRcmd,") {
+            self.monitor(command)
+        } else {
+            String::new()
+        }
+    }
+
+    /// Handles `monitor` commands, which provide the keypad input a debugger can't.
+    fn monitor(&mut self, command: &str) -> String {
+        let command = parse_hex_bytes(command)
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .unwrap_or_default();
+        let mut words = command.split_whitespace();
+
+        let output = match (words.next(), words.next()) {
+            (Some(action @ "press"), Some(key)) | (Some(action @ "release"), Some(key)) => {
+                match usize::from_str_radix(key, 16) {
+                    Ok(key) if key < 16 => {
+                        let event = if action == "press" {
+                            KeyEvent::Down(key)
+                        } else {
+                            KeyEvent::Up(key)
+                        };
+                        self.debugger.emulator_mut().handle_input(event);
+                        String::new()
+                    }
+                    _ => format!("'{}' is not a key (0-F)\n", key),
+                }
+            }
+            _ => "Commands: press KEY, release KEY\n".to_string(),
+        };
+
+        if output.is_empty() {
+            "OK".to_string()
+        } else {
+            // The reply can also be the output to print, as hex text.
+            hex(output.as_bytes())
+        }
+    }
+
+    /// Continues until a breakpoint, an error, a key wait or an interrupt from the debugger.
+    fn resume(&mut self) -> io::Result<Stop> {
+        loop {
+            match self.debugger.resume(CONTINUE_CHUNK) {
+                Stop::Done => {
+                    if self.interrupted()? {
+                        return Ok(Stop::Done);
+                    }
+                }
+                stop => return Ok(stop),
+            }
+        }
+    }
+
+    fn stop_reply(&self, stop: Stop) -> String {
+        match stop {
+            Stop::Exited => "W00".to_string(),
+            Stop::Breakpoint(_) => format!("T{:02x}swbreak:;", SIGTRAP),
+            Stop::Watchpoint(access) => {
+                // Tell apart the kinds of watchpoints as GDB set them with Z2, Z3 and Z4.
+                let watched = |kind| {
+                    self.debugger
+                        .emulator()
+                        .watchpoints()
+                        .iter()
+                        .any(|watchpoint| watchpoint.matches(access.addr, kind))
+                };
+                let kind = if !watched(Access::Read) {
+                    "watch"
+                } else if !watched(Access::Write) {
+                    "rwatch"
+                } else {
+                    "awatch"
+                };
+                format!("T{:02x}{}:{:x};", SIGTRAP, kind, access.addr)
+            }
+            Stop::Halted(Chip8Error::UnknownOpcode { .. }) => format!("S{:02x}", SIGILL),
+            Stop::Halted(_) => format!("S{:02x}", SIGSEGV),
+            Stop::Done | Stop::WaitingForKey => format!("S{:02x}", SIGTRAP),
+        }
+    }
+
+    fn register(&self, n: usize) -> Option<Vec<u8>> {
+        let emulator = self.debugger.emulator();
+
+        let bytes = match n {
+            0..=15 => vec![emulator.v(n)],
+            16 => emulator.i().to_be_bytes().to_vec(),
+            17 => emulator.pc().to_be_bytes().to_vec(),
+            18 => vec![emulator.sp() as u8],
+            19 => vec![emulator.delay_timer()],
+            20 => vec![emulator.sound_timer()],
+            _ => return None,
+        };
+
+        Some(bytes)
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        (0..REGISTER_COUNT)
+            .flat_map(|n| self.register(n).expect("register out of range"))
+            .collect()
+    }
+
+    /// Sets register `n` from its big-endian `value`. Returns `false` if either is invalid.
+    fn set_register(&mut self, n: usize, value: &[u8]) -> bool {
+        let emulator = self.debugger.emulator_mut();
+
+        match (n, value) {
+            (0..=15, &[value]) => emulator.set_v(n, value),
+            (16, &[high, low]) => emulator.set_i(u16::from_be_bytes([high, low])),
+            (17, &[high, low]) => emulator.set_pc(u16::from_be_bytes([high, low])),
+            (18, &[sp]) if sp <= 16 => emulator.set_sp(sp as u16),
+            (19, &[value]) => emulator.set_delay_timer(value),
+            (20, &[value]) => emulator.set_sound_timer(value),
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn set_registers(&mut self, bytes: &[u8]) {
+        let mut offset = 0;
+
+        for n in 0..REGISTER_COUNT {
+            let size = self.register(n).expect("register out of range").len();
+            self.set_register(n, &bytes[offset..offset + size]);
+            offset += size;
+        }
+    }
+
+    /// Reads up to `len` bytes from `addr`, fewer if they wouldn't fit in a packet
+    /// or are more than the whole memory.
+    fn read_memory(&self, addr: u16, len: usize) -> Option<String> {
+        let memory = self.debugger.emulator().memory();
+        let len = len.min(PACKET_SIZE / 2).min(memory.len());
+
+        let bytes: Option<Vec<u8>> = (0..len)
+            .map(|offset| {
+                memory
+                    .get(addr.wrapping_add(offset as u16) as usize)
+                    .copied()
+            })
+            .collect();
+
+        bytes.map(|bytes| hex(&bytes))
+    }
+
+    fn write_memory(&mut self, addr: u16, data: &[u8]) -> Option<String> {
+        let emulator = self.debugger.emulator_mut();
+
+        for (offset, &byte) in data.iter().enumerate() {
+            emulator
+                .write(addr.wrapping_add(offset as u16), byte)
+                .ok()?;
+        }
+
+        Some("OK".to_string())
+    }
+
+    /// Handles `Z`/`z` packets, setting or removing software (0) and hardware (1)
+    /// breakpoints, and write (2), read (3) and access (4) watchpoints.
+    fn breakpoint(&mut self, insert: bool, args: &str) -> Option<String> {
+        let (kind, range) = args.split_once(',')?;
+        let (addr, len) = parse_range(range)?;
+
+        let (read, write) = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.debugger.add_breakpoint(addr);
+                } else {
+                    self.debugger.remove_breakpoint(addr);
+                }
+                return Some("OK".to_string());
+            }
+            "2" => (false, true),
+            "3" => (true, false),
+            "4" => (true, true),
+            _ => return Some(String::new()),
+        };
+
+        let watchpoint = Watchpoint {
+            addr,
+            len: (len as u16).max(1),
+            read,
+            write,
+        };
+        let emulator = self.debugger.emulator_mut();
+        if insert {
+            emulator.add_watchpoint(watchpoint);
+        } else {
+            emulator.remove_watchpoint(&watchpoint);
+        }
+
+        Some("OK".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rs_chip8::{Chip8, Chip8Builder};
+
+    /// A loop incrementing V0, with I and V0 set.
+    fn builder() -> Chip8Builder {
+        Chip8::builder()
+            .memory(0x200, &[0x70, 0x01, 0x12, 0x00]) // ADD V0, 1; JP 0x200
+            .v(0x0, 0x12)
+            .i(0x345)
+    }
+
+    /// Returns a session over `emulator`, and the debugger's end of the connection.
+    fn connect(emulator: Chip8) -> (Session, TcpStream) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        let session = Session {
+            debugger: Debugger::new(emulator, 10),
+            stream,
+            pending: Vec::new(),
+        };
+        (session, client)
+    }
+
+    fn packet(data: &str) -> String {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        format!("${}#{:02x}", data, checksum)
+    }
+
+    /// Reads exactly `len` bytes sent by the session.
+    fn receive(client: &mut TcpStream, len: usize) -> String {
+        let mut received = vec![0; len];
+        client.read_exact(&mut received).unwrap();
+        String::from_utf8(received).unwrap()
+    }
+
+    fn reply(session: &mut Session, packet: &str) -> String {
+        match session.handle(packet).unwrap() {
+            Reply::Packet(reply) => reply,
+            Reply::Close(_) => panic!("{} closed the connection", packet),
+        }
+    }
+
+    #[test]
+    fn framing() {
+        let (mut session, mut client) = connect(builder().build());
+
+        // Acknowledgements and interrupts before a packet are skipped.
+        client.write_all(b"+\x03$g#67").unwrap();
+        assert_eq!(session.read_packet().unwrap().as_deref(), Some("g"));
+        assert_eq!(receive(&mut client, 1), "+");
+
+        // A wrong checksum asks for the packet again.
+        client
+            .write_all(format!("$g#00{}", packet("m200,2")).as_bytes())
+            .unwrap();
+        assert_eq!(session.read_packet().unwrap().as_deref(), Some("m200,2"));
+        assert_eq!(receive(&mut client, 2), "-+");
+
+        // `}` escapes the next byte.
+        client.write_all(packet("M300,1:}\x03").as_bytes()).unwrap();
+        assert_eq!(session.read_packet().unwrap().as_deref(), Some("M300,1:#"));
+        assert_eq!(receive(&mut client, 1), "+");
+
+        session.send_packet("OK").unwrap();
+        assert_eq!(receive(&mut client, 6), "$OK#9a");
+
+        drop(client);
+        assert_eq!(session.read_packet().unwrap(), None);
+    }
+
+    #[test]
+    fn serve_until_detached() {
+        let (mut session, mut client) = connect(builder().build());
+
+        client
+            .write_all(format!("{}{}", packet("p10"), packet("D")).as_bytes())
+            .unwrap();
+        session.serve().unwrap();
+
+        drop(session);
+        let mut received = String::new();
+        client.read_to_string(&mut received).unwrap();
+        assert_eq!(received, format!("+{}+{}", packet("0345"), packet("OK")));
+    }
+
+    #[test]
+    fn registers() {
+        let (mut session, _client) = connect(builder().build());
+
+        // V0-VF, then I, PC, SP, DT and ST.
+        let registers = format!("12{}0345{}", "00".repeat(15), "0200000000");
+        assert_eq!(reply(&mut session, "g"), registers);
+
+        let mut changed = registers.replace("0345", "0abc");
+        changed.replace_range(2..4, "ff");
+        assert_eq!(reply(&mut session, &format!("G{}", changed)), "OK");
+        assert_eq!(session.debugger.emulator().v(0x1), 0xFF);
+        assert_eq!(session.debugger.emulator().i(), 0xABC);
+        assert_eq!(reply(&mut session, "g"), changed);
+        assert_eq!(reply(&mut session, "Gff"), "E01");
+
+        assert_eq!(reply(&mut session, "p11"), "0200");
+        assert_eq!(reply(&mut session, "P11=0202"), "OK");
+        assert_eq!(session.debugger.emulator().pc(), 0x202);
+        assert_eq!(reply(&mut session, "P12=11"), "E01");
+        assert_eq!(reply(&mut session, "p15"), "E01");
+    }
+
+    #[test]
+    fn memory() {
+        let (mut session, _client) = connect(builder().build());
+
+        assert_eq!(reply(&mut session, "m200,4"), "70011200");
+        assert_eq!(reply(&mut session, "M300,2:abcd"), "OK");
+        assert_eq!(
+            session.debugger.emulator().memory()[0x300..0x302],
+            [0xAB, 0xCD]
+        );
+        assert_eq!(reply(&mut session, "m300,2"), "abcd");
+
+        assert_eq!(reply(&mut session, "M300,3:abcd"), "E01");
+        assert_eq!(reply(&mut session, "M300,2:abc"), "E01");
+        assert_eq!(reply(&mut session, "m300"), "E01");
+        assert_eq!(reply(&mut session, "m1000,1"), "E01");
+    }
+
+    #[test]
+    fn memory_reads_are_clamped() {
+        // To the size of the memory.
+        let (mut session, _client) = connect(builder().build());
+        assert_eq!(reply(&mut session, "m0,ffffffff").len(), 2 * 0x1000);
+
+        // To the size of a packet.
+        let (mut session, _client) = connect(builder().xo_chip().build());
+        assert_eq!(reply(&mut session, "m0,ffffffff").len(), PACKET_SIZE);
+    }
+
+    #[test]
+    fn breakpoints() {
+        let (mut session, _client) = connect(builder().build());
+
+        assert_eq!(reply(&mut session, "Z0,202,2"), "OK");
+        assert_eq!(reply(&mut session, "c"), "T05swbreak:;");
+        assert_eq!(session.debugger.emulator().pc(), 0x202);
+        assert_eq!(reply(&mut session, "s"), "S05");
+        assert_eq!(reply(&mut session, "c"), "T05swbreak:;");
+
+        assert_eq!(reply(&mut session, "z0,202,2"), "OK");
+        assert_eq!(session.debugger.breakpoints().count(), 0);
+        assert_eq!(reply(&mut session, "Z9,202,2"), "");
+        assert_eq!(reply(&mut session, "Z0"), "E01");
+    }
+
+    #[test]
+    fn watchpoints() {
+        let (mut session, _client) = connect(builder().build());
+
+        assert_eq!(reply(&mut session, "Z2,300,2"), "OK");
+        assert_eq!(reply(&mut session, "Z3,310,1"), "OK");
+        assert_eq!(reply(&mut session, "Z4,320,0"), "OK");
+        let watchpoints = session.debugger.emulator().watchpoints().to_vec();
+        assert_eq!(
+            watchpoints,
+            [
+                Watchpoint {
+                    addr: 0x300,
+                    len: 2,
+                    read: false,
+                    write: true
+                },
+                Watchpoint {
+                    addr: 0x310,
+                    len: 1,
+                    read: true,
+                    write: false
+                },
+                Watchpoint {
+                    addr: 0x320,
+                    len: 1,
+                    read: true,
+                    write: true
+                },
+            ]
+        );
+
+        // Writes from the debugger itself aren't reported.
+        assert_eq!(reply(&mut session, "M300,1:ff"), "OK");
+        assert_eq!(reply(&mut session, "s"), "S05");
+
+        assert_eq!(reply(&mut session, "z2,300,2"), "OK");
+        assert_eq!(reply(&mut session, "z3,310,1"), "OK");
+        assert_eq!(reply(&mut session, "z4,320,0"), "OK");
+        assert!(session.debugger.emulator().watchpoints().is_empty());
+    }
+
+    #[test]
+    fn target_description() {
+        let (mut session, _client) = connect(builder().build());
+
+        assert!(reply(&mut session, "qSupported:swbreak+").contains("qXfer:features:read+"));
+
+        let start = reply(&mut session, "qXfer:features:read:target.xml:0,10");
+        assert_eq!(start, format!("m{}", &TARGET_XML[..0x10]));
+
+        let rest = reply(&mut session, "qXfer:features:read:target.xml:10,1000");
+        assert_eq!(rest, format!("l{}", &TARGET_XML[0x10..]));
+
+        let past_the_end = format!("qXfer:features:read:target.xml:{:x},10", TARGET_XML.len());
+        assert_eq!(reply(&mut session, &past_the_end), "l");
+        assert_eq!(
+            reply(&mut session, "qXfer:features:read:target.xml:"),
+            "E01"
+        );
+    }
+
+    #[test]
+    fn other_packets() {
+        let (mut session, _client) = connect(builder().build());
+
+        assert_eq!(reply(&mut session, "?"), "S05");
+        assert_eq!(reply(&mut session, "qAttached"), "1");
+        assert_eq!(reply(&mut session, "vMustReplyEmpty"), "");
+
+        let monitor = |command: &str| format!("qRcmd,{}", hex(command.as_bytes()));
+        assert_eq!(reply(&mut session, &monitor("press 5")), "OK");
+        assert_eq!(
+            reply(&mut session, &monitor("press 10")),
+            hex(b"'10' is not a key (0-F)\n")
+        );
+
+        assert!(matches!(session.handle("k").unwrap(), Reply::Close(None)));
+        assert!(matches!(
+            session.handle("D").unwrap(),
+            Reply::Close(Some(_))
+        ));
+    }
+}
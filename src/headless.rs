@@ -0,0 +1,327 @@
+//! The `headless` subcommand: runs a ROM without a window or sound, following a
+//! script of key presses, and writes images of the screen at the requested frames.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, bail};
+
+use rs_chip8::{Chip8, KeyEvent};
+
+/// Colours (RGB) for each pixel value, the same as in the window.
+pub const DEFAULT_PALETTE: [[u8; 3]; 4] = [
+    [0x00, 0x00, 0x00],
+    [0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55],
+];
+
+/// Placeholder for the frame number in the path of the images.
+const FRAME_PLACEHOLDER: &str = "{frame}";
+
+pub struct Options {
+    /// Frames to run.
+    pub frames: u32,
+    /// Instructions executed per frame.
+    pub cycles_per_frame: u32,
+    /// Frames after which the screen is captured, 0 being the screen before the first one.
+    pub captures: Vec<u32>,
+    /// Path of the images, a PNG or PBM file depending on the extension.
+    pub output: String,
+    /// Size of the side of a CHIP-8 pixel, in image pixels.
+    pub scale: u32,
+    pub palette: [[u8; 3]; 4],
+    /// Key events, with the frame before which they happen.
+    pub input: Vec<(u32, KeyEvent)>,
+}
+
+/// Parses an input script: one key event per line, written `FRAME press KEY`
+/// or `FRAME release KEY`, where FRAME is the number of frames run before the
+/// event and KEY a hexadecimal key (0-F). Empty lines and lines starting with
+/// `#` are ignored.
+pub fn parse_input(text: &str) -> Result<Vec<(u32, KeyEvent)>, String> {
+    let mut events = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let event = match words.as_slice() {
+            [frame, action, key] => {
+                let frame = frame.parse().ok();
+                let key = usize::from_str_radix(key, 16).ok().filter(|&key| key < 16);
+                match (frame, *action, key) {
+                    (Some(frame), "press", Some(key)) => Some((frame, KeyEvent::Down(key))),
+                    (Some(frame), "release", Some(key)) => Some((frame, KeyEvent::Up(key))),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        match event {
+            Some(event) => events.push(event),
+            None => {
+                return Err(format!(
+                    "line {}: expected 'FRAME press KEY' or 'FRAME release KEY'",
+                    index + 1
+                ))
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+/// Parses a palette of 2 or 4 comma-separated RRGGBB colours. With 2 colours,
+/// the XO-CHIP ones keep their default.
+pub fn parse_palette(text: &str) -> Result<[[u8; 3]; 4], String> {
+    let colours: Vec<&str> = text.split(',').map(str::trim).collect();
+    if colours.len() != 2 && colours.len() != 4 {
+        return Err(format!("'{}' is not a list of 2 or 4 colours", text));
+    }
+
+    let mut palette = DEFAULT_PALETTE;
+    for (entry, colour) in palette.iter_mut().zip(colours) {
+        let rgb = colour.trim_start_matches('#');
+        let value = Some(rgb)
+            .filter(|rgb| rgb.len() == 6)
+            .and_then(|rgb| u32::from_str_radix(rgb, 16).ok())
+            .ok_or_else(|| format!("'{}' is not a RRGGBB colour", colour))?;
+
+        *entry = [(value >> 16) as u8, (value >> 8) as u8, value as u8];
+    }
+
+    Ok(palette)
+}
+
+/// Runs the emulator for `options.frames` frames, writing the captures.
+pub fn run(mut emulator: Chip8, options: &Options) -> anyhow::Result<()> {
+    if options.captures.len() > 1 && !options.output.contains(FRAME_PLACEHOLDER) {
+        bail!(
+            "the path of the images needs a {} placeholder to capture several frames",
+            FRAME_PLACEHOLDER
+        );
+    }
+
+    if options.scale == 0 {
+        bail!("the scale must be at least 1");
+    }
+
+    if let Some(capture) = options
+        .captures
+        .iter()
+        .find(|&&frame| frame > options.frames)
+    {
+        bail!(
+            "can't capture frame {}, only {} frames are run",
+            capture,
+            options.frames
+        );
+    }
+
+    for frame in 0..=options.frames {
+        if options.captures.contains(&frame) {
+            let path = options
+                .output
+                .replace(FRAME_PLACEHOLDER, &frame.to_string());
+            write_image(&emulator, Path::new(&path), options)?;
+        }
+
+        if frame == options.frames {
+            break;
+        }
+
+        for (_, event) in options.input.iter().filter(|(at, _)| *at == frame) {
+            emulator.handle_input(*event);
+        }
+
+        emulator
+            .run_frame(options.cycles_per_frame)
+            .map_err(|err| anyhow!("the emulator halted during frame {}: {}", frame + 1, err))?;
+    }
+
+    Ok(())
+}
+
+fn write_image(emulator: &Chip8, path: &Path, options: &Options) -> anyhow::Result<()> {
+    let (width, height) = emulator.display_size();
+    let scale = options.scale as usize;
+    let (image_width, image_height) = (width * scale, height * scale);
+
+    // One colour per image pixel, in row-major order.
+    let colours: Vec<[u8; 3]> = (0..image_height)
+        .flat_map(|y| {
+            (0..image_width).map(move |x| {
+                let pixel = emulator.get_framebuffer()[(y / scale) * width + x / scale];
+                options.palette[pixel as usize & 0x3]
+            })
+        })
+        .collect();
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    if matches!(path.extension(), Some(extension) if extension.eq_ignore_ascii_case("pbm")) {
+        write_pbm(&mut writer, image_width, image_height, &colours)?;
+    } else {
+        let mut encoder = png::Encoder::new(&mut writer, image_width as u32, image_height as u32);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()?
+            .write_image_data(&colours.concat())?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes a binary PBM bitmap, where dark colours are black and light ones white.
+fn write_pbm(
+    writer: &mut impl Write,
+    width: usize,
+    height: usize,
+    colours: &[[u8; 3]],
+) -> anyhow::Result<()> {
+    write!(writer, "P4\n{} {}\n", width, height)?;
+
+    // Each row is packed into bytes, 8 pixels per byte with 1 for black.
+    for row in colours.chunks(width) {
+        let bytes: Vec<u8> = row
+            .chunks(8)
+            .map(|pixels| {
+                pixels
+                    .iter()
+                    .enumerate()
+                    .fold(0, |byte, (bit, &[r, g, b])| {
+                        let luma = r as u32 * 299 + g as u32 * 587 + b as u32 * 114;
+                        byte | ((luma < 128_000) as u8) << (7 - bit)
+                    })
+            })
+            .collect();
+        writer.write_all(&bytes)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input() {
+        let script = "
+            # Start the game.
+            0 press 5
+            2 release 5
+
+            60 press F
+            60 release a
+        ";
+
+        assert_eq!(
+            parse_input(script),
+            Ok(vec![
+                (0, KeyEvent::Down(0x5)),
+                (2, KeyEvent::Up(0x5)),
+                (60, KeyEvent::Down(0xF)),
+                (60, KeyEvent::Up(0xA)),
+            ])
+        );
+        assert_eq!(parse_input(""), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn malformed_input() {
+        for line in [
+            "press 5",
+            "0 press",
+            "0 press 5 6",
+            "0 push 5",
+            "0 press 10",
+            "0 press G",
+            "-1 press 5",
+            "1.5 press 5",
+        ] {
+            assert_eq!(
+                parse_input(&format!("0 press 1\n{}", line)),
+                Err("line 2: expected 'FRAME press KEY' or 'FRAME release KEY'".to_string()),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn palette() {
+        assert_eq!(
+            parse_palette("102030, #A0b0C0"),
+            Ok([
+                [0x10, 0x20, 0x30],
+                [0xA0, 0xB0, 0xC0],
+                DEFAULT_PALETTE[2],
+                DEFAULT_PALETTE[3],
+            ])
+        );
+        assert_eq!(
+            parse_palette("000000,111111,222222,333333"),
+            Ok([[0; 3], [0x11; 3], [0x22; 3], [0x33; 3]])
+        );
+
+        let cases = [
+            ("000000", "'000000' is not a list of 2 or 4 colours"),
+            ("0,1,2", "'0,1,2' is not a list of 2 or 4 colours"),
+            ("000000,FFF", "'FFF' is not a RRGGBB colour"),
+            ("000000,GGGGGG", "'GGGGGG' is not a RRGGBB colour"),
+            ("000000,", "'' is not a RRGGBB colour"),
+        ];
+        for (text, message) in cases {
+            assert_eq!(parse_palette(text), Err(message.to_string()), "{}", text);
+        }
+    }
+
+    #[test]
+    fn pbm() {
+        let (black, white) = ([0, 0, 0], [0xFF, 0xFF, 0xFF]);
+        // Dark grey is black, light grey is white.
+        let mut colours = vec![
+            white, [0x55; 3], [0xAA; 3], black, white, white, white, white,
+        ];
+        colours.extend([black, white]);
+        colours.extend(vec![white; 10]);
+
+        let mut pbm = Vec::new();
+        write_pbm(&mut pbm, 10, 2, &colours).unwrap();
+
+        // Rows are padded to whole bytes.
+        let mut expected = b"P4\n10 2\n".to_vec();
+        expected.extend([0b0101_0000, 0b1000_0000, 0, 0]);
+        assert_eq!(pbm, expected);
+    }
+
+    #[test]
+    fn captures_after_the_last_frame() {
+        let mut emulator = Chip8::new();
+        emulator.load_rom(&[0x12, 0x00]).unwrap(); // JP 0x200
+        let options = Options {
+            frames: 60,
+            cycles_per_frame: 10,
+            captures: vec![30, 61],
+            output: "unused-{frame}.pbm".to_string(),
+            scale: 1,
+            palette: DEFAULT_PALETTE,
+            input: Vec::new(),
+        };
+
+        let err = run(emulator, &options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "can't capture frame 61, only 60 frames are run"
+        );
+        assert!(!Path::new("unused-30.pbm").exists());
+    }
+}
@@ -0,0 +1,351 @@
+use std::error;
+use std::fmt;
+
+/// A decoded CHIP-8 instruction, including the SUPER-CHIP and XO-CHIP extensions.
+///
+/// Register operands (`x`, `y`) are indices in `0..16`, `nn` is an 8-bit immediate
+/// value, `n` a 4-bit one, and addresses are 12 bits wide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 00CN: scroll the display down by N pixels.
+    ScrollDown(u8),
+    /// 00DN: scroll the display up by N pixels.
+    ScrollUp(u8),
+    /// 00E0: clear the display.
+    ClearScreen,
+    /// 00EE: return from a subroutine.
+    Return,
+    /// 00FB: scroll the display right by 4 pixels.
+    ScrollRight,
+    /// 00FC: scroll the display left by 4 pixels.
+    ScrollLeft,
+    /// 00FD: exit the interpreter.
+    Exit,
+    /// 00FE: switch to the 64x32 lores mode.
+    Lores,
+    /// 00FF: switch to the 128x64 hires mode.
+    Hires,
+    /// 1NNN: jump to NNN.
+    Jump(u16),
+    /// 2NNN: call the subroutine at NNN.
+    Call(u16),
+    /// 3XNN: skip the next instruction if VX == NN.
+    SkipEqImm { x: usize, nn: u8 },
+    /// 4XNN: skip the next instruction if VX != NN.
+    SkipNeImm { x: usize, nn: u8 },
+    /// 5XY0: skip the next instruction if VX == VY.
+    SkipEqReg { x: usize, y: usize },
+    /// 5XY2: store VX to VY in memory starting at I.
+    SaveRange { x: usize, y: usize },
+    /// 5XY3: load VX to VY from memory starting at I.
+    LoadRange { x: usize, y: usize },
+    /// 6XNN: VX = NN.
+    SetImm { x: usize, nn: u8 },
+    /// 7XNN: VX += NN.
+    AddImm { x: usize, nn: u8 },
+    /// 8XY0: VX = VY.
+    Assign { x: usize, y: usize },
+    /// 8XY1: VX |= VY.
+    Or { x: usize, y: usize },
+    /// 8XY2: VX &= VY.
+    And { x: usize, y: usize },
+    /// 8XY3: VX ^= VY.
+    Xor { x: usize, y: usize },
+    /// 8XY4: VX += VY, VF = carry.
+    Add { x: usize, y: usize },
+    /// 8XY5: VX -= VY, VF = not borrow.
+    Sub { x: usize, y: usize },
+    /// 8XY6: VX >>= 1, VF = shifted out bit.
+    ShiftRight { x: usize, y: usize },
+    /// 8XY7: VX = VY - VX, VF = not borrow.
+    SubN { x: usize, y: usize },
+    /// 8XYE: VX <<= 1, VF = shifted out bit.
+    ShiftLeft { x: usize, y: usize },
+    /// 9XY0: skip the next instruction if VX != VY.
+    SkipNeReg { x: usize, y: usize },
+    /// ANNN: I = NNN.
+    SetI(u16),
+    /// BNNN: jump to NNN + V0.
+    JumpOffset(u16),
+    /// CXNN: VX = random byte & NN.
+    Random { x: usize, nn: u8 },
+    /// DXYN: draw the N-rows sprite at I at (VX, VY).
+    Draw { x: usize, y: usize, n: u8 },
+    /// EX9E: skip the next instruction if the key in VX is pressed.
+    SkipKeyPressed { x: usize },
+    /// EXA1: skip the next instruction if the key in VX isn't pressed.
+    SkipKeyNotPressed { x: usize },
+    /// F000 NNNN: I = NNNN, the address being stored in the following 2 bytes.
+    LoadILong,
+    /// FN01: select the drawing planes from the bitmask N.
+    SelectPlane(u8),
+    /// F002: load the 16-byte audio pattern at I.
+    LoadAudio,
+    /// FX07: VX = delay timer.
+    GetDelay { x: usize },
+    /// FX0A: wait for a key press and release, and store the key in VX.
+    WaitKey { x: usize },
+    /// FX15: delay timer = VX.
+    SetDelay { x: usize },
+    /// FX18: sound timer = VX.
+    SetSound { x: usize },
+    /// FX1E: I += VX.
+    AddI { x: usize },
+    /// FX29: I = address of the small font sprite for the digit in VX.
+    Font { x: usize },
+    /// FX30: I = address of the big font sprite for the digit in VX.
+    BigFont { x: usize },
+    /// FX33: store the BCD representation of VX at I, I+1 and I+2.
+    Bcd { x: usize },
+    /// FX3A: set the audio pattern pitch to VX.
+    Pitch { x: usize },
+    /// FX55: store V0 to VX in memory starting at I.
+    Store { x: usize },
+    /// FX65: load V0 to VX from memory starting at I.
+    Load { x: usize },
+    /// FX75: store V0 to VX in the RPL user flags.
+    SaveFlags { x: usize },
+    /// FX85: load V0 to VX from the RPL user flags.
+    LoadFlags { x: usize },
+}
+
+/// Error returned when decoding an opcode that isn't a known instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownOpcode(pub u16);
+
+impl fmt::Display for UnknownOpcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown opcode ${:04x}", self.0)
+    }
+}
+
+impl error::Error for UnknownOpcode {}
+
+impl Instruction {
+    /// Decodes a 16-bit opcode.
+    pub fn decode(opcode: u16) -> Result<Instruction, UnknownOpcode> {
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let y = ((opcode & 0x00F0) >> 4) as usize;
+        let n = (opcode & 0x000F) as u8;
+        let nn = (opcode & 0x00FF) as u8;
+        let addr = opcode & 0x0FFF;
+
+        let instruction = match opcode {
+            0x00C0..=0x00CF => Instruction::ScrollDown(n),
+            0x00D0..=0x00DF => Instruction::ScrollUp(n),
+            0x00E0 => Instruction::ClearScreen,
+            0x00EE => Instruction::Return,
+            0x00FB => Instruction::ScrollRight,
+            0x00FC => Instruction::ScrollLeft,
+            0x00FD => Instruction::Exit,
+            0x00FE => Instruction::Lores,
+            0x00FF => Instruction::Hires,
+            0x1000..=0x1FFF => Instruction::Jump(addr),
+            0x2000..=0x2FFF => Instruction::Call(addr),
+            0x3000..=0x3FFF => Instruction::SkipEqImm { x, nn },
+            0x4000..=0x4FFF => Instruction::SkipNeImm { x, nn },
+            0x5000..=0x5FFF => match n {
+                0x0 => Instruction::SkipEqReg { x, y },
+                0x2 => Instruction::SaveRange { x, y },
+                0x3 => Instruction::LoadRange { x, y },
+                _ => return Err(UnknownOpcode(opcode)),
+            },
+            0x6000..=0x6FFF => Instruction::SetImm { x, nn },
+            0x7000..=0x7FFF => Instruction::AddImm { x, nn },
+            0x8000..=0x8FFF => match n {
+                0x0 => Instruction::Assign { x, y },
+                0x1 => Instruction::Or { x, y },
+                0x2 => Instruction::And { x, y },
+                0x3 => Instruction::Xor { x, y },
+                0x4 => Instruction::Add { x, y },
+                0x5 => Instruction::Sub { x, y },
+                0x6 => Instruction::ShiftRight { x, y },
+                0x7 => Instruction::SubN { x, y },
+                0xE => Instruction::ShiftLeft { x, y },
+                _ => return Err(UnknownOpcode(opcode)),
+            },
+            0x9000..=0x9FFF if n == 0 => Instruction::SkipNeReg { x, y },
+            0xA000..=0xAFFF => Instruction::SetI(addr),
+            0xB000..=0xBFFF => Instruction::JumpOffset(addr),
+            0xC000..=0xCFFF => Instruction::Random { x, nn },
+            0xD000..=0xDFFF => Instruction::Draw { x, y, n },
+            0xE000..=0xEFFF => match nn {
+                0x9E => Instruction::SkipKeyPressed { x },
+                0xA1 => Instruction::SkipKeyNotPressed { x },
+                _ => return Err(UnknownOpcode(opcode)),
+            },
+            0xF000..=0xFFFF => match nn {
+                0x00 if opcode == 0xF000 => Instruction::LoadILong,
+                0x01 => Instruction::SelectPlane(x as u8),
+                0x02 if opcode == 0xF002 => Instruction::LoadAudio,
+                0x07 => Instruction::GetDelay { x },
+                0x0A => Instruction::WaitKey { x },
+                0x15 => Instruction::SetDelay { x },
+                0x18 => Instruction::SetSound { x },
+                0x1E => Instruction::AddI { x },
+                0x29 => Instruction::Font { x },
+                0x30 => Instruction::BigFont { x },
+                0x33 => Instruction::Bcd { x },
+                0x3A => Instruction::Pitch { x },
+                0x55 => Instruction::Store { x },
+                0x65 => Instruction::Load { x },
+                0x75 => Instruction::SaveFlags { x },
+                0x85 => Instruction::LoadFlags { x },
+                _ => return Err(UnknownOpcode(opcode)),
+            },
+            _ => return Err(UnknownOpcode(opcode)),
+        };
+
+        Ok(instruction)
+    }
+
+    /// Encodes the instruction back into its 16-bit opcode.
+    /// Operands are masked to the size of their field.
+    pub fn encode(&self) -> u16 {
+        fn xy(prefix: u16, x: usize, y: usize, suffix: u16) -> u16 {
+            prefix << 12 | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4 | suffix
+        }
+
+        fn xnn(prefix: u16, x: usize, nn: u8) -> u16 {
+            prefix << 12 | (x as u16 & 0xF) << 8 | nn as u16
+        }
+
+        fn fx(x: usize, suffix: u16) -> u16 {
+            xnn(0xF, x, suffix as u8)
+        }
+
+        match *self {
+            Instruction::ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            Instruction::ScrollUp(n) => 0x00D0 | (n as u16 & 0xF),
+            Instruction::ClearScreen => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::Lores => 0x00FE,
+            Instruction::Hires => 0x00FF,
+            Instruction::Jump(addr) => 0x1000 | (addr & 0x0FFF),
+            Instruction::Call(addr) => 0x2000 | (addr & 0x0FFF),
+            Instruction::SkipEqImm { x, nn } => xnn(0x3, x, nn),
+            Instruction::SkipNeImm { x, nn } => xnn(0x4, x, nn),
+            Instruction::SkipEqReg { x, y } => xy(0x5, x, y, 0x0),
+            Instruction::SaveRange { x, y } => xy(0x5, x, y, 0x2),
+            Instruction::LoadRange { x, y } => xy(0x5, x, y, 0x3),
+            Instruction::SetImm { x, nn } => xnn(0x6, x, nn),
+            Instruction::AddImm { x, nn } => xnn(0x7, x, nn),
+            Instruction::Assign { x, y } => xy(0x8, x, y, 0x0),
+            Instruction::Or { x, y } => xy(0x8, x, y, 0x1),
+            Instruction::And { x, y } => xy(0x8, x, y, 0x2),
+            Instruction::Xor { x, y } => xy(0x8, x, y, 0x3),
+            Instruction::Add { x, y } => xy(0x8, x, y, 0x4),
+            Instruction::Sub { x, y } => xy(0x8, x, y, 0x5),
+            Instruction::ShiftRight { x, y } => xy(0x8, x, y, 0x6),
+            Instruction::SubN { x, y } => xy(0x8, x, y, 0x7),
+            Instruction::ShiftLeft { x, y } => xy(0x8, x, y, 0xE),
+            Instruction::SkipNeReg { x, y } => xy(0x9, x, y, 0x0),
+            Instruction::SetI(addr) => 0xA000 | (addr & 0x0FFF),
+            Instruction::JumpOffset(addr) => 0xB000 | (addr & 0x0FFF),
+            Instruction::Random { x, nn } => xnn(0xC, x, nn),
+            Instruction::Draw { x, y, n } => xy(0xD, x, y, n as u16 & 0xF),
+            Instruction::SkipKeyPressed { x } => xnn(0xE, x, 0x9E),
+            Instruction::SkipKeyNotPressed { x } => xnn(0xE, x, 0xA1),
+            Instruction::LoadILong => 0xF000,
+            Instruction::SelectPlane(n) => fx(n as usize, 0x01),
+            Instruction::LoadAudio => 0xF002,
+            Instruction::GetDelay { x } => fx(x, 0x07),
+            Instruction::WaitKey { x } => fx(x, 0x0A),
+            Instruction::SetDelay { x } => fx(x, 0x15),
+            Instruction::SetSound { x } => fx(x, 0x18),
+            Instruction::AddI { x } => fx(x, 0x1E),
+            Instruction::Font { x } => fx(x, 0x29),
+            Instruction::BigFont { x } => fx(x, 0x30),
+            Instruction::Bcd { x } => fx(x, 0x33),
+            Instruction::Pitch { x } => fx(x, 0x3A),
+            Instruction::Store { x } => fx(x, 0x55),
+            Instruction::Load { x } => fx(x, 0x65),
+            Instruction::SaveFlags { x } => fx(x, 0x75),
+            Instruction::LoadFlags { x } => fx(x, 0x85),
+        }
+    }
+
+    /// Returns the size of the instruction in bytes: 4 for F000 NNNN, 2 for everything else.
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LoadILong => 4,
+            _ => 2,
+        }
+    }
+
+    /// Returns whether the instruction may skip the one that follows it.
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Instruction::SkipEqImm { .. }
+                | Instruction::SkipNeImm { .. }
+                | Instruction::SkipEqReg { .. }
+                | Instruction::SkipNeReg { .. }
+                | Instruction::SkipKeyPressed { .. }
+                | Instruction::SkipKeyNotPressed { .. }
+        )
+    }
+}
+
+/// Formats the instruction with the mnemonics from Cowgod's technical reference,
+/// extended with the ones commonly used for SUPER-CHIP and XO-CHIP.
+/// The address of F000 NNNN is not part of the opcode, so it's not printed.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::ScrollDown(n) => write!(f, "SCD {}", n),
+            Instruction::ScrollUp(n) => write!(f, "SCU {}", n),
+            Instruction::ClearScreen => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::Lores => write!(f, "LOW"),
+            Instruction::Hires => write!(f, "HIGH"),
+            Instruction::Jump(addr) => write!(f, "JP 0x{:03X}", addr),
+            Instruction::Call(addr) => write!(f, "CALL 0x{:03X}", addr),
+            Instruction::SkipEqImm { x, nn } => write!(f, "SE V{:X}, 0x{:02X}", x, nn),
+            Instruction::SkipNeImm { x, nn } => write!(f, "SNE V{:X}, 0x{:02X}", x, nn),
+            Instruction::SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::SaveRange { x, y } => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            Instruction::LoadRange { x, y } => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            Instruction::SetImm { x, nn } => write!(f, "LD V{:X}, 0x{:02X}", x, nn),
+            Instruction::AddImm { x, nn } => write!(f, "ADD V{:X}, 0x{:02X}", x, nn),
+            Instruction::Assign { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::Add { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubN { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::SetI(addr) => write!(f, "LD I, 0x{:03X}", addr),
+            Instruction::JumpOffset(addr) => write!(f, "JP V0, 0x{:03X}", addr),
+            Instruction::Random { x, nn } => write!(f, "RND V{:X}, 0x{:02X}", x, nn),
+            Instruction::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKeyPressed { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SkipKeyNotPressed { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::LoadILong => write!(f, "LD I, LONG"),
+            Instruction::SelectPlane(n) => write!(f, "PLANE {}", n),
+            Instruction::LoadAudio => write!(f, "AUDIO"),
+            Instruction::GetDelay { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddI { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::Font { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::BigFont { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::Bcd { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::Pitch { x } => write!(f, "PITCH V{:X}", x),
+            Instruction::Store { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::Load { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::SaveFlags { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
@@ -0,0 +1,26 @@
+//! A CHIP-8 emulator core, with support for the SUPER-CHIP and XO-CHIP extensions.
+//!
+//! The core has no dependencies on graphics, sound or input libraries: frontends
+//! feed key events to a [`Chip8`], call [`Chip8::run_frame`] at [`FRAME_RATE`]
+//! and display its framebuffer.
+
+mod asm;
+mod chip8;
+mod condition;
+mod debugger;
+mod disasm;
+mod instruction;
+mod rewind;
+mod source_map;
+
+pub use crate::asm::{assemble, assemble_file, assemble_file_with_map, AsmError};
+pub use crate::chip8::{
+    Access, Chip8, Chip8Builder, Chip8Error, KeyEvent, MemoryAccess, Quirks, StateError,
+    TraceEntry, TraceFilter, Tracer, Watchpoint, FRAME_RATE, PROGRAM_START,
+};
+pub use crate::condition::Condition;
+pub use crate::debugger::{Debugger, Stop};
+pub use crate::disasm::Disassembly;
+pub use crate::instruction::{Instruction, UnknownOpcode};
+pub use crate::rewind::Rewind;
+pub use crate::source_map::{SourceLine, SourceMap};
@@ -1,189 +1,618 @@
-mod chip8;
-mod sound;
-
-use clap::{App, Arg};
-use ggez::{
-    conf::{WindowMode, WindowSetup},
-    event::{self, EventHandler, KeyCode},
-    graphics::{self, Color, DrawParam, Image},
-    input, timer, Context, ContextBuilder, GameResult,
+mod dap;
+#[cfg(feature = "frontend")]
+mod frontend;
+mod gdb;
+mod headless;
+mod repl;
+mod trace_diff;
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+use rs_chip8::{
+    assemble_file_with_map, Chip8, Debugger, Disassembly, Quirks, SourceMap, TraceEntry,
+    TraceFilter, Tracer, FRAME_RATE, PROGRAM_START,
 };
 
-use chip8::{Chip8, KeyEvent};
-use sound::Beeper;
-
-const WIDTH: f32 = 64.0;
-const HEIGHT: f32 = 32.0;
-
-/// Factor of scale for the size of the window in pixels.
-const SCALE: f32 = 12.0;
-
-const MIN_HEIGHT: f32 = HEIGHT * SCALE;
-const MIN_WIDTH: f32 = WIDTH * SCALE;
-
-const KEYS: [KeyCode; 16] = [
-    KeyCode::Key1,
-    KeyCode::Key2,
-    KeyCode::Key3,
-    KeyCode::Key4,
-    KeyCode::Q,
-    KeyCode::W,
-    KeyCode::E,
-    KeyCode::R,
-    KeyCode::A,
-    KeyCode::S,
-    KeyCode::D,
-    KeyCode::F,
-    KeyCode::Z,
-    KeyCode::X,
-    KeyCode::C,
-    KeyCode::V,
-];
-
-fn keycode_to_event(key: KeyCode) -> usize {
-    match key {
-        KeyCode::Key1 => 0,
-        KeyCode::Key2 => 1,
-        KeyCode::Key3 => 2,
-        KeyCode::Key4 => 3,
-        KeyCode::Q => 4,
-        KeyCode::W => 5,
-        KeyCode::E => 6,
-        KeyCode::R => 7,
-        KeyCode::A => 8,
-        KeyCode::S => 9,
-        KeyCode::D => 10,
-        KeyCode::F => 11,
-        KeyCode::Z => 12,
-        KeyCode::X => 13,
-        KeyCode::C => 14,
-        KeyCode::V => 15,
-        _ => 0,
-    }
-}
+/// Instructions executed per frame when no speed is given, i.e. 600 instructions per second.
+const DEFAULT_CYCLES_PER_FRAME: u32 = 10;
 
-/// Holds the state of the main program, i.e. all the things needed
-/// while the emulator is updating and doing I/O.
-struct EmulatorState {
-    emulator: Chip8,
-    beeper: Option<Beeper>,
-    fb: Vec<u8>,
+fn validate_number(value: String) -> Result<(), String> {
+    value
+        .parse::<u32>()
+        .map(|_| ())
+        .map_err(|_| format!("'{}' is not a number", value))
 }
 
-impl EmulatorState {
-    pub fn new(emulator: Chip8) -> Self {
-        Self {
-            emulator,
-            beeper: Beeper::new().ok(),
-            fb: vec![0; WIDTH as usize * HEIGHT as usize * 4],
-        }
+/// Validates a number that can't be 0, e.g. a speed or a scale.
+fn validate_positive(value: String) -> Result<(), String> {
+    match value.parse::<u32>() {
+        Ok(number) if number > 0 => Ok(()),
+        _ => Err(format!("'{}' is not a positive number", value)),
     }
 }
 
-impl EventHandler for EmulatorState {
-    fn update(&mut self, ctx: &mut Context) -> GameResult {
-        for key in KEYS {
-            let input = if input::keyboard::is_key_pressed(ctx, key) {
-                KeyEvent::Up(keycode_to_event(key))
-            } else {
-                KeyEvent::Down(keycode_to_event(key))
-            };
+/// Validates a speed in instructions per second, which must be a whole number of
+/// instructions per frame.
+fn validate_cpu_hz(value: String) -> Result<(), String> {
+    validate_positive(value.clone())?;
 
-            self.emulator.handle_input(input);
-        }
-
-        if let Some(beeper) = &self.beeper {
-            if self.emulator.should_beep() {
-                // TODO: this should play for longer than a single update.
-                // As it is, it will sometimes run for too short a time to hear it.
-                beeper.play();
-            } else {
-                beeper.pause();
-            }
-        }
-
-        while timer::check_update_time(ctx, 120) {
-            self.emulator.step();
-        }
+    match value.parse::<u32>() {
+        Ok(hz) if hz % FRAME_RATE == 0 => Ok(()),
+        _ => Err(format!(
+            "'{}' is not a multiple of {}, the frame rate",
+            value, FRAME_RATE
+        )),
+    }
+}
 
-        Ok(())
+fn parse_address(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
     }
+}
 
-    fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        if !self.emulator.should_draw() {
-            return Ok(());
-        }
+/// Parses an inclusive address range, written `START-END`.
+fn parse_range(text: &str) -> Option<(u16, u16)> {
+    let (start, end) = text.split_once('-')?;
+    Some((parse_address(start)?, parse_address(end)?)).filter(|(start, end)| start <= end)
+}
 
-        graphics::clear(ctx, Color::WHITE);
+fn validate_range(value: String) -> Result<(), String> {
+    parse_range(&value)
+        .map(|_| ())
+        .ok_or_else(|| format!("'{}' is not an address range like 0x200-0x2FF", value))
+}
 
-        let bw_framebuffer = self.emulator.get_framebuffer();
+/// Writes the trace to a file, one line per instruction.
+struct TraceFile {
+    writer: BufWriter<File>,
+    failed: bool,
+}
 
-        // Convert the internal framebuffer (1 number per pixel, black & white) to
-        // an RGBA framebuffer (4 numbers per pixel).
-        for i in 0..bw_framebuffer.len() {
-            let color = if bw_framebuffer[i] == 0 { 0 } else { 255 };
-            self.fb[(i * 4)] = color;
-            self.fb[(i * 4) + 1] = color;
-            self.fb[(i * 4) + 2] = color;
-            // Nothing is ever transparent, so alpha is fixed to maximum (255).
-            self.fb[(i * 4) + 3] = 255;
+impl Tracer for TraceFile {
+    fn trace(&mut self, entry: &TraceEntry) {
+        if self.failed {
+            return;
         }
 
-        let img = Image::from_rgba8(ctx, WIDTH as u16, HEIGHT as u16, &self.fb)?;
-        graphics::draw(ctx, &img, DrawParam::default())?;
-
-        graphics::present(ctx)
+        if let Err(err) = writeln!(self.writer, "{}", entry) {
+            eprintln!("Stopped tracing, writing the trace failed: {}", err);
+            self.failed = true;
+        }
     }
 }
 
+/// Arguments selecting the ROM and configuring the machine that runs it, see `load_emulator`.
+fn machine_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("file")
+            .value_name("FILE")
+            .help("Sets the input file to use")
+            .required(true)
+            .index(1),
+        Arg::with_name("quirks")
+            .long("quirks")
+            .value_name("PROFILE")
+            .help("Sets the platform whose behaviour ambiguous instructions follow")
+            .possible_values(&["vip", "schip", "xochip"])
+            .default_value("vip"),
+        Arg::with_name("ipf")
+            .long("ipf")
+            .value_name("COUNT")
+            .help("Sets the number of instructions executed per frame (60 frames per second)")
+            .validator(validate_positive)
+            .conflicts_with("cpu-hz"),
+        Arg::with_name("cpu-hz")
+            .long("cpu-hz")
+            .value_name("HZ")
+            .help("Sets the number of instructions executed per second, a multiple of 60")
+            .validator(validate_cpu_hz),
+        Arg::with_name("trace")
+            .long("trace")
+            .value_name("TRACE")
+            .help("Writes a line per executed instruction to the TRACE file"),
+        Arg::with_name("trace-range")
+            .long("trace-range")
+            .value_name("START-END")
+            .help("Only traces the instructions in this address range (can be repeated)")
+            .multiple(true)
+            .number_of_values(1)
+            .validator(validate_range)
+            .requires("trace"),
+        Arg::with_name("trace-op")
+            .long("trace-op")
+            .value_name("MNEMONIC")
+            .help("Only traces the instructions with these mnemonics, e.g. DRW,CALL")
+            .multiple(true)
+            .require_delimiter(true)
+            .requires("trace"),
+        Arg::with_name("decode-cache")
+            .long("decode-cache")
+            .help("Caches decoded instructions, which speeds up long runs"),
+    ]
+}
+
 fn main() -> anyhow::Result<()> {
     let matches = App::new("rs-chip8")
         .about("Chip8 Emulator")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .setting(AppSettings::ArgsNegateSubcommands)
+        .args(&machine_args())
         .arg(
-            Arg::with_name("file")
-                .value_name("FILE")
-                .help("Sets the input file to use")
-                .required(true)
-                .index(1),
+            Arg::with_name("rewind-seconds")
+                .long("rewind-seconds")
+                .value_name("SECONDS")
+                .help("Sets how far back holding Backspace can rewind the game")
+                .validator(validate_number)
+                .default_value("30"),
+        )
+        .subcommand(
+            SubCommand::with_name("disasm")
+                .about("Disassembles a ROM, separating code from data")
+                .arg(
+                    Arg::with_name("file")
+                        .value_name("FILE")
+                        .help("Sets the ROM to disassemble")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("debug")
+                .about("Runs a ROM in an interactive command-line debugger")
+                .args(&machine_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("gdb")
+                .about("Runs a ROM under a GDB remote protocol server on localhost")
+                .args(&machine_args())
+                .arg(
+                    Arg::with_name("port")
+                        .long("port")
+                        .value_name("PORT")
+                        .help("Sets the TCP port to listen on")
+                        .validator(validate_number)
+                        .default_value("1234"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("dap")
+                .about("Runs a ROM under a Debug Adapter Protocol server, for editors")
+                .args(&machine_args())
+                .arg(Arg::with_name("map").long("map").value_name("MAP").help(
+                    "Sets the source map written by `asm --map`, to set breakpoints on lines",
+                ))
+                .arg(
+                    Arg::with_name("port")
+                        .long("port")
+                        .value_name("PORT")
+                        .help("Listens on a TCP port of localhost instead of using stdin/stdout")
+                        .validator(validate_number),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("headless")
+                .about("Runs a ROM without a window, writing images of the screen")
+                .args(&machine_args())
+                .arg(
+                    Arg::with_name("frames")
+                        .long("frames")
+                        .value_name("COUNT")
+                        .help("Sets the number of frames to run (60 per second)")
+                        .validator(validate_number)
+                        .default_value("60"),
+                )
+                .arg(
+                    Arg::with_name("capture")
+                        .long("capture")
+                        .value_name("FRAMES")
+                        .help("Sets the frames after which the screen is captured, e.g. 60,120, up to --frames (the last one by default)")
+                        .multiple(true)
+                        .require_delimiter(true)
+                        .validator(validate_number),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("IMAGE")
+                        .help("Sets the PNG or PBM file to write, with {frame} replaced by the frame number")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("scale")
+                        .long("scale")
+                        .value_name("FACTOR")
+                        .help("Sets the size in pixels of the pixels of the screen")
+                        .validator(validate_positive)
+                        .default_value("1"),
+                )
+                .arg(
+                    Arg::with_name("palette")
+                        .long("palette")
+                        .value_name("COLOURS")
+                        .help("Sets the RRGGBB colours of the pixels, e.g. 000000,FFFFFF (4 for XO-CHIP)"),
+                )
+                .arg(
+                    Arg::with_name("input")
+                        .long("input")
+                        .value_name("SCRIPT")
+                        .help("Sets the script of key events, with lines like '30 press 5'"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("trace-diff")
+                .about("Compares the execution of a ROM with a reference trace, instruction by instruction")
+                .args(&machine_args())
+                .arg(
+                    Arg::with_name("reference")
+                        .value_name("REFERENCE")
+                        .help("Sets the reference trace, as written by --trace or in CSV")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Sets the format of the reference, guessed from its extension by default")
+                        .possible_values(&["text", "csv"]),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("asm")
+                .about("Assembles a source file into a ROM")
+                .arg(
+                    Arg::with_name("file")
+                        .value_name("FILE")
+                        .help("Sets the source file to assemble")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("ROM")
+                        .help("Sets the ROM file to write")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("map").long("map").value_name("MAP").help(
+                        "Writes the source line of every instruction, for the `dap` subcommand",
+                    ),
+                ),
         )
         .get_matches();
 
+    match matches.subcommand() {
+        ("disasm", Some(matches)) => disasm(matches),
+        ("asm", Some(matches)) => asm(matches),
+        ("debug", Some(matches)) => debug(matches),
+        ("gdb", Some(matches)) => gdb(matches),
+        ("dap", Some(matches)) => dap(matches),
+        ("trace-diff", Some(matches)) => trace_diff(matches),
+        ("headless", Some(matches)) => headless(matches),
+        _ => run(&matches),
+    }
+}
+
+/// Creates the emulator described by the `machine_args`, with the ROM loaded.
+/// Returns it with the number of instructions to execute per frame.
+fn load_emulator(matches: &ArgMatches) -> anyhow::Result<(Chip8, u32)> {
     let file_name = matches.value_of("file").expect("no file specified");
+    let profile = matches.value_of("quirks").expect("no quirks profile");
+    let quirks = Quirks::from_name(profile).expect("invalid quirks profile");
+
+    let cycles_per_frame = match (matches.value_of("ipf"), matches.value_of("cpu-hz")) {
+        (Some(ipf), _) => ipf.parse()?,
+        (None, Some(hz)) => hz.parse::<u32>()? / FRAME_RATE,
+        (None, None) => DEFAULT_CYCLES_PER_FRAME,
+    };
 
-    let mut emulator = Chip8::new();
+    let mut emulator = if profile == "xochip" {
+        Chip8::xo_chip(quirks)
+    } else {
+        Chip8::with_quirks(quirks)
+    };
     emulator.load_rom_file(file_name)?;
+    emulator.set_instruction_cache(matches.is_present("decode-cache"));
+
+    if let Some(path) = matches.value_of("trace") {
+        let filter = trace_filter(matches);
+        let file = TraceFile {
+            writer: BufWriter::new(File::create(path)?),
+            failed: false,
+        };
+        emulator.set_tracer(file, filter);
+    }
+
+    Ok((emulator, cycles_per_frame))
+}
+
+/// Returns the filter set by `--trace-range` and `--trace-op`.
+fn trace_filter(matches: &ArgMatches) -> TraceFilter {
+    TraceFilter {
+        ranges: matches
+            .values_of("trace-range")
+            .into_iter()
+            .flatten()
+            .filter_map(parse_range)
+            .collect(),
+        mnemonics: matches
+            .values_of("trace-op")
+            .into_iter()
+            .flatten()
+            .map(str::to_string)
+            .collect(),
+    }
+}
+
+/// Runs a ROM in the window frontend.
+fn run(matches: &ArgMatches) -> anyhow::Result<()> {
+    let file_name = matches.value_of("file").expect("no file specified");
+    let (emulator, cycles_per_frame) = load_emulator(matches)?;
+    let rewind_seconds = matches
+        .value_of("rewind-seconds")
+        .expect("no rewind duration");
+
+    run_window(
+        emulator,
+        cycles_per_frame,
+        file_name,
+        rewind_seconds.parse()?,
+    )
+}
+
+/// Runs a ROM in the command-line debugger.
+fn debug(matches: &ArgMatches) -> anyhow::Result<()> {
+    let (emulator, cycles_per_frame) = load_emulator(matches)?;
+
+    repl::run(Debugger::new(emulator, cycles_per_frame))
+}
+
+/// Runs a ROM under the GDB remote protocol server.
+fn gdb(matches: &ArgMatches) -> anyhow::Result<()> {
+    let (emulator, cycles_per_frame) = load_emulator(matches)?;
+    let port = matches
+        .value_of("port")
+        .expect("no port specified")
+        .parse()?;
+
+    gdb::run(Debugger::new(emulator, cycles_per_frame), port)
+}
 
-    // Make a Context.
-    let (mut ctx, event_loop) = ContextBuilder::new("rs-chip8", "Valerio")
-        .window_setup(WindowSetup {
-            title: "Chip8".to_owned(),
-            ..WindowSetup::default()
-        })
-        .build()?;
-
-    let state = EmulatorState::new(emulator);
-
-    graphics::set_mode(
-        &mut ctx,
-        WindowMode {
-            width: MIN_WIDTH,
-            height: MIN_HEIGHT,
-            maximized: false,
-            min_width: MIN_WIDTH,
-            min_height: MIN_HEIGHT,
-            resizable: true,
-            ..WindowMode::default()
-        },
-    )?;
-
-    // Set default filter mode to nearest-neighbor.
-    // This makes low resolution images look crisp as they are upscaled, if left to default
-    // Chip8 graphics would be smoothened out and look "blurry".
-    graphics::set_default_filter(&mut ctx, graphics::FilterMode::Nearest);
-
-    // Set screen coordinates to match the size of a Chip8 framebuffer.
-    // This ensures that the framebuffer is stretched across the entire window.
-    graphics::set_screen_coordinates(&mut ctx, graphics::Rect::new(0.0, 0.0, WIDTH, HEIGHT))?;
-
-    event::run(ctx, event_loop, state);
+/// Runs a ROM under the Debug Adapter Protocol server.
+fn dap(matches: &ArgMatches) -> anyhow::Result<()> {
+    let (emulator, cycles_per_frame) = load_emulator(matches)?;
+
+    let map = match matches.value_of("map") {
+        Some(map_file) => SourceMap::parse(&fs::read_to_string(map_file)?)
+            .map_err(|err| anyhow::anyhow!("{}: {}", map_file, err))?,
+        None => SourceMap::new(),
+    };
+    let port = match matches.value_of("port") {
+        Some(port) => Some(port.parse()?),
+        None => None,
+    };
+
+    dap::run(Debugger::new(emulator, cycles_per_frame), map, port)
+}
+
+/// Runs a ROM without a window, writing images of the screen.
+fn headless(matches: &ArgMatches) -> anyhow::Result<()> {
+    let (emulator, cycles_per_frame) = load_emulator(matches)?;
+    let frames = matches
+        .value_of("frames")
+        .expect("no frame count")
+        .parse()?;
+
+    let captures = match matches.values_of("capture") {
+        Some(captures) => captures.map(str::parse).collect::<Result<_, _>>()?,
+        None => vec![frames],
+    };
+    let palette = match matches.value_of("palette") {
+        Some(palette) => headless::parse_palette(palette).map_err(anyhow::Error::msg)?,
+        None => headless::DEFAULT_PALETTE,
+    };
+    let input = match matches.value_of("input") {
+        Some(path) => headless::parse_input(&fs::read_to_string(path)?)
+            .map_err(|err| anyhow::anyhow!("{}: {}", path, err))?,
+        None => Vec::new(),
+    };
+
+    let options = headless::Options {
+        frames,
+        cycles_per_frame,
+        captures,
+        output: matches
+            .value_of("output")
+            .expect("no output specified")
+            .to_string(),
+        scale: matches.value_of("scale").expect("no scale").parse()?,
+        palette,
+        input,
+    };
+
+    headless::run(emulator, &options)
+}
+
+/// Runs a ROM, comparing its execution with a reference trace.
+fn trace_diff(matches: &ArgMatches) -> anyhow::Result<()> {
+    let (emulator, cycles_per_frame) = load_emulator(matches)?;
+    let path = matches
+        .value_of("reference")
+        .expect("no reference specified");
+    let text = fs::read_to_string(path)?;
+
+    let csv = match matches.value_of("format") {
+        Some(format) => format == "csv",
+        None => {
+            matches!(Path::new(path).extension(), Some(extension) if extension.eq_ignore_ascii_case("csv"))
+        }
+    };
+    let reference = if csv {
+        trace_diff::parse_csv(&text)
+    } else {
+        trace_diff::parse_text(&text)
+    }
+    .map_err(|err| anyhow::anyhow!("{}: {}", path, err))?;
+
+    trace_diff::run(Debugger::new(emulator, cycles_per_frame), &reference)
+}
+
+/// Prints the disassembly of a ROM.
+fn disasm(matches: &ArgMatches) -> anyhow::Result<()> {
+    let file_name = matches.value_of("file").expect("no file specified");
+    let rom = fs::read(file_name)?;
+
+    let mut out = io::stdout().lock();
+    writeln!(out, "; {}", file_name)?;
+    write!(out, "{}", Disassembly::new(&rom, PROGRAM_START))?;
+
+    Ok(())
+}
+
+/// Assembles a source file into a ROM.
+fn asm(matches: &ArgMatches) -> anyhow::Result<()> {
+    let file_name = matches.value_of("file").expect("no file specified");
+    let output = matches.value_of("output").expect("no output specified");
+
+    let (rom, map) = assemble_file_with_map(Path::new(file_name))?;
+    fs::write(output, rom)?;
+
+    if let Some(map_file) = matches.value_of("map") {
+        fs::write(map_file, map.to_string())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "frontend")]
+fn run_window(
+    emulator: Chip8,
+    cycles_per_frame: u32,
+    rom_path: &str,
+    rewind_seconds: u32,
+) -> anyhow::Result<()> {
+    let options = frontend::Options {
+        cycles_per_frame,
+        rom_path: rom_path.to_owned(),
+        rewind_seconds,
+    };
+
+    frontend::run(emulator, &options)
+}
+
+#[cfg(not(feature = "frontend"))]
+fn run_window(
+    _emulator: Chip8,
+    _cycles_per_frame: u32,
+    _rom_path: &str,
+    _rewind_seconds: u32,
+) -> anyhow::Result<()> {
+    anyhow::bail!("rs-chip8 was built without the `frontend` feature, no window is available")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses the machine arguments of `args`, after a ROM and `--trace`.
+    fn parse_machine_args(args: &[&str]) -> clap::Result<ArgMatches<'static>> {
+        let all = ["rs-chip8", "game.ch8", "--trace", "trace.txt"];
+        App::new("rs-chip8")
+            .args(&machine_args())
+            .get_matches_from_safe(all.iter().chain(args))
+    }
+
+    #[test]
+    fn speeds() {
+        assert_eq!(validate_cpu_hz("720".to_string()), Ok(()));
+        assert_eq!(
+            validate_cpu_hz("700".to_string()),
+            Err("'700' is not a multiple of 60, the frame rate".to_string())
+        );
+        assert_eq!(
+            validate_cpu_hz("0".to_string()),
+            Err("'0' is not a positive number".to_string())
+        );
+
+        assert!(parse_machine_args(&["--cpu-hz", "700"]).is_err());
+        assert!(parse_machine_args(&["--ipf", "0"]).is_err());
+        assert!(parse_machine_args(&["--cpu-hz", "60"]).is_ok());
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range("0x200-0x2FF"), Some((0x200, 0x2FF)));
+        assert_eq!(parse_range("512-0X2ff"), Some((0x200, 0x2FF)));
+        assert_eq!(parse_range("0x200-0x200"), Some((0x200, 0x200)));
+        assert_eq!(parse_range("0x0-0xFFFF"), Some((0, 0xFFFF)));
+
+        for text in [
+            "0x2FF-0x200",
+            "0x200",
+            "0x200-",
+            "-0x200",
+            "0x200-0x2FF-0x300",
+            "0x200-0x10000",
+            "0x200 - 0x2FF",
+            "0xZZ-0x2FF",
+        ] {
+            assert_eq!(parse_range(text), None, "{}", text);
+        }
+
+        assert_eq!(
+            validate_range("0x200".to_string()),
+            Err("'0x200' is not an address range like 0x200-0x2FF".to_string())
+        );
+    }
+
+    #[test]
+    fn trace_filters() {
+        let matches = parse_machine_args(&[]).unwrap();
+        assert_eq!(trace_filter(&matches), TraceFilter::default());
+
+        let matches = parse_machine_args(&[
+            "--trace-range",
+            "0x200-0x2FF",
+            "--trace-range=0x300-0x310",
+            "--trace-op",
+            "DRW,call",
+            "--trace-op=SE",
+        ])
+        .unwrap();
+        assert_eq!(
+            trace_filter(&matches),
+            TraceFilter {
+                ranges: vec![(0x200, 0x2FF), (0x300, 0x310)],
+                mnemonics: vec!["DRW".to_string(), "call".to_string(), "SE".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_trace_filters() {
+        for args in [
+            &["--trace-range", "0x2FF-0x200"][..],
+            &["--trace-range", "0x200-0x2FF,0x300-0x310"],
+            &["--trace-range"],
+            // Mnemonics are separated by commas only.
+            &["--trace-op", "DRW", "CALL"],
+        ] {
+            assert!(parse_machine_args(args).is_err(), "{:?}", args);
+        }
+
+        // Filters need a trace.
+        let without_trace = App::new("rs-chip8")
+            .args(&machine_args())
+            .get_matches_from_safe(["rs-chip8", "game.ch8", "--trace-op", "DRW"]);
+        assert!(without_trace.is_err());
+    }
 }
@@ -0,0 +1,606 @@
+//! The `debug` subcommand: a command-line debugger over the emulator core.
+
+use std::convert::TryFrom;
+use std::io::{self, BufRead, Write};
+
+use rs_chip8::{Access, Condition, Debugger, Instruction, KeyEvent, Stop, Watchpoint};
+
+/// Instructions `continue` executes before giving up, about half an hour at the default speed.
+const CONTINUE_LIMIT: u64 = 1_000_000;
+
+/// Instructions shown by `list` when no count is given.
+const DEFAULT_LIST_COUNT: u16 = 10;
+
+/// Bytes shown by `mem` when no length is given.
+const DEFAULT_DUMP_LENGTH: usize = 64;
+
+const HELP: &str = "\
+Numbers are decimal, or hexadecimal with a 0x prefix.
+
+  s, step [N]            execute N instructions (default 1)
+  c, continue            run until a breakpoint, an error or a key wait
+  b, break ADDR [if C]   set a breakpoint, stopping only when the condition C holds
+                         e.g. 'break 0x230 if v3 == 0x10 && i > 0x300'
+  d, delete ADDR         remove a breakpoint
+  w, watch ADDR [LEN] [r|w|rw]
+                         stop after instructions that read and/or write LEN bytes
+                         of memory (default 1 byte, writes)
+  unwatch ADDR           remove the watchpoints starting at ADDR
+  breakpoints            list the breakpoints and watchpoints
+  r, regs                print the registers
+  set REG VALUE          set V0-VF, I, PC, SP, DT or ST
+  stack                  print the return addresses on the stack
+  x, mem ADDR [LEN]      dump LEN bytes of memory (default 64)
+  l, list [N]            disassemble N instructions around PC (default 10)
+  fb, screen             print the framebuffer
+  press KEY, release KEY change the state of a key of the keypad (0-F)
+  h, help                print this help
+  q, quit                exit the debugger";
+
+fn parse_number(text: &str) -> Result<u32, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+
+    parsed.map_err(|_| format!("'{}' is not a number", text))
+}
+
+fn parse_u16(text: Option<&str>) -> Result<u16, String> {
+    let value = parse_number(text.ok_or("missing number")?)?;
+    u16::try_from(value).map_err(|_| format!("{} is out of range", text.unwrap_or_default()))
+}
+
+/// Parses the arguments of `watch`: `ADDR [LEN] [r|w|rw]`.
+fn parse_watchpoint(args: &[&str]) -> Result<Watchpoint, String> {
+    let addr = parse_u16(args.first().copied())?;
+    let mut rest = &args[1..];
+
+    let len = match rest.first() {
+        Some(len) if len.starts_with(|c: char| c.is_ascii_digit()) => {
+            rest = &rest[1..];
+            parse_u16(Some(len))?.max(1)
+        }
+        _ => 1,
+    };
+
+    let (read, write) = match rest {
+        [] | ["w"] => (false, true),
+        ["r"] => (true, false),
+        ["rw"] => (true, true),
+        [kind, ..] => return Err(format!("expected r, w or rw, found '{}'", kind)),
+    };
+
+    Ok(Watchpoint {
+        addr,
+        len,
+        read,
+        write,
+    })
+}
+
+fn format_watchpoint(watchpoint: &Watchpoint) -> String {
+    let kind = match (watchpoint.read, watchpoint.write) {
+        (true, true) => "reads and writes",
+        (true, false) => "reads",
+        _ => "writes",
+    };
+
+    let last = watchpoint.addr.wrapping_add(watchpoint.len.max(1) - 1);
+    if last == watchpoint.addr {
+        format!("0x{:03X} ({})", watchpoint.addr, kind)
+    } else {
+        format!("0x{:03X}-0x{:03X} ({})", watchpoint.addr, last, kind)
+    }
+}
+
+/// Runs the debugger, reading commands from stdin until `quit` or the end of the input.
+pub fn run(mut debugger: Debugger) -> anyhow::Result<()> {
+    let stdin = io::stdin();
+    let mut out = io::stdout().lock();
+
+    writeln!(out, "Type 'help' for the list of commands.")?;
+    write!(out, "{}", current(&debugger))?;
+
+    let mut last_command = String::new();
+    loop {
+        write!(out, "(chip8) ")?;
+        out.flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+
+        // An empty line repeats the last command, to step repeatedly.
+        let line = line.trim();
+        let command = if line.is_empty() {
+            last_command.clone()
+        } else {
+            line.to_string()
+        };
+
+        let mut words = command.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => continue,
+        };
+        let args: Vec<&str> = words.collect();
+
+        if name == "q" || name == "quit" {
+            return Ok(());
+        }
+
+        match execute(&mut debugger, name, &args) {
+            Ok(output) => write!(out, "{}", output)?,
+            Err(err) => writeln!(out, "error: {}", err)?,
+        }
+        last_command = command;
+    }
+}
+
+/// Executes a command, returning the text to print.
+fn execute(debugger: &mut Debugger, name: &str, args: &[&str]) -> Result<String, String> {
+    let mut out = String::new();
+
+    match name {
+        "s" | "step" => {
+            let count = match args.first() {
+                Some(count) => parse_number(count)? as u64,
+                None => 1,
+            };
+            let stop = debugger.step(count);
+            describe_stop(debugger, stop, &mut out);
+        }
+        "c" | "continue" => {
+            let stop = debugger.resume(CONTINUE_LIMIT);
+            if stop == Stop::Done {
+                out += &format!("Still running after {} instructions\n", CONTINUE_LIMIT);
+            }
+            describe_stop(debugger, stop, &mut out);
+        }
+        "b" | "break" => {
+            let addr = parse_u16(args.first().copied())?;
+            let condition = match args.get(1) {
+                Some(&"if") => Some(Condition::parse(&args[2..].join(" "))?),
+                Some(arg) => return Err(format!("expected 'if', found '{}'", arg)),
+                None => None,
+            };
+
+            let conditional = condition.is_some();
+            if debugger.set_breakpoint(addr, condition) {
+                out += &format!("Breakpoint set at 0x{:03X}\n", addr);
+            } else if conditional {
+                out += &format!("Condition of the breakpoint at 0x{:03X} updated\n", addr);
+            } else {
+                out += &format!("The breakpoint at 0x{:03X} is now unconditional\n", addr);
+            }
+        }
+        "d" | "delete" => {
+            let addr = parse_u16(args.first().copied())?;
+            if !debugger.remove_breakpoint(addr) {
+                return Err(format!("no breakpoint at 0x{:03X}", addr));
+            }
+        }
+        "w" | "watch" => {
+            let watchpoint = parse_watchpoint(args)?;
+            debugger.emulator_mut().add_watchpoint(watchpoint);
+            out += &format!("Watchpoint set on {}\n", format_watchpoint(&watchpoint));
+        }
+        "unwatch" => {
+            let addr = parse_u16(args.first().copied())?;
+            let emulator = debugger.emulator_mut();
+            let removed: Vec<Watchpoint> = emulator
+                .watchpoints()
+                .iter()
+                .filter(|watchpoint| watchpoint.addr == addr)
+                .copied()
+                .collect();
+
+            if removed.is_empty() {
+                return Err(format!("no watchpoint at 0x{:03X}", addr));
+            }
+            for watchpoint in &removed {
+                emulator.remove_watchpoint(watchpoint);
+            }
+        }
+        "breakpoints" => {
+            for addr in debugger.breakpoints() {
+                match debugger.condition(addr) {
+                    Some(condition) => out += &format!("0x{:03X} if {}\n", addr, condition),
+                    None => out += &format!("0x{:03X}\n", addr),
+                }
+            }
+            for watchpoint in debugger.emulator().watchpoints() {
+                out += &format!("watch {}\n", format_watchpoint(watchpoint));
+            }
+        }
+        "r" | "regs" => print_registers(debugger, &mut out),
+        "set" => set_register(debugger, args)?,
+        "stack" => {
+            for (depth, addr) in debugger.emulator().stack().iter().enumerate().rev() {
+                out += &format!("#{:<2} 0x{:03X}\n", depth, addr);
+            }
+        }
+        "x" | "mem" => {
+            let start = parse_u16(args.first().copied())? as usize;
+            let len = match args.get(1) {
+                Some(len) => parse_number(len)? as usize,
+                None => DEFAULT_DUMP_LENGTH,
+            };
+            dump_memory(debugger, start, len, &mut out);
+        }
+        "l" | "list" => {
+            let count = match args.first() {
+                Some(&count) => parse_u16(Some(count))?,
+                None => DEFAULT_LIST_COUNT,
+            };
+            list(debugger, count, &mut out);
+        }
+        "fb" | "screen" => print_framebuffer(debugger, &mut out),
+        "press" | "release" => {
+            let key = match args.first() {
+                Some(key) => usize::from_str_radix(key.trim_start_matches("0x"), 16)
+                    .ok()
+                    .filter(|&key| key < 16)
+                    .ok_or_else(|| format!("'{}' is not a key (0-F)", key))?,
+                None => return Err("missing key".to_string()),
+            };
+
+            let event = if name == "press" {
+                KeyEvent::Down(key)
+            } else {
+                KeyEvent::Up(key)
+            };
+            debugger.emulator_mut().handle_input(event);
+        }
+        "h" | "help" => out += &format!("{}\n", HELP),
+        _ => return Err(format!("unknown command '{}', try 'help'", name)),
+    }
+
+    Ok(out)
+}
+
+fn describe_stop(debugger: &Debugger, stop: Stop, out: &mut String) {
+    match stop {
+        Stop::Done => {}
+        Stop::Breakpoint(addr) => *out += &format!("Breakpoint at 0x{:03X}\n", addr),
+        Stop::Watchpoint(access) => {
+            *out += &match access.access {
+                Access::Read => format!("Read 0x{:02X} from", access.value),
+                Access::Write => format!("Wrote 0x{:02X} to", access.value),
+            };
+            *out += &format!(" 0x{:03X} at 0x{:03X}\n", access.addr, access.pc);
+        }
+        Stop::Halted(err) => *out += &format!("Emulator halted: {}\n", err),
+        Stop::Exited => *out += "The program exited\n",
+        Stop::WaitingForKey => *out += "Waiting for a key, use 'press' and 'release'\n",
+    }
+
+    *out += &current(debugger);
+}
+
+/// Returns the line showing the address and disassembly of the next instruction.
+fn current(debugger: &Debugger) -> String {
+    let pc = debugger.emulator().pc();
+    format!("=> {}\n", format_instruction(debugger, pc))
+}
+
+fn format_instruction(debugger: &Debugger, addr: u16) -> String {
+    let emulator = debugger.emulator();
+    let byte = |addr: u16| emulator.memory().get(addr as usize).copied();
+
+    match (byte(addr), byte(addr.wrapping_add(1))) {
+        (Some(high), Some(low)) => {
+            let opcode = (high as u16) << 8 | low as u16;
+            match Instruction::decode(opcode) {
+                Ok(instruction) => format!("0x{:03X}: {:04X}  {}", addr, opcode, instruction),
+                Err(_) => format!("0x{:03X}: {:04X}  ???", addr, opcode),
+            }
+        }
+        _ => format!("0x{:03X}: out of memory", addr),
+    }
+}
+
+fn print_registers(debugger: &Debugger, out: &mut String) {
+    let emulator = debugger.emulator();
+
+    for x in 0..16 {
+        *out += &format!("V{:X}=0x{:02X}", x, emulator.v(x));
+        *out += if x % 8 == 7 { "\n" } else { " " };
+    }
+
+    *out += &format!(
+        "I=0x{:03X} PC=0x{:03X} SP={} DT={} ST={}\n",
+        emulator.i(),
+        emulator.pc(),
+        emulator.sp(),
+        emulator.delay_timer(),
+        emulator.sound_timer()
+    );
+}
+
+fn set_register(debugger: &mut Debugger, args: &[&str]) -> Result<(), String> {
+    let (register, value) = match args {
+        [register, value] => (register.to_ascii_uppercase(), parse_number(value)?),
+        _ => return Err("usage: set REG VALUE".to_string()),
+    };
+
+    let byte = || u8::try_from(value).map_err(|_| format!("{} doesn't fit in a byte", value));
+    let word = || u16::try_from(value).map_err(|_| format!("{} doesn't fit in 16 bits", value));
+    let emulator = debugger.emulator_mut();
+
+    match register.as_str() {
+        "I" => emulator.set_i(word()?),
+        "PC" => emulator.set_pc(word()?),
+        "SP" if value <= 16 => emulator.set_sp(value as u16),
+        "SP" => return Err("the stack pointer is at most 16".to_string()),
+        "DT" => emulator.set_delay_timer(byte()?),
+        "ST" => emulator.set_sound_timer(byte()?),
+        _ => match register.strip_prefix('V') {
+            Some(x) if x.len() == 1 => {
+                let x = usize::from_str_radix(x, 16).map_err(|_| "invalid register")?;
+                emulator.set_v(x, byte()?);
+            }
+            _ => return Err(format!("unknown register '{}'", register)),
+        },
+    }
+
+    Ok(())
+}
+
+fn dump_memory(debugger: &Debugger, start: usize, len: usize, out: &mut String) {
+    let memory = debugger.emulator().memory();
+    let end = (start + len).min(memory.len());
+
+    for line_start in (start..end).step_by(16) {
+        let bytes = &memory[line_start..(line_start + 16).min(end)];
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let ascii: String = bytes
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+
+        *out += &format!("0x{:03X}: {:<47}  {}\n", line_start, hex.join(" "), ascii);
+    }
+}
+
+/// Disassembles `count` instructions, starting a few instructions before PC.
+/// Instructions are assumed to be aligned with PC, which might not hold for data.
+fn list(debugger: &Debugger, count: u16, out: &mut String) {
+    let pc = debugger.emulator().pc();
+    let start = pc.saturating_sub(count / 2 * 2);
+
+    for n in 0..count {
+        let addr = start.wrapping_add(n.wrapping_mul(2));
+        let marker = if addr == pc { "=>" } else { "  " };
+        let breakpoint = if debugger.breakpoints().any(|b| b == addr) {
+            '*'
+        } else {
+            ' '
+        };
+
+        *out += &format!(
+            "{}{} {}\n",
+            marker,
+            breakpoint,
+            format_instruction(debugger, addr)
+        );
+    }
+}
+
+/// Prints the framebuffer, `#` for the first plane, `+` for the second and `*` for both.
+fn print_framebuffer(debugger: &Debugger, out: &mut String) {
+    let emulator = debugger.emulator();
+    let (width, _) = emulator.display_size();
+
+    for row in emulator.get_framebuffer().chunks(width) {
+        out.extend(row.iter().map(|&pixel| match pixel & 0x3 {
+            0 => '.',
+            1 => '#',
+            2 => '+',
+            _ => '*',
+        }));
+        out.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rs_chip8::Chip8;
+
+    /// A debugger over a loop incrementing V0 and storing it at 0x300.
+    fn debugger() -> Debugger {
+        let program = [
+            0x60, 0x00, // 0x200: LD V0, 0
+            0x70, 0x01, // 0x202: ADD V0, 1
+            0xA3, 0x00, // 0x204: LD I, 0x300
+            0xF0, 0x55, // 0x206: LD [I], V0
+            0x12, 0x02, // 0x208: JP 0x202
+        ];
+        Debugger::new(Chip8::builder().memory(0x200, &program).build(), 10)
+    }
+
+    fn run(debugger: &mut Debugger, command: &str) -> Result<String, String> {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        execute(debugger, words[0], &words[1..])
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number("42"), Ok(42));
+        assert_eq!(parse_number("0x2A"), Ok(42));
+        assert_eq!(parse_number("0Xff"), Ok(255));
+        assert_eq!(parse_number("2A"), Err("'2A' is not a number".to_string()));
+        assert_eq!(parse_number("-1"), Err("'-1' is not a number".to_string()));
+
+        assert_eq!(parse_u16(Some("0xFFFF")), Ok(0xFFFF));
+        assert_eq!(
+            parse_u16(Some("0x10000")),
+            Err("0x10000 is out of range".to_string())
+        );
+        assert_eq!(parse_u16(None), Err("missing number".to_string()));
+    }
+
+    #[test]
+    fn watchpoints() {
+        let watchpoint = |addr, len, read, write| Watchpoint {
+            addr,
+            len,
+            read,
+            write,
+        };
+
+        assert_eq!(
+            parse_watchpoint(&["0x300"]),
+            Ok(watchpoint(0x300, 1, false, true))
+        );
+        assert_eq!(
+            parse_watchpoint(&["0x300", "r"]),
+            Ok(watchpoint(0x300, 1, true, false))
+        );
+        assert_eq!(
+            parse_watchpoint(&["0x300", "16", "rw"]),
+            Ok(watchpoint(0x300, 16, true, true))
+        );
+        assert_eq!(
+            parse_watchpoint(&["0x300", "0", "w"]),
+            Ok(watchpoint(0x300, 1, false, true))
+        );
+        assert_eq!(
+            parse_watchpoint(&["0x300", "x"]),
+            Err("expected r, w or rw, found 'x'".to_string())
+        );
+        assert_eq!(parse_watchpoint(&[]), Err("missing number".to_string()));
+
+        assert_eq!(
+            format_watchpoint(&watchpoint(0x300, 4, true, false)),
+            "0x300-0x303 (reads)"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut debugger = debugger();
+
+        let cases = [
+            ("frobnicate", "unknown command 'frobnicate', try 'help'"),
+            ("break", "missing number"),
+            ("break 0x202 when v0", "expected 'if', found 'when'"),
+            ("delete 0x202", "no breakpoint at 0x202"),
+            ("unwatch 0x300", "no watchpoint at 0x300"),
+            ("set v0", "usage: set REG VALUE"),
+            ("set v0 256", "256 doesn't fit in a byte"),
+            ("set SP 17", "the stack pointer is at most 16"),
+            ("set VG 1", "invalid register"),
+            ("set X 1", "unknown register 'X'"),
+            ("press 0x10", "'0x10' is not a key (0-F)"),
+        ];
+        for (command, message) in cases {
+            assert_eq!(
+                run(&mut debugger, command),
+                Err(message.to_string()),
+                "{}",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn set_registers() {
+        let mut debugger = debugger();
+        run(&mut debugger, "set vA 0x10").unwrap();
+        run(&mut debugger, "set i 0x345").unwrap();
+        run(&mut debugger, "set DT 60").unwrap();
+
+        let emulator = debugger.emulator();
+        assert_eq!(emulator.v(0xA), 0x10);
+        assert_eq!(emulator.i(), 0x345);
+        assert_eq!(emulator.delay_timer(), 60);
+    }
+
+    #[test]
+    fn step() {
+        let mut debugger = debugger();
+
+        let out = run(&mut debugger, "step").unwrap();
+        assert!(out.starts_with("=> 0x202: 7001"), "{}", out);
+
+        run(&mut debugger, "s 4").unwrap();
+        assert_eq!(debugger.emulator().pc(), 0x202);
+        assert_eq!(debugger.emulator().v(0), 1);
+    }
+
+    #[test]
+    fn break_and_continue() {
+        let mut debugger = debugger();
+
+        assert_eq!(
+            run(&mut debugger, "break 0x206"),
+            Ok("Breakpoint set at 0x206\n".to_string())
+        );
+        let out = run(&mut debugger, "continue").unwrap();
+        assert!(out.starts_with("Breakpoint at 0x206\n=> 0x206"), "{}", out);
+        assert_eq!(debugger.emulator().v(0), 1);
+
+        // Stepping stops at breakpoints too, after the first instruction.
+        let out = run(&mut debugger, "step 10").unwrap();
+        assert!(out.starts_with("Breakpoint at 0x206\n"), "{}", out);
+        assert_eq!(debugger.emulator().v(0), 2);
+
+        assert_eq!(
+            run(&mut debugger, "break 0x206 if v0 == 5"),
+            Ok("Condition of the breakpoint at 0x206 updated\n".to_string())
+        );
+        run(&mut debugger, "c").unwrap();
+        assert_eq!(debugger.emulator().v(0), 5);
+
+        run(&mut debugger, "delete 0x206").unwrap();
+        let out = run(&mut debugger, "continue").unwrap();
+        assert!(out.starts_with("Still running after"), "{}", out);
+    }
+
+    #[test]
+    fn watch_and_continue() {
+        let mut debugger = debugger();
+
+        run(&mut debugger, "watch 0x300").unwrap();
+        let out = run(&mut debugger, "continue").unwrap();
+        assert!(
+            out.starts_with("Wrote 0x01 to 0x300 at 0x206\n=> 0x208"),
+            "{}",
+            out
+        );
+
+        run(&mut debugger, "unwatch 0x300").unwrap();
+        assert!(debugger.emulator().watchpoints().is_empty());
+    }
+
+    #[test]
+    fn list_around_pc() {
+        let mut debugger = debugger();
+        run(&mut debugger, "s 2").unwrap();
+        run(&mut debugger, "b 0x206").unwrap();
+
+        let out = run(&mut debugger, "list 3").unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("    0x202: 7001"), "{}", out);
+        assert!(lines[1].starts_with("=>  0x204: A300"), "{}", out);
+        assert!(lines[2].starts_with("  * 0x206: F055"), "{}", out);
+    }
+
+    #[test]
+    fn list_the_whole_address_space() {
+        let mut debugger = debugger();
+        let out = run(&mut debugger, "list 65535").unwrap();
+        assert_eq!(out.lines().count(), 65535);
+    }
+}
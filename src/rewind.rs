@@ -0,0 +1,187 @@
+use std::collections::VecDeque;
+
+use crate::chip8::{Chip8, FRAME_RATE};
+
+/// Bytes that can be equal between two changed ranges, and still be stored as part of a
+/// single run: each run costs a few bytes of bookkeeping, so tiny gaps aren't worth it.
+const MAX_RUN_GAP: usize = 8;
+
+/// The changes that turn a save state into the one of the previous frame.
+#[derive(Debug, Clone)]
+enum Delta {
+    /// The previous frame's state, when its layout is different (e.g. the resolution changed).
+    Full(Vec<u8>),
+    /// Ranges of bytes, by offset, holding the previous frame's values.
+    Runs(Vec<(usize, Vec<u8>)>),
+}
+
+impl Delta {
+    /// Computes the delta that turns `current` back into `previous`.
+    fn between(current: &[u8], previous: &[u8]) -> Delta {
+        if current.len() != previous.len() {
+            return Delta::Full(previous.to_vec());
+        }
+
+        let mut runs: Vec<(usize, Vec<u8>)> = Vec::new();
+        let mut end = 0;
+
+        for (offset, (a, b)) in current.iter().zip(previous).enumerate() {
+            if a == b {
+                continue;
+            }
+
+            match runs.last_mut() {
+                Some((start, bytes)) if offset - end <= MAX_RUN_GAP => {
+                    bytes.extend_from_slice(&previous[*start + bytes.len()..=offset]);
+                }
+                _ => runs.push((offset, vec![*b])),
+            }
+            end = offset + 1;
+        }
+
+        Delta::Runs(runs)
+    }
+
+    /// Turns `state` into the previous frame's state.
+    fn apply(self, state: &mut Vec<u8>) {
+        match self {
+            Delta::Full(previous) => *state = previous,
+            Delta::Runs(runs) => {
+                for (offset, bytes) in runs {
+                    state[offset..offset + bytes.len()].copy_from_slice(&bytes);
+                }
+            }
+        }
+    }
+}
+
+/// A ring buffer of the last frames of an emulator, to step back through them.
+///
+/// Only the latest frame is kept as a full save state: every older frame is
+/// stored as the difference with the one after it, which is usually a handful of bytes.
+#[derive(Debug)]
+pub struct Rewind {
+    capacity: usize,
+    /// Save state of the latest frame.
+    current: Option<Vec<u8>>,
+    /// Deltas to go back one frame from `current`, the most recent last.
+    deltas: VecDeque<Delta>,
+}
+
+impl Rewind {
+    /// Creates a buffer that can go back up to `frames` frames.
+    pub fn new(frames: usize) -> Rewind {
+        Rewind {
+            capacity: frames,
+            current: None,
+            deltas: VecDeque::with_capacity(frames),
+        }
+    }
+
+    /// Creates a buffer that can go back up to `seconds` seconds of emulation.
+    pub fn with_seconds(seconds: u32) -> Rewind {
+        Rewind::new(seconds as usize * FRAME_RATE as usize)
+    }
+
+    /// Records the state of `emulator`, to be called after each frame.
+    /// The oldest frame is dropped once the buffer is full.
+    pub fn push(&mut self, emulator: &Chip8) {
+        let state = emulator.save_state();
+
+        if let Some(previous) = self.current.take() {
+            if self.deltas.len() == self.capacity {
+                self.deltas.pop_front();
+            }
+
+            if self.capacity > 0 {
+                self.deltas.push_back(Delta::between(&state, &previous));
+            }
+        }
+
+        self.current = Some(state);
+    }
+
+    /// Restores `emulator` to the frame before the latest one recorded, which is
+    /// then dropped from the buffer. Returns `false`, leaving `emulator`
+    /// untouched, when there are no older frames.
+    pub fn rewind(&mut self, emulator: &mut Chip8) -> bool {
+        let (delta, state) = match (self.deltas.pop_back(), self.current.as_mut()) {
+            (Some(delta), Some(state)) => (delta, state),
+            _ => return false,
+        };
+
+        delta.apply(state);
+        emulator
+            .load_state(state)
+            .expect("rewind states come from the same emulator");
+        true
+    }
+
+    /// Returns the number of frames that can be rewound.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delta_round_trip(previous: &[u8], current: &[u8]) -> Delta {
+        let delta = Delta::between(current, previous);
+        let mut state = current.to_vec();
+        delta.clone().apply(&mut state);
+        assert_eq!(state, previous);
+        delta
+    }
+
+    #[test]
+    fn delta_of_equal_states() {
+        let delta = delta_round_trip(&[1, 2, 3], &[1, 2, 3]);
+        assert!(matches!(delta, Delta::Runs(runs) if runs.is_empty()));
+    }
+
+    #[test]
+    fn delta_runs() {
+        let previous = vec![0; 64];
+        let mut current = previous.clone();
+        // Close changes share a run, distant ones don't.
+        current[2] = 1;
+        current[5] = 1;
+        current[40] = 1;
+
+        match delta_round_trip(&previous, &current) {
+            Delta::Runs(runs) => assert_eq!(runs, vec![(2, vec![0; 4]), (40, vec![0])]),
+            delta => panic!("{:?}", delta),
+        }
+    }
+
+    #[test]
+    fn delta_of_different_sizes() {
+        let delta = delta_round_trip(&[1, 2, 3, 4], &[1, 2]);
+        assert!(matches!(delta, Delta::Full(previous) if previous == [1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn capacity_in_seconds() {
+        let mut emulator = Chip8::new();
+        emulator.load_rom(&[0x12, 0x00]).unwrap(); // JP 0x200
+        let mut rewind = Rewind::with_seconds(2);
+
+        for _ in 0..3 * FRAME_RATE {
+            emulator.run_frame(10).unwrap();
+            rewind.push(&emulator);
+        }
+        assert_eq!(rewind.len(), 2 * FRAME_RATE as usize);
+
+        let mut rewind = Rewind::with_seconds(0);
+        rewind.push(&emulator);
+        rewind.push(&emulator);
+        assert!(rewind.is_empty());
+        assert!(!rewind.rewind(&mut emulator));
+    }
+}
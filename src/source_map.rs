@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// A line of a source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    pub file: String,
+    /// Line number, starting at 1.
+    pub line: usize,
+}
+
+/// Maps the addresses of instructions to the source lines they were assembled from.
+///
+/// It's stored as text, one instruction per line: its address in hexadecimal and
+/// its `file:line`, e.g. `0x200 pong.s:12`. Empty lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    lines: BTreeMap<u16, SourceLine>,
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap::default()
+    }
+
+    /// Parses a source map, returning an error with the line number of the first invalid line.
+    pub fn parse(text: &str) -> Result<SourceMap, String> {
+        let mut map = SourceMap::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let entry = line
+                .split_once(char::is_whitespace)
+                .and_then(|(addr, location)| {
+                    let addr = addr.strip_prefix("0x").unwrap_or(addr);
+                    let (file, number) = location.trim().rsplit_once(':')?;
+                    Some((
+                        u16::from_str_radix(addr, 16).ok()?,
+                        file,
+                        number.parse().ok()?,
+                    ))
+                });
+
+            match entry {
+                Some((addr, file, number)) => map.insert(addr, file, number),
+                None => return Err(format!("line {}: expected 'ADDR FILE:LINE'", index + 1)),
+            }
+        }
+
+        Ok(map)
+    }
+
+    /// Records that the instruction at `addr` comes from `line` of `file`.
+    pub fn insert(&mut self, addr: u16, file: &str, line: usize) {
+        self.lines.insert(
+            addr,
+            SourceLine {
+                file: file.to_string(),
+                line,
+            },
+        );
+    }
+
+    /// Returns the source line of the instruction at `addr`.
+    pub fn line_at(&self, addr: u16) -> Option<&SourceLine> {
+        self.lines.get(&addr)
+    }
+
+    /// Returns the address of the first instruction assembled from `line` of `file`.
+    ///
+    /// Files match when one path ends with the other, so that relative paths
+    /// in the map match the absolute paths used by editors.
+    pub fn address_of(&self, file: &str, line: usize) -> Option<u16> {
+        let file = Path::new(file);
+
+        self.lines
+            .iter()
+            .find(|(_, source)| {
+                let mapped = Path::new(&source.file);
+                source.line == line && (file.ends_with(mapped) || mapped.ends_with(file))
+            })
+            .map(|(&addr, _)| addr)
+    }
+}
+
+impl fmt::Display for SourceMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (addr, source) in &self.lines {
+            writeln!(f, "0x{:03X} {}:{}", addr, source.file, source.line)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,529 @@
+//! The `trace-diff` subcommand: runs a ROM and compares the state of the machine,
+//! instruction by instruction, with a reference trace written by another emulator.
+//!
+//! References are either in the text format written by `--trace`, or CSV files
+//! with one column per register. Only the columns a reference has are compared,
+//! and the results of CXNN are taken from the reference, since random numbers
+//! can't be reproduced across emulators.
+
+use std::convert::TryFrom;
+use std::fmt::Write as _;
+
+use anyhow::bail;
+
+use rs_chip8::{Chip8, Debugger, Instruction, Stop};
+
+const REGISTERS: usize = 16;
+
+/// The state of the machine before an instruction executes. The fields that a
+/// reference doesn't have are `None`, and aren't compared.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct State {
+    pc: Option<u16>,
+    opcode: Option<u16>,
+    v: [Option<u8>; REGISTERS],
+    i: Option<u16>,
+    sp: Option<u16>,
+    dt: Option<u8>,
+    st: Option<u8>,
+}
+
+impl State {
+    fn of(emulator: &Chip8) -> State {
+        let pc = emulator.pc();
+        let byte = |addr: u16| emulator.memory().get(addr as usize).copied();
+        let opcode = byte(pc)
+            .zip(byte(pc.wrapping_add(1)))
+            .map(|(high, low)| (high as u16) << 8 | low as u16);
+
+        let mut v = [None; REGISTERS];
+        for (x, value) in v.iter_mut().enumerate() {
+            *value = Some(emulator.v(x));
+        }
+
+        State {
+            pc: Some(pc),
+            opcode,
+            v,
+            i: Some(emulator.i()),
+            sp: Some(emulator.sp()),
+            dt: Some(emulator.delay_timer()),
+            st: Some(emulator.sound_timer()),
+        }
+    }
+
+    /// Sets `field` to `value`. Returns `false` if it doesn't fit.
+    fn set(&mut self, field: Field, value: u32) -> bool {
+        fn fits<T: TryFrom<u32>>(field: &mut Option<T>, value: u32) -> bool {
+            *field = T::try_from(value).ok();
+            field.is_some()
+        }
+
+        match field {
+            Field::Pc => fits(&mut self.pc, value),
+            Field::Opcode => fits(&mut self.opcode, value),
+            Field::V(x) => fits(&mut self.v[x], value),
+            Field::I => fits(&mut self.i, value),
+            Field::Sp => fits(&mut self.sp, value),
+            Field::Dt => fits(&mut self.dt, value),
+            Field::St => fits(&mut self.st, value),
+        }
+    }
+
+    /// Returns the (name, value) of every field, formatted in hexadecimal.
+    fn fields(&self) -> Vec<(String, Option<String>)> {
+        let mut fields = vec![
+            ("PC".to_string(), self.pc.map(|pc| format!("0x{:03X}", pc))),
+            (
+                "opcode".to_string(),
+                self.opcode.map(|op| format!("0x{:04X}", op)),
+            ),
+        ];
+
+        for (x, value) in self.v.iter().enumerate() {
+            fields.push((format!("V{:X}", x), value.map(|v| format!("0x{:02X}", v))));
+        }
+
+        fields.push(("I".to_string(), self.i.map(|i| format!("0x{:03X}", i))));
+        fields.push(("SP".to_string(), self.sp.map(|sp| sp.to_string())));
+        fields.push(("DT".to_string(), self.dt.map(|dt| format!("0x{:02X}", dt))));
+        fields.push(("ST".to_string(), self.st.map(|st| format!("0x{:02X}", st))));
+        fields
+    }
+}
+
+/// A field of `State`, named like the columns of CSV traces.
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Pc,
+    Opcode,
+    V(usize),
+    I,
+    Sp,
+    Dt,
+    St,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        let field = match name {
+            "pc" => Field::Pc,
+            "opcode" => Field::Opcode,
+            "i" => Field::I,
+            "sp" => Field::Sp,
+            "dt" => Field::Dt,
+            "st" => Field::St,
+            _ => name
+                .strip_prefix('v')
+                .filter(|x| x.len() == 1)
+                .and_then(|x| usize::from_str_radix(x, 16).ok())
+                .map(Field::V)?,
+        };
+
+        Some(field)
+    }
+}
+
+/// A state read from line `line` of a reference trace.
+#[derive(Debug, Clone)]
+pub struct Reference {
+    line: usize,
+    state: State,
+}
+
+/// Parses a number, hexadecimal with a `0x` prefix or decimal otherwise.
+fn parse_number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn narrow<T: TryFrom<u32>>(value: Option<u32>) -> Option<T> {
+    value.and_then(|value| T::try_from(value).ok())
+}
+
+/// Parses a trace in the format written by `--trace`, where numbers are hexadecimal:
+///
+/// ```text
+/// CYCLE PC OPCODE DISASSEMBLY V=V0 V1 ... VF I=I SP=SP DT=DT ST=ST
+/// ```
+///
+/// Empty lines and lines starting with `#` are ignored.
+pub fn parse_text(text: &str) -> Result<Vec<Reference>, String> {
+    let mut references = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let error = || format!("line {}: not an instruction trace", index + 1);
+        let hex = |text: &str| u32::from_str_radix(text, 16).ok();
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        // The disassembly has a variable number of words, the registers start after it.
+        let registers = words
+            .iter()
+            .position(|word| word.starts_with("V="))
+            .filter(|&start| start >= 3 && words.len() == start + REGISTERS + 4)
+            .ok_or_else(error)?;
+
+        let mut state = State {
+            pc: narrow(hex(words[1])),
+            opcode: narrow(hex(words[2])),
+            ..State::default()
+        };
+
+        for (x, word) in words[registers..registers + REGISTERS].iter().enumerate() {
+            state.v[x] = narrow(hex(word.trim_start_matches("V=")));
+        }
+
+        let named = |name: &str, word: &str| word.strip_prefix(name).and_then(hex);
+        let rest = &words[registers + REGISTERS..];
+        state.i = narrow(named("I=", rest[0]));
+        state.sp = narrow(named("SP=", rest[1]));
+        state.dt = narrow(named("DT=", rest[2]));
+        state.st = narrow(named("ST=", rest[3]));
+
+        if state.pc.is_none()
+            || state.opcode.is_none()
+            || state.v.iter().any(Option::is_none)
+            || state.i.is_none()
+            || state.sp.is_none()
+            || state.dt.is_none()
+            || state.st.is_none()
+        {
+            return Err(error());
+        }
+
+        references.push(Reference {
+            line: index + 1,
+            state,
+        });
+    }
+
+    Ok(references)
+}
+
+/// Parses a CSV trace. Its first line names the columns, among `pc`, `opcode`,
+/// `v0` to `vf`, `i`, `sp`, `dt` and `st` (case-insensitive), and other columns
+/// are ignored. Numbers are decimal, or hexadecimal with a `0x` prefix.
+pub fn parse_csv(text: &str) -> Result<Vec<Reference>, String> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+
+    let columns: Vec<String> = match lines.next() {
+        Some((_, header)) => header
+            .split(',')
+            .map(|name| name.trim().to_ascii_lowercase())
+            .collect(),
+        None => return Ok(Vec::new()),
+    };
+
+    let mut references = Vec::new();
+    for (index, line) in lines {
+        let values: Vec<&str> = line.split(',').map(str::trim).collect();
+        if values.len() != columns.len() {
+            return Err(format!(
+                "line {}: {} values for {} columns",
+                index + 1,
+                values.len(),
+                columns.len()
+            ));
+        }
+
+        let mut state = State::default();
+        for (name, text) in columns.iter().zip(values) {
+            let field = match Field::from_name(name) {
+                Some(field) => field,
+                None => continue,
+            };
+
+            if !matches!(parse_number(text), Some(value) if state.set(field, value)) {
+                return Err(format!("line {}: invalid {} '{}'", index + 1, name, text));
+            }
+        }
+
+        references.push(Reference {
+            line: index + 1,
+            state,
+        });
+    }
+
+    Ok(references)
+}
+
+/// Returns the names of the fields of `reference` that differ in `actual`.
+fn differences(reference: &State, actual: &State) -> Vec<String> {
+    reference
+        .fields()
+        .into_iter()
+        .zip(actual.fields())
+        .filter(|((_, expected), (_, value))| expected.is_some() && expected != value)
+        .map(|((name, _), _)| name)
+        .collect()
+}
+
+/// Describes the instruction at `addr`, e.g. `0x2D8: F265 LD V2, [I]`.
+fn describe_instruction(addr: u16, opcode: Option<u16>) -> String {
+    match opcode {
+        Some(opcode) => match Instruction::decode(opcode) {
+            Ok(instruction) => format!("0x{:03X}: {:04X} {}", addr, opcode, instruction),
+            Err(_) => format!("0x{:03X}: {:04X}", addr, opcode),
+        },
+        None => format!("0x{:03X}", addr),
+    }
+}
+
+/// Formats the reference and actual states side by side, marking the differences.
+fn report(reference: &State, actual: &State) -> String {
+    let different = differences(reference, actual);
+    let mut out = format!("  {:<8}{:<12}{}\n", "", "reference", "rs-chip8");
+
+    for ((name, expected), (_, value)) in reference.fields().into_iter().zip(actual.fields()) {
+        let marker = if different.contains(&name) {
+            "  <=="
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            out,
+            "  {:<8}{:<12}{}{}",
+            name,
+            expected.as_deref().unwrap_or("-"),
+            value.as_deref().unwrap_or("-"),
+            marker
+        );
+    }
+
+    out
+}
+
+/// Steps through the program, comparing the state before each instruction with
+/// the `reference`, until the first divergence or the end of the reference.
+pub fn run(mut debugger: Debugger, reference: &[Reference]) -> anyhow::Result<()> {
+    if let Some(divergence) = first_divergence(&mut debugger, reference)? {
+        print!("{}", divergence);
+        bail!("the traces differ");
+    }
+
+    println!(
+        "The {} instructions of the reference match",
+        reference.len()
+    );
+    Ok(())
+}
+
+/// Like `run`, returning the description of the first divergence, if any.
+fn first_divergence(
+    debugger: &mut Debugger,
+    reference: &[Reference],
+) -> anyhow::Result<Option<String>> {
+    // The address and opcode of the last instruction executed, to tell what caused a divergence.
+    let mut last: Option<(u16, Option<u16>)> = None;
+
+    for (count, expected) in reference.iter().enumerate() {
+        // Random numbers can't be reproduced, take the result of CXNN from the reference.
+        let last_instruction = last.and_then(|(_, opcode)| Instruction::decode(opcode?).ok());
+        if let Some(Instruction::Random { x, .. }) = last_instruction {
+            if let Some(value) = expected.state.v[x] {
+                debugger.emulator_mut().set_v(x, value);
+            }
+        }
+
+        let actual = State::of(debugger.emulator());
+
+        if !differences(&expected.state, &actual).is_empty() {
+            let mut out = match last {
+                Some((addr, opcode)) => format!(
+                    "Diverged before instruction {} (line {} of the reference), after {}\n",
+                    count,
+                    expected.line,
+                    describe_instruction(addr, opcode)
+                ),
+                None => format!(
+                    "Diverged before the first instruction (line {} of the reference)\n",
+                    expected.line
+                ),
+            };
+            out += &report(&expected.state, &actual);
+            return Ok(Some(out));
+        }
+
+        if count + 1 == reference.len() {
+            break;
+        }
+
+        last = Some((debugger.emulator().pc(), actual.opcode));
+
+        match debugger.step(1) {
+            Stop::Done | Stop::Breakpoint(_) | Stop::Watchpoint(_) => {}
+            Stop::Halted(err) => bail!(
+                "the emulator halted after {} instructions, before the end of the reference: {}",
+                count + 1,
+                err
+            ),
+            Stop::Exited => bail!(
+                "the program exited after {} instructions, before the end of the reference",
+                count + 1
+            ),
+            Stop::WaitingForKey => bail!(
+                "the program waits for a key after {} instructions, which traces don't record",
+                count + 1
+            ),
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// LD V0, 5; ADD V0, 1; RND V1, 0xFF; JP 0x202
+    fn debugger() -> Debugger {
+        let program = [0x60, 0x05, 0x70, 0x01, 0xC1, 0xFF, 0x12, 0x02];
+        Debugger::new(Chip8::builder().memory(0x200, &program).build(), 10)
+    }
+
+    #[test]
+    fn text() {
+        let registers = |v0| format!("V={:02X}{}", v0, " 00".repeat(15));
+        let text = format!(
+            "# a comment\n\n       0 0200 00E0 CLS                {} I=0000 SP=0 DT=00 ST=00\n\
+             1 0202 DAB6 DRW VA, VB, 6 {} I=02EA SP=2 DT=3C ST=0A\n",
+            registers(0),
+            registers(0x1F)
+        );
+
+        let references = parse_text(&text).unwrap();
+        assert_eq!(references.len(), 2);
+        assert_eq!(references[0].line, 3);
+        assert_eq!(references[0].state.pc, Some(0x200));
+        assert_eq!(references[0].state.opcode, Some(0x00E0));
+
+        let state = &references[1].state;
+        assert_eq!(references[1].line, 4);
+        assert_eq!(state.v[0], Some(0x1F));
+        assert_eq!(state.v[1], Some(0));
+        assert_eq!(
+            (state.i, state.sp, state.dt, state.st),
+            (Some(0x2EA), Some(2), Some(0x3C), Some(0x0A))
+        );
+    }
+
+    #[test]
+    fn malformed_text() {
+        let registers = format!("V={}", " 00".repeat(16).trim_start());
+        for line in [
+            "0 0200 00E0 CLS".to_string(),
+            format!("0 0200 00E0 CLS {} I=0000 SP=0 DT=00", registers),
+            format!("0 0200 00E0 CLS {} I=0000 SP=0 DT=00 ST=00 X", registers),
+            format!("0 0200 00E0 CLS {} I=0000 SP=0 DT=00 XX=00", registers),
+            format!("0 0200 00E0 CLS {} I=0000 SP=0 DT=100 ST=00", registers),
+            format!("0 0200 ZZZZ CLS {} I=0000 SP=0 DT=00 ST=00", registers),
+            format!("0 0200 {} I=0000 SP=0 DT=00 ST=00", registers),
+        ] {
+            assert_eq!(
+                parse_text(&format!("\n{}", line)).unwrap_err(),
+                "line 2: not an instruction trace",
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn csv() {
+        let text = "cycle, PC, V0 ,vF,i,comment\n0,0x200,0,0x1,512,start\n\n1,0X202,255,0,0x2EA,\n";
+
+        let references = parse_csv(text).unwrap();
+        assert_eq!(references.len(), 2);
+        assert_eq!(references[1].line, 4);
+
+        let state = &references[1].state;
+        assert_eq!(state.pc, Some(0x202));
+        assert_eq!(state.v[0], Some(255));
+        assert_eq!(state.v[0xF], Some(0));
+        assert_eq!(state.i, Some(0x2EA));
+        // Columns the reference doesn't have aren't compared.
+        assert_eq!((state.opcode, state.v[1], state.sp), (None, None, None));
+
+        assert_eq!(references[0].state.i, Some(0x200));
+        assert!(parse_csv("").unwrap().is_empty());
+        assert!(parse_csv("pc,v0\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed_csv() {
+        let cases = [
+            ("pc,v0\n0x200", "line 2: 1 values for 2 columns"),
+            ("pc,v0\n0x200,1,2", "line 2: 3 values for 2 columns"),
+            ("pc,v0\n0x200,256", "line 2: invalid v0 '256'"),
+            ("pc,v0\n0x200,-1", "line 2: invalid v0 '-1'"),
+            ("pc,v0\n0x200,0x", "line 2: invalid v0 '0x'"),
+            ("pc,v0\n0x200,1\nFF,1", "line 3: invalid pc 'FF'"),
+            ("pc,v0\n0x10000,1", "line 2: invalid pc '0x10000'"),
+        ];
+
+        for (text, message) in cases {
+            assert_eq!(parse_csv(text).unwrap_err(), message, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn matching_reference() {
+        let reference =
+            parse_csv("pc,v0,v1\n0x200,0,0\n0x202,5,0\n0x204,6,0\n0x206,6,0x2A\n0x202,6,0x2A")
+                .unwrap();
+        assert_eq!(first_divergence(&mut debugger(), &reference).unwrap(), None);
+    }
+
+    #[test]
+    fn divergence() {
+        let reference = parse_csv("pc,v0\n0x200,0\n0x202,5\n0x204,7\n0x206,7").unwrap();
+        let divergence = first_divergence(&mut debugger(), &reference)
+            .unwrap()
+            .unwrap();
+
+        let lines: Vec<&str> = divergence.lines().collect();
+        assert_eq!(
+            lines[0],
+            "Diverged before instruction 2 (line 4 of the reference), after 0x202: 7001 ADD V0, 0x01"
+        );
+        assert!(
+            lines.contains(&"  PC      0x204       0x204"),
+            "{}",
+            divergence
+        );
+        assert!(
+            lines.contains(&"  V0      0x07        0x06  <=="),
+            "{}",
+            divergence
+        );
+        assert!(
+            lines.contains(&"  V1      -           0x00"),
+            "{}",
+            divergence
+        );
+    }
+
+    #[test]
+    fn divergence_before_the_first_instruction() {
+        let reference = parse_csv("pc\n0x300").unwrap();
+        let divergence = first_divergence(&mut debugger(), &reference)
+            .unwrap()
+            .unwrap();
+        assert!(
+            divergence
+                .starts_with("Diverged before the first instruction (line 2 of the reference)\n"),
+            "{}",
+            divergence
+        );
+    }
+}
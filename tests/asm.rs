@@ -0,0 +1,208 @@
+//! Tests of the assembler: symbols, data directives, includes, the errors it
+//! reports, and the round trip through the disassembler.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use rs_chip8::{assemble, assemble_file, AsmError, Disassembly, PROGRAM_START};
+
+fn assemble_ok(source: &str) -> Vec<u8> {
+    assemble(source).unwrap_or_else(|err| panic!("{}", err))
+}
+
+fn assemble_err(source: &str) -> AsmError {
+    match assemble(source) {
+        Ok(rom) => panic!("assembled to {:02X?}", rom),
+        Err(err) => err,
+    }
+}
+
+/// Creates an empty directory for the files of a test.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("rs-chip8-asm-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn instructions() {
+    let rom = assemble_ok(
+        "
+        CLS
+        LD V1, 0x2A
+        ld va, vb       ; case-insensitive
+        DRW V0, V1, 5
+        LD I, LONG 0x1234
+        ",
+    );
+
+    assert_eq!(
+        rom,
+        [0x00, 0xE0, 0x61, 0x2A, 0x8A, 0xB0, 0xD0, 0x15, 0xF0, 0x00, 0x12, 0x34]
+    );
+}
+
+#[test]
+fn labels() {
+    let rom = assemble_ok(
+        "
+        start: JP end       ; forward reference
+        loop:
+            CALL loop
+        end:
+            JP start
+        ",
+    );
+
+    assert_eq!(rom, [0x12, 0x04, 0x22, 0x02, 0x12, 0x00]);
+}
+
+#[test]
+fn constants() {
+    let rom = assemble_ok(
+        "
+        SPEED = BASE + 2    ; defined before BASE
+        BASE = 0x10
+        LD V0, SPEED
+        LD V1, -(1)         ; negative bytes wrap around
+        DRW V0, V1, sprite_end - sprite
+        sprite:
+            db 0b10101010, 0xFF
+        sprite_end:
+        ",
+    );
+
+    assert_eq!(rom, [0x60, 0x12, 0x61, 0xFF, 0xD0, 0x12, 0xAA, 0xFF]);
+}
+
+#[test]
+fn data() {
+    let rom = assemble_ok(
+        r#"
+        db 1, 2, "HI; not a comment", 0
+        dw 0x1234, -1
+        "#,
+    );
+
+    let mut expected = vec![1, 2];
+    expected.extend(b"HI; not a comment");
+    expected.extend([0, 0x12, 0x34, 0xFF, 0xFF]);
+    assert_eq!(rom, expected);
+}
+
+#[test]
+fn include() {
+    let dir = temp_dir("include");
+    fs::create_dir(dir.join("lib")).unwrap();
+    fs::write(dir.join("main.s"), "JP sprite\ninclude \"lib/sprite.s\"\n").unwrap();
+    // Relative to the including file.
+    fs::write(dir.join("lib/sprite.s"), "include \"data.s\"\n").unwrap();
+    fs::write(dir.join("lib/data.s"), "sprite: db 0xF0\n").unwrap();
+
+    let rom = assemble_file(&dir.join("main.s")).unwrap();
+    assert_eq!(rom, [0x12, 0x02, 0xF0]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn error_in_an_included_file() {
+    let dir = temp_dir("include-error");
+    fs::write(dir.join("main.s"), "CLS\ninclude \"bad.s\"\n").unwrap();
+    fs::write(dir.join("bad.s"), "CLS\n\nJP nowhere\n").unwrap();
+
+    let err = assemble_file(&dir.join("main.s")).unwrap_err();
+    assert!(err.file.ends_with("bad.s"), "{}", err);
+    assert_eq!(err.line, 3);
+    assert_eq!(err.message, "undefined symbol 'nowhere'");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn include_loop() {
+    let dir = temp_dir("include-loop");
+    fs::write(dir.join("loop.s"), "include \"loop.s\"\n").unwrap();
+
+    let err = assemble_file(&dir.join("loop.s")).unwrap_err();
+    assert_eq!(err.message, "includes are nested too deeply");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn errors() {
+    let cases = [
+        (
+            "CLS\nFOO V0",
+            2,
+            "invalid instruction or operands for 'FOO'",
+        ),
+        ("LD V0, missing", 1, "undefined symbol 'missing'"),
+        ("a:\na:", 2, "'a' is already defined"),
+        (
+            "A = B\nB = A\nLD V0, A",
+            3,
+            "constant is defined in terms of itself",
+        ),
+        ("\n\nLD V0, 256", 3, "256 doesn't fit in 8 bits"),
+        ("LD V0, -129", 1, "-129 doesn't fit in 8 bits"),
+        ("DRW V0, V1, 16", 1, "16 doesn't fit in 4 bits"),
+        ("JP 0x1000", 1, "4096 doesn't fit in 12 bits"),
+        ("JP -2", 1, "-2 is not a valid address"),
+        ("LD I, LONG -1", 1, "-1 is not a valid address"),
+        ("LD V0, (1 + 2", 1, "missing ')' in expression"),
+        ("LD V0, 0xZZ", 1, "invalid number '0xZZ'"),
+        ("db \"é\"", 1, "'é' is not an ASCII string"),
+    ];
+
+    for (source, line, message) in cases {
+        let err = assemble_err(source);
+        assert_eq!(
+            (err.line, err.message.as_str()),
+            (line, message),
+            "{:?}",
+            source
+        );
+    }
+}
+
+#[test]
+fn overflowing_expressions() {
+    let max = "0x7FFFFFFFFFFFFFFF";
+
+    for source in [
+        format!("LD V0, {} + 1", max),
+        format!("LD V0, -{} - 2", max),
+        format!("M = -{} - 1\nLD V0, -M", max),
+    ] {
+        assert_eq!(
+            assemble_err(&source).message,
+            "expression overflows",
+            "{}",
+            source
+        );
+    }
+}
+
+/// Disassembling each game and assembling the result gives back the game.
+#[test]
+fn disassembly_round_trip() {
+    let games = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("games");
+    let mut count = 0;
+
+    for entry in fs::read_dir(&games).unwrap() {
+        let path = entry.unwrap().path();
+        let rom = fs::read(&path).unwrap();
+
+        let source = Disassembly::new(&rom, PROGRAM_START).to_string();
+        let assembled = assemble(&source)
+            .unwrap_or_else(|err| panic!("{}: {}\n{}", path.display(), err, source));
+        assert!(assembled == rom, "{} differs", path.display());
+        count += 1;
+    }
+
+    assert!(count > 0, "no games in {}", games.display());
+}
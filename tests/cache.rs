@@ -0,0 +1,114 @@
+//! Tests of the instruction cache: code modified after it was cached, by each
+//! way of writing memory, must execute as modified.
+
+mod common;
+
+use rs_chip8::{Chip8, Chip8Builder};
+
+/// A builder with `LD V1, 1` at 0x200.
+fn builder() -> Chip8Builder {
+    common::builder().memory(0x200, &[0x61, 0x01])
+}
+
+/// Enables the cache and executes `LD V1, 1`, which caches it.
+fn cached(builder: Chip8Builder) -> Chip8 {
+    let mut c8 = builder.build();
+    c8.set_instruction_cache(true);
+    assert!(c8.instruction_cache());
+
+    c8.step().unwrap();
+    assert_eq!(c8.v(0x1), 1);
+    c8
+}
+
+/// Executes the instruction at `addr`, and returns V1.
+fn run_at(c8: &mut Chip8, addr: u16) -> u8 {
+    c8.set_pc(addr);
+    c8.step().unwrap();
+    c8.v(0x1)
+}
+
+#[test]
+fn cached_instruction() {
+    let mut c8 = cached(builder());
+    c8.set_v(0x1, 0);
+    assert_eq!(run_at(&mut c8, 0x200), 1);
+}
+
+#[test]
+fn write_to_the_operands() {
+    let mut c8 = cached(builder());
+    c8.write(0x201, 0x05).unwrap();
+    assert_eq!(run_at(&mut c8, 0x200), 5);
+}
+
+#[test]
+fn write_to_the_opcode() {
+    let mut c8 = cached(builder());
+    c8.write(0x200, 0x71).unwrap(); // ADD V1, 1
+    assert_eq!(run_at(&mut c8, 0x200), 2);
+}
+
+#[test]
+fn store_registers() {
+    // LD [I], V1 writes LD V1, 7 over the cached instruction.
+    let mut c8 = cached(builder().memory(0x300, &[0xF1, 0x55]).i(0x200).v(0x0, 0x61));
+    c8.set_v(0x1, 0x07);
+    run_at(&mut c8, 0x300);
+
+    assert_eq!(run_at(&mut c8, 0x200), 7);
+}
+
+#[test]
+fn bcd() {
+    // LD B, V2 writes 2, 5, 5 from 0x201, making it LD V1, 2.
+    let mut c8 = cached(builder().memory(0x300, &[0xF2, 0x33]).i(0x201).v(0x2, 255));
+    run_at(&mut c8, 0x300);
+
+    assert_eq!(run_at(&mut c8, 0x200), 2);
+}
+
+#[test]
+fn save_register_range() {
+    // SAVE V3 - V4 writes LD V1, 9 over the cached instruction.
+    let mut c8 = cached(
+        builder()
+            .xo_chip()
+            .memory(0x300, &[0x53, 0x42])
+            .i(0x200)
+            .v(0x3, 0x61)
+            .v(0x4, 0x09),
+    );
+    run_at(&mut c8, 0x300);
+
+    assert_eq!(run_at(&mut c8, 0x200), 9);
+}
+
+#[test]
+fn load_state() {
+    let mut c8 = cached(builder());
+    let state = c8.save_state();
+
+    c8.write(0x201, 0x09).unwrap();
+    assert_eq!(run_at(&mut c8, 0x200), 9);
+
+    c8.load_state(&state).unwrap();
+    assert_eq!(run_at(&mut c8, 0x200), 1);
+}
+
+#[test]
+fn load_rom() {
+    let mut c8 = cached(builder());
+    c8.load_rom(&[0x61, 0x03]).unwrap();
+    assert_eq!(run_at(&mut c8, 0x200), 3);
+}
+
+#[test]
+fn disabled() {
+    let mut c8 = cached(builder());
+    c8.set_instruction_cache(false);
+    assert!(!c8.instruction_cache());
+
+    c8.write(0x201, 0x04).unwrap();
+    assert_eq!(run_at(&mut c8, 0x200), 4);
+}
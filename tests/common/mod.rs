@@ -0,0 +1,9 @@
+//! Fixtures shared by the integration tests.
+
+use rs_chip8::{Chip8, Chip8Builder, Quirks};
+
+/// A builder with every quirk off: the tests of the instructions that depend
+/// on them turn on the ones they check, and nothing blocks or moves I.
+pub fn builder() -> Chip8Builder {
+    Chip8::builder().quirks(Quirks::none())
+}
@@ -0,0 +1,118 @@
+//! Tests of breakpoint conditions: their syntax, and their value in a given state.
+
+use rs_chip8::{Chip8, Condition};
+
+/// An emulator with a few registers and memory bytes set.
+fn emulator() -> Chip8 {
+    Chip8::builder()
+        .v(0x3, 0x10)
+        .v(0xF, 1)
+        .i(0x345)
+        .pc(0x230)
+        .stack(&[0x202, 0x20A])
+        .delay_timer(60)
+        .memory(0x300, &[0xAB, 0x05])
+        .build()
+}
+
+fn holds(condition: &str) -> bool {
+    let condition = Condition::parse(condition).unwrap_or_else(|err| panic!("{}", err));
+    condition.evaluate(&emulator())
+}
+
+fn error(condition: &str) -> String {
+    match Condition::parse(condition) {
+        Ok(condition) => panic!("parsed {:?}", condition),
+        Err(err) => err,
+    }
+}
+
+#[test]
+fn registers() {
+    for condition in [
+        "v3 == 0x10",
+        "V3 == 16",
+        "vf",
+        "vF == 1",
+        "v0 == 0",
+        "i == 0x345",
+        "PC == 0x230",
+        "sp == 2",
+        "dt == 60",
+        "st == 0",
+    ] {
+        assert!(holds(condition), "{}", condition);
+    }
+}
+
+#[test]
+fn numbers() {
+    assert!(holds("0x10 == 16 && 0b10000 == 16 && 0X1f == 31"));
+    assert!(holds("-1 < 0"));
+    assert!(!holds("0"));
+}
+
+#[test]
+fn memory() {
+    assert!(holds("[0x300] == 0xAB"));
+    assert!(holds("[i - 0x44] == 5"));
+    assert!(holds("[[0x301] + 0x2FB] == 0xAB"));
+    // Out of range reads are 0.
+    assert!(holds("[0x10000] == 0 && [-1] == 0"));
+}
+
+#[test]
+fn precedence() {
+    // Comparisons bind tighter than && and ||, and && tighter than ||.
+    assert!(holds("v3 == 0x10 && i > 0x300"));
+    assert!(holds("1 || 0 && 0"));
+    assert!(!holds("(1 || 0) && 0"));
+    // Arithmetic binds tighter than comparisons, left to right.
+    assert!(holds("v3 + 1 == 0x11"));
+    assert!(holds("10 - 3 - 2 == 5"));
+    assert!(holds("v3 | 1 & 3 == 1"));
+    // Unary operators bind tightest.
+    assert!(holds("!0 + 1 == 2"));
+    assert!(holds("--1 == 1"));
+    assert!(holds("!(v3 == 0x11)"));
+    // & and | aren't && and ||.
+    assert!(!holds("2 & 1"));
+    assert!(holds("2 && 1"));
+}
+
+#[test]
+fn comparisons() {
+    assert!(holds("1 < 2 && 2 <= 2 && 3 > 2 && 2 >= 2 && 1 != 2"));
+    assert!(!holds("2 < 2"));
+    assert!(!holds("1 >= 2"));
+}
+
+#[test]
+fn display() {
+    let condition = Condition::parse("  v3 == 0x10 && [i]  ").unwrap();
+    assert_eq!(condition.to_string(), "v3 == 0x10 && [i]");
+}
+
+#[test]
+fn errors() {
+    let cases = [
+        ("", "missing value in condition"),
+        ("v3 ==", "missing value in condition"),
+        ("vg == 1", "unknown register 'vg'"),
+        ("v10 == 1", "unknown register 'v10'"),
+        ("foo", "unknown register 'foo'"),
+        ("0x1G", "invalid number '0x1G'"),
+        ("12ab", "invalid number '12ab'"),
+        ("(v3 == 1", "missing ')' in condition"),
+        ("[0x300 == 1", "missing ']' in condition"),
+        ("v3 == 1)", "unexpected ')' in condition"),
+        ("v3 = 1", "unexpected '=' in condition"),
+        ("v3 === 1", "unexpected '=' in condition"),
+        ("v3 == 1 v4", "unexpected 'v' in condition"),
+        ("v3 @ 1", "unexpected '@' in condition"),
+    ];
+
+    for (condition, message) in cases {
+        assert_eq!(error(condition), message, "{:?}", condition);
+    }
+}
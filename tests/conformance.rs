@@ -0,0 +1,312 @@
+//! Conformance tests: runs test ROMs through a headless `Chip8` and compares the
+//! screen they leave with golden images.
+//!
+//! Two of the ROMs are community ones: the IBM logo, vendored in `tests/roms`,
+//! and the delay timer test in `games`. The community opcode, flags, quirks and
+//! keypad test ROMs aren't vendored in this repository, so the other ROMs in
+//! `tests/roms` are hand-written approximations of them: they check the same
+//! behaviours, are written for our assembler and are assembled by the tests.
+//! Each check one of these ROMs makes stores its result in memory at `RESULTS`
+//! and draws a mark in a grid, 8 per row: a tick for a pass (or a quirk that's
+//! on), a cross otherwise. The tests print the result of every check, and fail
+//! if any of them doesn't give the expected result.
+//!
+//! The golden images in `tests/golden` are plain PBM files, with one line of
+//! `0` and `1` per row of the screen. Run the tests with `UPDATE_GOLDEN=1` to
+//! rewrite them after an intended change.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+use rs_chip8::{assemble_file, Chip8, Instruction, KeyEvent, Quirks};
+
+/// Address of the results of the checks, see `tests/roms/report.s`.
+const RESULTS: usize = 0xE00;
+
+const CYCLES_PER_FRAME: u32 = 20;
+
+/// Frames after which a ROM that hasn't finished is considered stuck.
+const MAX_FRAMES: u32 = 600;
+
+fn path(dir: &str, file: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join(dir)
+        .join(file)
+}
+
+/// Returns whether the emulator is in the `done: JP done` loop ending a ROM.
+fn finished(emulator: &Chip8) -> bool {
+    let pc = emulator.pc();
+    let opcode =
+        (emulator.memory()[pc as usize] as u16) << 8 | emulator.memory()[pc as usize + 1] as u16;
+    matches!(Instruction::decode(opcode), Ok(Instruction::Jump(addr)) if addr == pc)
+}
+
+/// Assembles and runs `tests/roms/<rom>.s` until it finishes, see `run_program`.
+fn run(rom: &str, emulator: Chip8, input: &[(u32, KeyEvent)]) -> Chip8 {
+    let program = assemble_file(&path("tests/roms", &format!("{}.s", rom)))
+        .unwrap_or_else(|err| panic!("{}", err));
+    run_program(rom, &program, emulator, input)
+}
+
+/// Runs `program` until it finishes, in a `done: JP done` loop or waiting for a
+/// key once all the `input` was sent, sending each key event before the frame
+/// it's paired with.
+fn run_program(rom: &str, program: &[u8], mut emulator: Chip8, input: &[(u32, KeyEvent)]) -> Chip8 {
+    emulator.load_rom(program).unwrap();
+
+    for frame in 0..MAX_FRAMES {
+        let input_sent = input.iter().all(|(at, _)| *at < frame);
+        if finished(&emulator) || (input_sent && emulator.waiting_for_key().is_some()) {
+            return emulator;
+        }
+
+        for (_, event) in input.iter().filter(|(at, _)| *at == frame) {
+            emulator.handle_input(*event);
+        }
+
+        emulator
+            .run_frame(CYCLES_PER_FRAME)
+            .unwrap_or_else(|err| panic!("{} halted during frame {}: {}", rom, frame + 1, err));
+    }
+
+    panic!("{} didn't finish in {} frames", rom, MAX_FRAMES);
+}
+
+/// Presses and releases each of `keys` in turn, a few frames apart.
+fn presses(keys: &[usize]) -> Vec<(u32, KeyEvent)> {
+    let mut input = Vec::new();
+    for (n, &key) in keys.iter().enumerate() {
+        let frame = 10 * n as u32 + 5;
+        input.push((frame, KeyEvent::Down(key)));
+        input.push((frame + 3, KeyEvent::Up(key)));
+    }
+    input
+}
+
+/// Compares the result of each of the `checks` of a ROM with the expected one
+/// (1 for a pass), printing them all. Panics with the report if any differs.
+fn check_results(rom: &str, emulator: &Chip8, checks: &[(&str, u8)]) {
+    let results = &emulator.memory()[RESULTS..RESULTS + checks.len()];
+    let mut report = String::new();
+    let mut failed = false;
+
+    for (&(name, expected), &result) in checks.iter().zip(results) {
+        let status = if result == expected {
+            "ok"
+        } else {
+            failed = true;
+            "FAILED"
+        };
+        let _ = writeln!(
+            report,
+            "{} {:<24} {} (expected {})",
+            rom, name, status, expected
+        );
+    }
+
+    print!("{}", report);
+    assert!(!failed, "some checks of {} failed:\n{}", rom, report);
+}
+
+/// Formats the screen as a plain PBM image.
+fn pbm(emulator: &Chip8) -> String {
+    let (width, height) = emulator.display_size();
+    let mut image = format!("P1\n{} {}\n", width, height);
+
+    for row in emulator.get_framebuffer().chunks(width).take(height) {
+        let line: String = row
+            .iter()
+            .map(|&pixel| if pixel != 0 { '1' } else { '0' })
+            .collect();
+        image.push_str(&line);
+        image.push('\n');
+    }
+
+    image
+}
+
+/// Compares the screen with `tests/golden/<name>.pbm`, or rewrites it when the
+/// `UPDATE_GOLDEN` environment variable is set.
+fn check_screen(name: &str, emulator: &Chip8) {
+    let path = path("tests/golden", &format!("{}.pbm", name));
+    let actual = pbm(emulator);
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("cannot read {}: {}", path.display(), err));
+
+    if expected != actual {
+        let screen: String = actual
+            .lines()
+            .skip(2)
+            .map(|line| line.replace('0', ".").replace('1', "#") + "\n")
+            .collect();
+        panic!(
+            "the screen differs from {}, it shows:\n{}",
+            path.display(),
+            screen
+        );
+    }
+}
+
+#[test]
+fn logo() {
+    let emulator = run("logo", Chip8::new(), &[]);
+    check_screen("logo", &emulator);
+}
+
+/// The IBM logo ROM, as distributed with most emulators.
+#[test]
+fn ibm_logo() {
+    let program = fs::read(path("tests/roms", "ibm_logo.ch8")).unwrap();
+    let emulator = run_program("ibm_logo", &program, Chip8::new(), &[]);
+    check_screen("ibm_logo", &emulator);
+}
+
+/// The delay timer test: 2 and 8 increment and decrement a value it shows, and
+/// 5 loads it in the delay timer, whose value it shows until it reaches 0.
+#[test]
+fn delay_timer_test() {
+    let program = fs::read(path("games", "delay_timer_test.ch8")).unwrap();
+
+    let input = presses(&[0x2, 0x2, 0x2, 0x2, 0x2, 0x8]);
+    let emulator = run_program("delay_timer_test", &program, Chip8::new(), &input);
+    assert_eq!(emulator.v(0x6), 4);
+    check_screen("delay_timer_test", &emulator);
+
+    let input = presses(&[0x2, 0x2, 0x2, 0x5]);
+    let emulator = run_program("delay_timer_test", &program, Chip8::new(), &input);
+    assert_eq!(emulator.v(0x6), 0);
+    assert_eq!(emulator.delay_timer(), 0);
+    check_screen("delay_timer_test_done", &emulator);
+}
+
+#[test]
+fn opcodes() {
+    let emulator = run("opcodes", Chip8::new(), &[]);
+
+    let checks = [
+        "00E0 CLS",
+        "1NNN JP",
+        "3XNN SE",
+        "4XNN SNE",
+        "5XY0 SE",
+        "9XY0 SNE",
+        "6XNN LD",
+        "7XNN ADD",
+        "8XY0 LD",
+        "8XY1 OR",
+        "8XY2 AND",
+        "8XY3 XOR",
+        "8XY4 ADD",
+        "8XY5 SUB",
+        "8XY6 SHR",
+        "8XY7 SUBN",
+        "8XYE SHL",
+        "ANNN/FX1E LD I, ADD I",
+        "BNNN JP V0",
+        "2NNN/00EE CALL, RET",
+        "FX33 LD B",
+        "FX55/FX65 LD [I]",
+        "FX15/FX07 LD DT",
+        "CXNN RND",
+        "DXYN DRW",
+        "FX29 LD F",
+    ];
+    let checks: Vec<(&str, u8)> = checks.iter().map(|&name| (name, 1)).collect();
+
+    check_results("opcodes", &emulator, &checks);
+    check_screen("opcodes", &emulator);
+}
+
+#[test]
+fn flags() {
+    let emulator = run("flags", Chip8::new(), &[]);
+
+    let checks = [
+        "8XY4 carry",
+        "8XY4 no carry",
+        "8XY5 no borrow",
+        "8XY5 borrow",
+        "8XY5 equal",
+        "8XY7 no borrow",
+        "8XY7 borrow",
+        "8XY6 low bit",
+        "8XYE high bit",
+        "8XY4 VF as VX",
+        "8XY4 VF as VY",
+        "8XY5 VF as VX",
+        "8XY7 VF as VX",
+        "8XY6 VF as VX",
+        "8XYE VF as VX",
+    ];
+    let checks: Vec<(&str, u8)> = checks.iter().map(|&name| (name, 1)).collect();
+
+    check_results("flags", &emulator, &checks);
+    check_screen("flags", &emulator);
+}
+
+/// Runs the quirks ROM and checks that it detects the `quirks` of `emulator`.
+fn check_quirks(preset: &str, emulator: Chip8, quirks: Quirks) {
+    let emulator = run("quirks", emulator, &[]);
+
+    let checks = [
+        ("vF reset", quirks.logic_resets_vf as u8),
+        ("memory", quirks.load_store_increments_i as u8),
+        ("display wait", quirks.display_wait as u8),
+        ("clipping", quirks.clip_sprites as u8),
+        ("shifting", quirks.shift_uses_vy as u8),
+        ("jumping", quirks.jump_uses_vx as u8),
+    ];
+
+    check_results("quirks", &emulator, &checks);
+    check_screen(&format!("quirks_{}", preset), &emulator);
+}
+
+#[test]
+fn quirks_vip() {
+    check_quirks("vip", Chip8::with_quirks(Quirks::vip()), Quirks::vip());
+}
+
+#[test]
+fn quirks_schip() {
+    check_quirks(
+        "schip",
+        Chip8::with_quirks(Quirks::schip()),
+        Quirks::schip(),
+    );
+}
+
+#[test]
+fn quirks_xochip() {
+    check_quirks("xochip", Chip8::xo_chip(Quirks::xochip()), Quirks::xochip());
+}
+
+#[test]
+fn keypad() {
+    let input = [
+        (5, KeyEvent::Down(0x5)),
+        (8, KeyEvent::Up(0x5)),
+        (20, KeyEvent::Down(0xA)),
+        (30, KeyEvent::Up(0xA)),
+    ];
+    let emulator = run("keypad", Chip8::new(), &input);
+
+    let checks = [
+        ("FX0A LD K", 1),
+        ("EX9E not pressed", 1),
+        ("EX9E pressed", 1),
+        ("EXA1 pressed", 1),
+        ("EXA1 released", 1),
+    ];
+
+    check_results("keypad", &emulator, &checks);
+    check_screen("keypad", &emulator);
+}
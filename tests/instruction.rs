@@ -0,0 +1,139 @@
+//! Tests of instruction decoding, encoding and disassembly.
+
+use rs_chip8::{Instruction, UnknownOpcode};
+
+use Instruction::*;
+
+/// An opcode of each instruction, with the instruction and its text.
+const INSTRUCTIONS: &[(u16, Instruction, &str)] = &[
+    (0x00C5, ScrollDown(5), "SCD 5"),
+    (0x00DA, ScrollUp(10), "SCU 10"),
+    (0x00E0, ClearScreen, "CLS"),
+    (0x00EE, Return, "RET"),
+    (0x00FB, ScrollRight, "SCR"),
+    (0x00FC, ScrollLeft, "SCL"),
+    (0x00FD, Exit, "EXIT"),
+    (0x00FE, Lores, "LOW"),
+    (0x00FF, Hires, "HIGH"),
+    (0x1234, Jump(0x234), "JP 0x234"),
+    (0x2FFE, Call(0xFFE), "CALL 0xFFE"),
+    (0x3A42, SkipEqImm { x: 0xA, nn: 0x42 }, "SE VA, 0x42"),
+    (0x4B07, SkipNeImm { x: 0xB, nn: 0x07 }, "SNE VB, 0x07"),
+    (0x5120, SkipEqReg { x: 1, y: 2 }, "SE V1, V2"),
+    (0x5342, SaveRange { x: 3, y: 4 }, "SAVE V3, V4"),
+    (0x5563, LoadRange { x: 5, y: 6 }, "LOAD V5, V6"),
+    (0x6CFF, SetImm { x: 0xC, nn: 0xFF }, "LD VC, 0xFF"),
+    (0x7D01, AddImm { x: 0xD, nn: 0x01 }, "ADD VD, 0x01"),
+    (0x8010, Assign { x: 0, y: 1 }, "LD V0, V1"),
+    (0x8231, Or { x: 2, y: 3 }, "OR V2, V3"),
+    (0x8452, And { x: 4, y: 5 }, "AND V4, V5"),
+    (0x8673, Xor { x: 6, y: 7 }, "XOR V6, V7"),
+    (0x8894, Add { x: 8, y: 9 }, "ADD V8, V9"),
+    (0x8AB5, Sub { x: 0xA, y: 0xB }, "SUB VA, VB"),
+    (0x8CD6, ShiftRight { x: 0xC, y: 0xD }, "SHR VC, VD"),
+    (0x8EF7, SubN { x: 0xE, y: 0xF }, "SUBN VE, VF"),
+    (0x8F0E, ShiftLeft { x: 0xF, y: 0 }, "SHL VF, V0"),
+    (0x9120, SkipNeReg { x: 1, y: 2 }, "SNE V1, V2"),
+    (0xA2EA, SetI(0x2EA), "LD I, 0x2EA"),
+    (0xB300, JumpOffset(0x300), "JP V0, 0x300"),
+    (0xC30F, Random { x: 3, nn: 0x0F }, "RND V3, 0x0F"),
+    (
+        0xDAB6,
+        Draw {
+            x: 0xA,
+            y: 0xB,
+            n: 6,
+        },
+        "DRW VA, VB, 6",
+    ),
+    (0xD120, Draw { x: 1, y: 2, n: 0 }, "DRW V1, V2, 0"),
+    (0xE59E, SkipKeyPressed { x: 5 }, "SKP V5"),
+    (0xE6A1, SkipKeyNotPressed { x: 6 }, "SKNP V6"),
+    (0xF000, LoadILong, "LD I, LONG"),
+    (0xF201, SelectPlane(2), "PLANE 2"),
+    (0xF002, LoadAudio, "AUDIO"),
+    (0xF107, GetDelay { x: 1 }, "LD V1, DT"),
+    (0xF20A, WaitKey { x: 2 }, "LD V2, K"),
+    (0xF315, SetDelay { x: 3 }, "LD DT, V3"),
+    (0xF418, SetSound { x: 4 }, "LD ST, V4"),
+    (0xF51E, AddI { x: 5 }, "ADD I, V5"),
+    (0xF629, Font { x: 6 }, "LD F, V6"),
+    (0xF730, BigFont { x: 7 }, "LD HF, V7"),
+    (0xF833, Bcd { x: 8 }, "LD B, V8"),
+    (0xF93A, Pitch { x: 9 }, "PITCH V9"),
+    (0xFA55, Store { x: 0xA }, "LD [I], VA"),
+    (0xFB65, Load { x: 0xB }, "LD VB, [I]"),
+    (0xFC75, SaveFlags { x: 0xC }, "LD R, VC"),
+    (0xFD85, LoadFlags { x: 0xD }, "LD VD, R"),
+];
+
+#[test]
+fn every_instruction() {
+    for &(opcode, instruction, text) in INSTRUCTIONS {
+        assert_eq!(
+            Instruction::decode(opcode),
+            Ok(instruction),
+            "{:04X}",
+            opcode
+        );
+        assert_eq!(instruction.encode(), opcode, "{}", text);
+        assert_eq!(instruction.to_string(), text, "{:04X}", opcode);
+    }
+}
+
+/// Every opcode that decodes encodes back to itself.
+#[test]
+fn round_trip() {
+    let mut known = 0;
+
+    for opcode in 0..=u16::MAX {
+        if let Ok(instruction) = Instruction::decode(opcode) {
+            assert_eq!(instruction.encode(), opcode, "{}", instruction);
+            known += 1;
+        }
+    }
+
+    // 00CN, 00DN and the 7 other 00NN; 1NNN to 4NNN; 5XY0, 5XY2 and 5XY3; 6XNN and
+    // 7XNN; the 9 kinds of 8XYN; 9XY0; ANNN to DXYN; EX9E and EXA1; F000, F002 and
+    // the 14 kinds of FXNN.
+    let expected = 32
+        + 7
+        + 4 * 0x1000
+        + 3 * 0x100
+        + 2 * 0x1000
+        + 9 * 0x100
+        + 0x100
+        + 4 * 0x1000
+        + 2 * 0x10
+        + 2
+        + 14 * 0x10;
+    assert_eq!(known, expected);
+}
+
+#[test]
+fn unknown_opcodes() {
+    for opcode in [
+        0x0000, 0x0123, 0x00E1, 0x00EF, 0x00FA, 0x5121, 0x512F, 0x8128, 0x812F, 0x9121, 0xE19F,
+        0xE1A2, 0xF100, 0xF102, 0xF1FF, 0xF0FF,
+    ] {
+        assert_eq!(
+            Instruction::decode(opcode),
+            Err(UnknownOpcode(opcode)),
+            "{:04X}",
+            opcode
+        );
+    }
+
+    assert_eq!(UnknownOpcode(0x8128).to_string(), "unknown opcode $8128");
+}
+
+#[test]
+fn size_and_skips() {
+    for &(opcode, instruction, text) in INSTRUCTIONS {
+        let expected_size = if opcode == 0xF000 { 4 } else { 2 };
+        assert_eq!(instruction.size(), expected_size, "{}", text);
+
+        let skips = text.starts_with("SE ") || text.starts_with("SNE ") || text.starts_with("SK");
+        assert_eq!(instruction.is_skip(), skips, "{}", text);
+    }
+}
@@ -0,0 +1,1028 @@
+//! Unit tests for every instruction: each one builds an emulator in a given
+//! state with `Chip8::builder`, executes a single opcode and checks the state
+//! it leaves, including the flags and the cases where X or Y is F.
+
+mod common;
+
+use rs_chip8::{Chip8, Chip8Error, KeyEvent, Quirks};
+
+use common::builder;
+
+/// Writes `opcode` at the PC and executes it, panicking if it fails.
+fn exec(c8: &mut Chip8, opcode: u16) {
+    write_opcode(c8, opcode);
+    c8.step()
+        .unwrap_or_else(|err| panic!("{:04X} failed: {}", opcode, err));
+}
+
+/// Writes `opcode` at the PC and executes it, returning the error it fails with.
+fn exec_err(c8: &mut Chip8, opcode: u16) -> Chip8Error {
+    write_opcode(c8, opcode);
+    match c8.step() {
+        Ok(()) => panic!("{:04X} didn't fail", opcode),
+        Err(err) => err,
+    }
+}
+
+fn write_opcode(c8: &mut Chip8, opcode: u16) {
+    let pc = c8.pc();
+    c8.write(pc, (opcode >> 8) as u8).unwrap();
+    c8.write(pc + 1, opcode as u8).unwrap();
+}
+
+/// Checks the registers `expected` as (X, value) pairs.
+fn assert_v(c8: &Chip8, expected: &[(usize, u8)]) {
+    for &(x, value) in expected {
+        assert_eq!(
+            c8.v(x),
+            value,
+            "V{:X} is 0x{:02X} instead of 0x{:02X}",
+            x,
+            c8.v(x),
+            value
+        );
+    }
+}
+
+fn assert_pc(c8: &Chip8, pc: u16) {
+    assert_eq!(
+        c8.pc(),
+        pc,
+        "PC is 0x{:03X} instead of 0x{:03X}",
+        c8.pc(),
+        pc
+    );
+}
+
+/// Checks the memory bytes starting at `addr`.
+fn assert_memory(c8: &Chip8, addr: u16, expected: &[u8]) {
+    let start = addr as usize;
+    assert_eq!(
+        &c8.memory()[start..start + expected.len()],
+        expected,
+        "memory at 0x{:03X}",
+        addr
+    );
+}
+
+/// Returns the value of the pixel at (x, y): a bitmask of the planes it is set on.
+fn pixel(c8: &Chip8, x: usize, y: usize) -> u8 {
+    let (width, _) = c8.display_size();
+    c8.get_framebuffer()[y * width + x]
+}
+
+/// Returns the number of pixels set on any plane.
+fn lit_pixels(c8: &Chip8) -> usize {
+    c8.get_framebuffer()
+        .iter()
+        .filter(|&&pixel| pixel != 0)
+        .count()
+}
+
+// 00E0
+
+#[test]
+fn clear_screen() {
+    let mut c8 = builder().i(0).build();
+    exec(&mut c8, 0xD005);
+    assert_ne!(lit_pixels(&c8), 0);
+
+    exec(&mut c8, 0x00E0);
+    assert_eq!(lit_pixels(&c8), 0);
+    assert_pc(&c8, 0x204);
+}
+
+#[test]
+fn clear_screen_only_clears_the_selected_planes() {
+    let mut c8 = builder().xo_chip().build();
+    exec(&mut c8, 0xF301); // both planes
+    exec(&mut c8, 0xD001);
+    exec(&mut c8, 0xF101);
+    exec(&mut c8, 0x00E0);
+    assert_eq!(pixel(&c8, 0, 0), 2);
+}
+
+// 00EE
+
+#[test]
+fn return_from_subroutine() {
+    let mut c8 = builder().pc(0x400).stack(&[0x300, 0x250]).build();
+    exec(&mut c8, 0x00EE);
+    assert_pc(&c8, 0x252);
+    assert_eq!(c8.stack(), &[0x300]);
+}
+
+#[test]
+fn return_with_an_empty_stack() {
+    let mut c8 = builder().build();
+    assert_eq!(
+        exec_err(&mut c8, 0x00EE),
+        Chip8Error::StackUnderflow { addr: 0x200 }
+    );
+}
+
+// 00CN, 00DN, 00FB and 00FC
+
+#[test]
+fn scroll_down() {
+    let mut c8 = builder().v(0x1, 3).build();
+    exec(&mut c8, 0xD101); // the top row of the 0 digit at (3, 0)
+    exec(&mut c8, 0x00C2);
+    assert_eq!(pixel(&c8, 3, 0), 0);
+    assert_eq!(pixel(&c8, 3, 2), 1);
+}
+
+#[test]
+fn scroll_up() {
+    let mut c8 = builder().xo_chip().v(0x2, 5).build();
+    exec(&mut c8, 0xD021);
+    exec(&mut c8, 0x00D3);
+    assert_eq!(pixel(&c8, 0, 5), 0);
+    assert_eq!(pixel(&c8, 0, 2), 1);
+}
+
+#[test]
+fn scroll_right_and_left() {
+    let mut c8 = builder().build();
+    exec(&mut c8, 0xD001);
+    exec(&mut c8, 0x00FB);
+    assert_eq!(pixel(&c8, 0, 0), 0);
+    assert_eq!(pixel(&c8, 4, 0), 1);
+
+    exec(&mut c8, 0x00FC);
+    assert_eq!(pixel(&c8, 0, 0), 1);
+    assert_eq!(lit_pixels(&c8), 4);
+
+    exec(&mut c8, 0x00FC);
+    assert_eq!(lit_pixels(&c8), 0, "pixels scrolled past the edge are lost");
+}
+
+// 00FD, 00FE and 00FF
+
+#[test]
+fn exit() {
+    let mut c8 = builder().build();
+    exec(&mut c8, 0x00FD);
+    assert!(c8.exited);
+
+    c8.step().unwrap();
+    assert_pc(&c8, 0x200);
+}
+
+#[test]
+fn hires_and_lores() {
+    let mut c8 = builder().build();
+    exec(&mut c8, 0x00FF);
+    assert_eq!(c8.display_size(), (128, 64));
+
+    exec(&mut c8, 0xD005);
+    exec(&mut c8, 0x00FE);
+    assert_eq!(c8.display_size(), (64, 32));
+    assert_eq!(lit_pixels(&c8), 0, "switching modes clears the screen");
+}
+
+// 1NNN and 2NNN
+
+#[test]
+fn jump() {
+    let mut c8 = builder().build();
+    exec(&mut c8, 0x1ABC);
+    assert_pc(&c8, 0xABC);
+}
+
+#[test]
+fn call() {
+    let mut c8 = builder().pc(0x208).stack(&[0x300]).build();
+    exec(&mut c8, 0x2ABC);
+    assert_pc(&c8, 0xABC);
+    assert_eq!(c8.stack(), &[0x300, 0x208]);
+}
+
+#[test]
+fn call_with_a_full_stack() {
+    let mut c8 = builder().stack(&[0x300; 16]).build();
+    assert_eq!(
+        exec_err(&mut c8, 0x2ABC),
+        Chip8Error::StackOverflow { addr: 0x200 }
+    );
+}
+
+// 3XNN, 4XNN, 5XY0 and 9XY0
+
+#[test]
+fn skip_if_equal_to_immediate() {
+    let mut c8 = builder().v(0x3, 0x42).build();
+    exec(&mut c8, 0x3342);
+    assert_pc(&c8, 0x204);
+
+    exec(&mut c8, 0x3343);
+    assert_pc(&c8, 0x206);
+}
+
+#[test]
+fn skip_if_not_equal_to_immediate() {
+    let mut c8 = builder().v(0x3, 0x42).build();
+    exec(&mut c8, 0x4343);
+    assert_pc(&c8, 0x204);
+
+    exec(&mut c8, 0x4342);
+    assert_pc(&c8, 0x206);
+}
+
+#[test]
+fn skip_if_registers_equal() {
+    let mut c8 = builder().v(0x1, 7).v(0x2, 7).v(0x3, 8).build();
+    exec(&mut c8, 0x5120);
+    assert_pc(&c8, 0x204);
+
+    exec(&mut c8, 0x5130);
+    assert_pc(&c8, 0x206);
+}
+
+#[test]
+fn skip_if_registers_not_equal() {
+    let mut c8 = builder().v(0x1, 7).v(0x2, 7).v(0x3, 8).build();
+    exec(&mut c8, 0x9130);
+    assert_pc(&c8, 0x204);
+
+    exec(&mut c8, 0x9120);
+    assert_pc(&c8, 0x206);
+}
+
+#[test]
+fn skip_over_a_long_load() {
+    let mut c8 = builder()
+        .xo_chip()
+        .memory(0x202, &[0xF0, 0x00, 0x12, 0x34])
+        .build();
+    exec(&mut c8, 0x3000);
+    assert_pc(&c8, 0x206);
+}
+
+// 5XY2 and 5XY3
+
+#[test]
+fn save_register_range() {
+    let mut c8 = builder()
+        .xo_chip()
+        .v(0x2, 0xA2)
+        .v(0x3, 0xA3)
+        .v(0x4, 0xA4)
+        .i(0x400)
+        .build();
+    exec(&mut c8, 0x5242);
+    assert_memory(&c8, 0x400, &[0xA2, 0xA3, 0xA4]);
+    assert_eq!(c8.i(), 0x400);
+
+    exec(&mut c8, 0x5422);
+    assert_memory(&c8, 0x400, &[0xA4, 0xA3, 0xA2]);
+}
+
+#[test]
+fn load_register_range() {
+    let mut c8 = builder()
+        .xo_chip()
+        .memory(0x400, &[0xB1, 0xB2])
+        .i(0x400)
+        .build();
+    exec(&mut c8, 0x5EF3);
+    assert_v(&c8, &[(0xE, 0xB1), (0xF, 0xB2)]);
+
+    exec(&mut c8, 0x5FE3);
+    assert_v(&c8, &[(0xF, 0xB1), (0xE, 0xB2)]);
+    assert_eq!(c8.i(), 0x400);
+}
+
+// 6XNN and 7XNN
+
+#[test]
+fn set_immediate() {
+    let mut c8 = builder().build();
+    exec(&mut c8, 0x6A42);
+    assert_v(&c8, &[(0xA, 0x42)]);
+    assert_pc(&c8, 0x202);
+}
+
+#[test]
+fn add_immediate_wraps_without_carry() {
+    let mut c8 = builder().v(0x1, 0xFF).v(0xF, 7).build();
+    exec(&mut c8, 0x7102);
+    assert_v(&c8, &[(0x1, 0x01), (0xF, 7)]);
+}
+
+#[test]
+fn add_immediate_to_vf() {
+    let mut c8 = builder().v(0xF, 0xFF).build();
+    exec(&mut c8, 0x7F02);
+    assert_v(&c8, &[(0xF, 0x01)]);
+}
+
+// 8XY0 to 8XY3
+
+#[test]
+fn assign() {
+    let mut c8 = builder().v(0x2, 0x33).build();
+    exec(&mut c8, 0x8120);
+    assert_v(&c8, &[(0x1, 0x33), (0x2, 0x33)]);
+}
+
+#[test]
+fn logic_operations() {
+    for (opcode, expected) in [(0x8121, 0x3F), (0x8122, 0x0C), (0x8123, 0x33)] {
+        let mut c8 = builder().v(0x1, 0x3C).v(0x2, 0x0F).v(0xF, 5).build();
+        exec(&mut c8, opcode);
+        assert_v(&c8, &[(0x1, expected), (0x2, 0x0F), (0xF, 5)]);
+    }
+}
+
+#[test]
+fn logic_operations_reset_vf() {
+    let quirks = Quirks {
+        logic_resets_vf: true,
+        ..Quirks::none()
+    };
+
+    for opcode in [0x8121, 0x8122, 0x8123] {
+        let mut c8 = builder()
+            .quirks(quirks)
+            .v(0x1, 0x3C)
+            .v(0x2, 0x0F)
+            .v(0xF, 5)
+            .build();
+        exec(&mut c8, opcode);
+        assert_v(&c8, &[(0xF, 0)]);
+    }
+
+    // The flag overwrites the result when X is F.
+    let mut c8 = builder().quirks(quirks).v(0xF, 0x30).v(0x1, 0x03).build();
+    exec(&mut c8, 0x8F11);
+    assert_v(&c8, &[(0xF, 0)]);
+}
+
+// 8XY4
+
+#[test]
+fn add_without_carry() {
+    let mut c8 = builder().v(0x1, 0x12).v(0x2, 0x34).v(0xF, 5).build();
+    exec(&mut c8, 0x8124);
+    assert_v(&c8, &[(0x1, 0x46), (0xF, 0)]);
+}
+
+#[test]
+fn add_with_carry() {
+    let mut c8 = builder().v(0x1, 0xF0).v(0x2, 0x20).build();
+    exec(&mut c8, 0x8124);
+    assert_v(&c8, &[(0x1, 0x10), (0xF, 1)]);
+}
+
+#[test]
+fn add_up_to_the_limit() {
+    let mut c8 = builder().v(0x1, 0xFE).v(0x2, 0x01).build();
+    exec(&mut c8, 0x8124);
+    assert_v(&c8, &[(0x1, 0xFF), (0xF, 0)]);
+
+    let mut c8 = builder().v(0x1, 0xFF).v(0x2, 0x01).build();
+    exec(&mut c8, 0x8124);
+    assert_v(&c8, &[(0x1, 0x00), (0xF, 1)]);
+}
+
+#[test]
+fn add_to_itself() {
+    let mut c8 = builder().v(0x1, 0x90).build();
+    exec(&mut c8, 0x8114);
+    assert_v(&c8, &[(0x1, 0x20), (0xF, 1)]);
+}
+
+#[test]
+fn add_to_vf() {
+    // The carry overwrites the sum.
+    let mut c8 = builder().v(0xF, 0xF0).v(0x1, 0x20).build();
+    exec(&mut c8, 0x8F14);
+    assert_v(&c8, &[(0xF, 1)]);
+
+    let mut c8 = builder().v(0xF, 0x10).v(0x1, 0x20).build();
+    exec(&mut c8, 0x8F14);
+    assert_v(&c8, &[(0xF, 0)]);
+}
+
+#[test]
+fn add_vf() {
+    let mut c8 = builder().v(0x1, 0xF0).v(0xF, 0x20).build();
+    exec(&mut c8, 0x81F4);
+    assert_v(&c8, &[(0x1, 0x10), (0xF, 1)]);
+}
+
+// 8XY5 and 8XY7
+
+#[test]
+fn sub_without_borrow() {
+    let mut c8 = builder().v(0x1, 0x34).v(0x2, 0x12).build();
+    exec(&mut c8, 0x8125);
+    assert_v(&c8, &[(0x1, 0x22), (0xF, 1)]);
+}
+
+#[test]
+fn sub_with_borrow() {
+    let mut c8 = builder().v(0x1, 0x10).v(0x2, 0x30).v(0xF, 5).build();
+    exec(&mut c8, 0x8125);
+    assert_v(&c8, &[(0x1, 0xE0), (0xF, 0)]);
+}
+
+#[test]
+fn sub_equal_values() {
+    let mut c8 = builder().v(0x1, 0x10).v(0x2, 0x10).build();
+    exec(&mut c8, 0x8125);
+    assert_v(&c8, &[(0x1, 0x00), (0xF, 1)]);
+
+    let mut c8 = builder().v(0x1, 0x10).build();
+    exec(&mut c8, 0x8115);
+    assert_v(&c8, &[(0x1, 0x00), (0xF, 1)]);
+}
+
+#[test]
+fn sub_from_vf() {
+    let mut c8 = builder().v(0xF, 0x10).v(0x1, 0x30).build();
+    exec(&mut c8, 0x8F15);
+    assert_v(&c8, &[(0xF, 0)]);
+
+    let mut c8 = builder().v(0xF, 0x30).v(0x1, 0x10).build();
+    exec(&mut c8, 0x8F15);
+    assert_v(&c8, &[(0xF, 1)]);
+}
+
+#[test]
+fn sub_vf() {
+    let mut c8 = builder().v(0x1, 0x10).v(0xF, 0x30).build();
+    exec(&mut c8, 0x81F5);
+    assert_v(&c8, &[(0x1, 0xE0), (0xF, 0)]);
+}
+
+#[test]
+fn subn_without_borrow() {
+    let mut c8 = builder().v(0x1, 0x12).v(0x2, 0x34).build();
+    exec(&mut c8, 0x8127);
+    assert_v(&c8, &[(0x1, 0x22), (0x2, 0x34), (0xF, 1)]);
+}
+
+#[test]
+fn subn_with_borrow() {
+    let mut c8 = builder().v(0x1, 0x30).v(0x2, 0x10).v(0xF, 5).build();
+    exec(&mut c8, 0x8127);
+    assert_v(&c8, &[(0x1, 0xE0), (0xF, 0)]);
+}
+
+#[test]
+fn subn_from_vf() {
+    let mut c8 = builder().v(0xF, 0x10).v(0x1, 0x30).build();
+    exec(&mut c8, 0x8F17);
+    assert_v(&c8, &[(0xF, 1)]);
+}
+
+#[test]
+fn subn_vf() {
+    let mut c8 = builder().v(0x1, 0x30).v(0xF, 0x10).build();
+    exec(&mut c8, 0x81F7);
+    assert_v(&c8, &[(0x1, 0xE0), (0xF, 0)]);
+}
+
+// 8XY6 and 8XYE
+
+#[test]
+fn shift_right() {
+    let mut c8 = builder().v(0x1, 0x05).v(0x2, 0x80).build();
+    exec(&mut c8, 0x8126);
+    assert_v(&c8, &[(0x1, 0x02), (0x2, 0x80), (0xF, 1)]);
+
+    exec(&mut c8, 0x8126);
+    assert_v(&c8, &[(0x1, 0x01), (0xF, 0)]);
+}
+
+#[test]
+fn shift_right_uses_vy() {
+    let quirks = Quirks {
+        shift_uses_vy: true,
+        ..Quirks::none()
+    };
+    let mut c8 = builder().quirks(quirks).v(0x1, 0x05).v(0x2, 0x81).build();
+    exec(&mut c8, 0x8126);
+    assert_v(&c8, &[(0x1, 0x40), (0x2, 0x81), (0xF, 1)]);
+}
+
+#[test]
+fn shift_right_vf() {
+    // The shifted out bit overwrites the result.
+    let mut c8 = builder().v(0xF, 0x04).build();
+    exec(&mut c8, 0x8F06);
+    assert_v(&c8, &[(0xF, 0)]);
+
+    let mut c8 = builder().v(0xF, 0x03).build();
+    exec(&mut c8, 0x8F06);
+    assert_v(&c8, &[(0xF, 1)]);
+}
+
+#[test]
+fn shift_left() {
+    let mut c8 = builder().v(0x1, 0x81).build();
+    exec(&mut c8, 0x812E);
+    assert_v(&c8, &[(0x1, 0x02), (0xF, 1)]);
+
+    exec(&mut c8, 0x812E);
+    assert_v(&c8, &[(0x1, 0x04), (0xF, 0)]);
+}
+
+#[test]
+fn shift_left_uses_vy() {
+    let quirks = Quirks {
+        shift_uses_vy: true,
+        ..Quirks::none()
+    };
+    let mut c8 = builder().quirks(quirks).v(0x1, 0x01).v(0x2, 0xC0).build();
+    exec(&mut c8, 0x812E);
+    assert_v(&c8, &[(0x1, 0x80), (0x2, 0xC0), (0xF, 1)]);
+}
+
+#[test]
+fn shift_left_vf() {
+    let mut c8 = builder().v(0xF, 0x40).build();
+    exec(&mut c8, 0x8F0E);
+    assert_v(&c8, &[(0xF, 0)]);
+
+    let mut c8 = builder().v(0xF, 0x80).build();
+    exec(&mut c8, 0x8F0E);
+    assert_v(&c8, &[(0xF, 1)]);
+}
+
+// ANNN, BNNN and CXNN
+
+#[test]
+fn set_i() {
+    let mut c8 = builder().build();
+    exec(&mut c8, 0xA123);
+    assert_eq!(c8.i(), 0x123);
+}
+
+#[test]
+fn jump_with_offset() {
+    let mut c8 = builder().v(0x0, 0x10).v(0x3, 0x20).build();
+    exec(&mut c8, 0xB300);
+    assert_pc(&c8, 0x310);
+}
+
+#[test]
+fn jump_with_offset_uses_vx() {
+    let quirks = Quirks {
+        jump_uses_vx: true,
+        ..Quirks::none()
+    };
+    let mut c8 = builder().quirks(quirks).v(0x0, 0x10).v(0x3, 0x20).build();
+    exec(&mut c8, 0xB300);
+    assert_pc(&c8, 0x320);
+}
+
+#[test]
+fn random_is_masked() {
+    let mut c8 = builder().build();
+    exec(&mut c8, 0xC100);
+    assert_v(&c8, &[(0x1, 0)]);
+
+    for _ in 0..100 {
+        c8.set_pc(0x200);
+        exec(&mut c8, 0xC10F);
+        assert_eq!(c8.v(0x1) & 0xF0, 0);
+    }
+}
+
+// DXYN
+
+#[test]
+fn draw_sprite() {
+    let mut c8 = builder()
+        .v(0x1, 10)
+        .v(0x2, 5)
+        .i(0x300)
+        .memory(0x300, &[0b1010_0001])
+        .build();
+    exec(&mut c8, 0xD121);
+    assert_v(&c8, &[(0xF, 0)]);
+    assert_eq!(
+        [
+            pixel(&c8, 10, 5),
+            pixel(&c8, 11, 5),
+            pixel(&c8, 12, 5),
+            pixel(&c8, 17, 5)
+        ],
+        [1, 0, 1, 1]
+    );
+    assert_eq!(lit_pixels(&c8), 3);
+    assert!(c8.should_draw());
+}
+
+#[test]
+fn draw_collision_erases() {
+    let mut c8 = builder().v(0xF, 5).build();
+    exec(&mut c8, 0xD005);
+    assert_v(&c8, &[(0xF, 0)]);
+
+    exec(&mut c8, 0xD005);
+    assert_v(&c8, &[(0xF, 1)]);
+    assert_eq!(lit_pixels(&c8), 0);
+}
+
+#[test]
+fn draw_at_vf() {
+    // The position is read before VF is cleared.
+    let mut c8 = builder().v(0xF, 8).i(0x300).memory(0x300, &[0x80]).build();
+    exec(&mut c8, 0xDFF1);
+    assert_eq!(pixel(&c8, 8, 8), 1);
+    assert_v(&c8, &[(0xF, 0)]);
+}
+
+#[test]
+fn draw_wraps_the_start_position() {
+    let mut c8 = builder()
+        .v(0x1, 64 + 3)
+        .v(0x2, 32 + 2)
+        .i(0x300)
+        .memory(0x300, &[0x80])
+        .build();
+    exec(&mut c8, 0xD121);
+    assert_eq!(pixel(&c8, 3, 2), 1);
+}
+
+#[test]
+fn draw_clips_at_the_edges() {
+    let quirks = Quirks {
+        clip_sprites: true,
+        ..Quirks::none()
+    };
+    let mut c8 = builder()
+        .quirks(quirks)
+        .v(0x1, 62)
+        .v(0x2, 31)
+        .i(0x300)
+        .memory(0x300, &[0xFF, 0xFF])
+        .build();
+    exec(&mut c8, 0xD122);
+    assert_eq!(lit_pixels(&c8), 2);
+    assert_eq!(pixel(&c8, 0, 0), 0);
+}
+
+#[test]
+fn draw_wraps_at_the_edges() {
+    let mut c8 = builder()
+        .v(0x1, 62)
+        .v(0x2, 31)
+        .i(0x300)
+        .memory(0x300, &[0xC0, 0xC0])
+        .build();
+    exec(&mut c8, 0xD122);
+    assert_eq!(lit_pixels(&c8), 4);
+    assert_eq!(
+        [
+            pixel(&c8, 62, 31),
+            pixel(&c8, 63, 31),
+            pixel(&c8, 62, 0),
+            pixel(&c8, 63, 0)
+        ],
+        [1, 1, 1, 1]
+    );
+}
+
+#[test]
+fn draw_big_sprite_in_hires() {
+    let mut c8 = builder().i(0x300).memory(0x300, &[0xFF; 32]).build();
+    exec(&mut c8, 0x00FF);
+    exec(&mut c8, 0xD000);
+    assert_eq!(lit_pixels(&c8), 16 * 16);
+    assert_eq!(pixel(&c8, 15, 15), 1);
+}
+
+#[test]
+fn draw_on_both_planes() {
+    // The sprite of the second plane follows the one of the first.
+    let mut c8 = builder()
+        .xo_chip()
+        .i(0x300)
+        .memory(0x300, &[0x80, 0xC0])
+        .build();
+    exec(&mut c8, 0xF301);
+    exec(&mut c8, 0xD001);
+    assert_eq!([pixel(&c8, 0, 0), pixel(&c8, 1, 0)], [3, 2]);
+}
+
+#[test]
+fn draw_waits_for_vblank() {
+    let quirks = Quirks {
+        display_wait: true,
+        ..Quirks::none()
+    };
+    let mut c8 = builder().quirks(quirks).build();
+    exec(&mut c8, 0xD005);
+    assert!(c8.waiting_for_vblank());
+
+    c8.step().unwrap();
+    assert_pc(&c8, 0x202);
+
+    c8.tick_timers();
+    assert!(!c8.waiting_for_vblank());
+}
+
+#[test]
+fn draw_out_of_memory() {
+    let mut c8 = builder().i(0xFFF).build();
+    assert_eq!(
+        exec_err(&mut c8, 0xD002),
+        Chip8Error::MemoryOutOfRange {
+            addr: 0x200,
+            target: 0x1000
+        }
+    );
+}
+
+// EX9E and EXA1
+
+#[test]
+fn skip_if_key_pressed() {
+    let mut c8 = builder().v(0x1, 0xA).key_pressed(0xA).build();
+    exec(&mut c8, 0xE19E);
+    assert_pc(&c8, 0x204);
+
+    c8.handle_input(KeyEvent::Up(0xA));
+    exec(&mut c8, 0xE19E);
+    assert_pc(&c8, 0x206);
+}
+
+#[test]
+fn skip_if_key_not_pressed() {
+    let mut c8 = builder().v(0x1, 0xA).build();
+    exec(&mut c8, 0xE1A1);
+    assert_pc(&c8, 0x204);
+
+    c8.handle_input(KeyEvent::Down(0xA));
+    exec(&mut c8, 0xE1A1);
+    assert_pc(&c8, 0x206);
+}
+
+#[test]
+fn skip_if_key_uses_the_low_nibble() {
+    let mut c8 = builder().v(0x1, 0x1A).key_pressed(0xA).build();
+    exec(&mut c8, 0xE19E);
+    assert_pc(&c8, 0x204);
+}
+
+// F000 NNNN, FN01, F002 and FX3A
+
+#[test]
+fn load_i_long() {
+    let mut c8 = builder().xo_chip().memory(0x202, &[0xAB, 0xCD]).build();
+    exec(&mut c8, 0xF000);
+    assert_eq!(c8.i(), 0xABCD);
+    assert_pc(&c8, 0x204);
+}
+
+#[test]
+fn select_plane() {
+    let mut c8 = builder().xo_chip().build();
+    exec(&mut c8, 0xF201);
+    exec(&mut c8, 0xD001);
+    assert_eq!(pixel(&c8, 0, 0), 2);
+}
+
+#[test]
+fn audio_pattern_and_pitch() {
+    let pattern: Vec<u8> = (0..16).collect();
+    let mut c8 = builder()
+        .xo_chip()
+        .i(0x400)
+        .memory(0x400, &pattern)
+        .v(0x1, 112)
+        .build();
+    assert!(c8.audio_pattern().is_none());
+
+    exec(&mut c8, 0xF002);
+    assert_eq!(c8.audio_pattern(), Some((&pattern[..], 4000.0)));
+
+    exec(&mut c8, 0xF13A);
+    assert_eq!(c8.audio_pattern(), Some((&pattern[..], 8000.0)));
+}
+
+// FX07, FX0A, FX15 and FX18
+
+#[test]
+fn get_delay() {
+    let mut c8 = builder().delay_timer(42).build();
+    exec(&mut c8, 0xF107);
+    assert_v(&c8, &[(0x1, 42)]);
+}
+
+#[test]
+fn set_timers() {
+    let mut c8 = builder().v(0x1, 42).v(0x2, 3).build();
+    exec(&mut c8, 0xF115);
+    exec(&mut c8, 0xF218);
+    assert_eq!((c8.delay_timer(), c8.sound_timer()), (42, 3));
+    assert!(c8.should_beep());
+}
+
+#[test]
+fn wait_for_key() {
+    let mut c8 = builder().build();
+    exec(&mut c8, 0xF30A);
+    assert_eq!(c8.waiting_for_key(), Some(0x3));
+
+    c8.step().unwrap();
+    c8.handle_input(KeyEvent::Down(0x7));
+    assert_eq!(
+        c8.waiting_for_key(),
+        Some(0x3),
+        "the key must be released too"
+    );
+
+    c8.handle_input(KeyEvent::Up(0x7));
+    assert_eq!(c8.waiting_for_key(), None);
+    assert_v(&c8, &[(0x3, 0x7)]);
+    assert_pc(&c8, 0x202);
+}
+
+// FX1E, FX29 and FX30
+
+#[test]
+fn add_to_i() {
+    let mut c8 = builder().i(0x100).v(0x1, 0x20).v(0xF, 5).build();
+    exec(&mut c8, 0xF11E);
+    assert_eq!(c8.i(), 0x120);
+    assert_v(&c8, &[(0xF, 0)]);
+}
+
+#[test]
+fn add_to_i_past_the_memory() {
+    let mut c8 = builder().i(0xFFF).v(0x1, 0x01).build();
+    exec(&mut c8, 0xF11E);
+    assert_eq!(c8.i(), 0x1000);
+    assert_v(&c8, &[(0xF, 1)]);
+}
+
+#[test]
+fn add_vf_to_i() {
+    // VF is added before it's overwritten by the flag.
+    let mut c8 = builder().i(0x100).v(0xF, 0x20).build();
+    exec(&mut c8, 0xFF1E);
+    assert_eq!(c8.i(), 0x120);
+    assert_v(&c8, &[(0xF, 0)]);
+}
+
+#[test]
+fn font_sprite() {
+    let mut c8 = builder().v(0x1, 0xA).build();
+    exec(&mut c8, 0xF129);
+    assert_eq!(c8.i(), 0xA * 5);
+    assert_memory(&c8, c8.i(), &[0xF0, 0x90, 0xF0, 0x90, 0x90]);
+}
+
+#[test]
+fn font_sprite_uses_the_low_nibble() {
+    let mut c8 = builder().v(0x1, 0x1A).build();
+    exec(&mut c8, 0xF129);
+    assert_eq!(c8.i(), 0xA * 5);
+}
+
+#[test]
+fn big_font_sprite() {
+    let mut c8 = builder().v(0x1, 0x1).build();
+    exec(&mut c8, 0xF130);
+    assert_memory(&c8, c8.i(), &[0x18, 0x78, 0x78, 0x18, 0x18]);
+}
+
+// FX33
+
+#[test]
+fn bcd() {
+    for (value, digits) in [
+        (137, [1, 3, 7]),
+        (0, [0, 0, 0]),
+        (255, [2, 5, 5]),
+        (42, [0, 4, 2]),
+    ] {
+        let mut c8 = builder().v(0x1, value).i(0x300).build();
+        exec(&mut c8, 0xF133);
+        assert_memory(&c8, 0x300, &digits);
+        assert_eq!(c8.i(), 0x300);
+    }
+}
+
+#[test]
+fn bcd_out_of_memory() {
+    let mut c8 = builder().i(0xFFE).build();
+    assert_eq!(
+        exec_err(&mut c8, 0xF133),
+        Chip8Error::MemoryOutOfRange {
+            addr: 0x200,
+            target: 0x1000
+        }
+    );
+}
+
+// FX55 and FX65
+
+#[test]
+fn store_registers() {
+    let mut c8 = builder()
+        .v(0x0, 0xA0)
+        .v(0x1, 0xA1)
+        .v(0x2, 0xA2)
+        .i(0x300)
+        .memory(0x303, &[0xEE])
+        .build();
+    exec(&mut c8, 0xF255);
+    assert_memory(&c8, 0x300, &[0xA0, 0xA1, 0xA2, 0xEE]);
+    assert_eq!(c8.i(), 0x300);
+}
+
+#[test]
+fn store_registers_increments_i() {
+    let quirks = Quirks {
+        load_store_increments_i: true,
+        ..Quirks::none()
+    };
+    let mut c8 = builder().quirks(quirks).i(0x300).build();
+    exec(&mut c8, 0xF255);
+    assert_eq!(c8.i(), 0x303);
+}
+
+#[test]
+fn store_all_registers() {
+    let mut c8 = builder().v(0xF, 0xFF).i(0x300).build();
+    exec(&mut c8, 0xFF55);
+    assert_memory(&c8, 0x30F, &[0xFF]);
+}
+
+#[test]
+fn load_registers() {
+    let mut c8 = builder()
+        .i(0x300)
+        .memory(0x300, &[0xB0, 0xB1, 0xB2, 0xB3])
+        .build();
+    exec(&mut c8, 0xF265);
+    assert_v(&c8, &[(0x0, 0xB0), (0x1, 0xB1), (0x2, 0xB2), (0x3, 0)]);
+    assert_eq!(c8.i(), 0x300);
+}
+
+#[test]
+fn load_registers_increments_i() {
+    let quirks = Quirks {
+        load_store_increments_i: true,
+        ..Quirks::none()
+    };
+    let mut c8 = builder().quirks(quirks).i(0x300).build();
+    exec(&mut c8, 0xFF65);
+    assert_eq!(c8.i(), 0x310);
+}
+
+#[test]
+fn load_registers_out_of_memory() {
+    let mut c8 = builder().i(0xFFF).build();
+    assert_eq!(
+        exec_err(&mut c8, 0xF165),
+        Chip8Error::MemoryOutOfRange {
+            addr: 0x200,
+            target: 0x1000
+        }
+    );
+}
+
+// FX75 and FX85
+
+#[test]
+fn rpl_flags() {
+    let mut c8 = builder().v(0x0, 1).v(0x1, 2).v(0x2, 3).build();
+    exec(&mut c8, 0xF175);
+    for x in 0..3 {
+        c8.set_v(x, 0);
+    }
+
+    exec(&mut c8, 0xF285);
+    assert_v(&c8, &[(0x0, 1), (0x1, 2), (0x2, 0)]);
+}
+
+// Errors
+
+#[test]
+fn unknown_opcode() {
+    let mut c8 = builder().build();
+    assert_eq!(
+        exec_err(&mut c8, 0xFFFF),
+        Chip8Error::UnknownOpcode {
+            opcode: 0xFFFF,
+            addr: 0x200
+        }
+    );
+
+    assert!(c8.step().is_err(), "the emulator stays halted");
+    assert_eq!(
+        c8.error(),
+        Some(Chip8Error::UnknownOpcode {
+            opcode: 0xFFFF,
+            addr: 0x200
+        })
+    );
+}
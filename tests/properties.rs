@@ -0,0 +1,249 @@
+//! Property tests: random machine states and instructions, executed both by
+//! `Chip8` and by the reference interpreter in `reference`, must leave the same
+//! registers, stack, memory and screen.
+
+mod reference;
+
+use proptest::prelude::*;
+
+use rs_chip8::{Chip8, Quirks};
+
+use reference::{Machine, MEMORY_SIZE};
+
+/// The instructions known to the reference: (fixed bits, operand bits).
+const INSTRUCTIONS: [(u16, u16); 35] = [
+    (0x00E0, 0x0000),
+    (0x00EE, 0x0000),
+    (0x1000, 0x0FFF),
+    (0x2000, 0x0FFF),
+    (0x3000, 0x0FFF),
+    (0x4000, 0x0FFF),
+    (0x5000, 0x0FF0),
+    (0x6000, 0x0FFF),
+    (0x7000, 0x0FFF),
+    (0x8000, 0x0FF0),
+    (0x8001, 0x0FF0),
+    (0x8002, 0x0FF0),
+    (0x8003, 0x0FF0),
+    (0x8004, 0x0FF0),
+    (0x8005, 0x0FF0),
+    (0x8006, 0x0FF0),
+    (0x8007, 0x0FF0),
+    (0x800E, 0x0FF0),
+    (0x9000, 0x0FF0),
+    (0xA000, 0x0FFF),
+    (0xB000, 0x0FFF),
+    (0xC000, 0x0FFF),
+    (0xD001, 0x0FFE), // N is odd, so never 0 (a SUPER-CHIP 16x16 sprite)
+    (0xD002, 0x0FFC), // N is 2, 6, 10 or 14
+    (0xE09E, 0x0F00),
+    (0xE0A1, 0x0F00),
+    (0xF007, 0x0F00),
+    (0xF00A, 0x0F00),
+    (0xF015, 0x0F00),
+    (0xF018, 0x0F00),
+    (0xF01E, 0x0F00),
+    (0xF029, 0x0F00),
+    (0xF033, 0x0F00),
+    (0xF055, 0x0F00),
+    (0xF065, 0x0F00),
+];
+
+fn opcode() -> impl Strategy<Value = u16> {
+    (0..INSTRUCTIONS.len(), any::<u16>()).prop_map(|(index, operands)| {
+        let (fixed, mask) = INSTRUCTIONS[index];
+        fixed | operands & mask
+    })
+}
+
+fn quirks() -> impl Strategy<Value = Quirks> {
+    any::<[bool; 6]>().prop_map(|switches| Quirks {
+        shift_uses_vy: switches[0],
+        load_store_increments_i: switches[1],
+        jump_uses_vx: switches[2],
+        logic_resets_vf: switches[3],
+        clip_sprites: switches[4],
+        display_wait: switches[5],
+    })
+}
+
+/// The state both machines start from. The memory is generated from a seed,
+/// which keeps failing cases short.
+#[derive(Debug, Clone)]
+struct Setup {
+    quirks: Quirks,
+    v: [u8; 16],
+    i: u16,
+    pc: u16,
+    stack: Vec<u16>,
+    memory_seed: u64,
+    delay_timer: u8,
+    sound_timer: u8,
+    keys: [bool; 16],
+}
+
+fn setup() -> impl Strategy<Value = Setup> {
+    (
+        quirks(),
+        any::<[u8; 16]>(),
+        0..0x1000u16,
+        (0x100..0x7FFu16).prop_map(|addr| addr * 2),
+        prop::collection::vec(0..0x1000u16, 0..=16),
+        any::<u64>(),
+        any::<u8>(),
+        any::<u8>(),
+        any::<[bool; 16]>(),
+    )
+        .prop_map(
+            |(quirks, v, i, pc, stack, memory_seed, delay_timer, sound_timer, keys)| Setup {
+                quirks,
+                v,
+                i,
+                pc,
+                stack,
+                memory_seed,
+                delay_timer,
+                sound_timer,
+                keys,
+            },
+        )
+}
+
+/// Fills the memory with bytes from an xorshift generator, or zeros for a seed of 0.
+fn memory(seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..MEMORY_SIZE)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+/// Creates both machines, with `program` at the PC.
+fn machines(setup: &Setup, program: &[u16]) -> (Chip8, Machine) {
+    let mut memory = memory(setup.memory_seed);
+    for (index, opcode) in program.iter().enumerate() {
+        let addr = setup.pc as usize + index * 2;
+        if addr + 1 < MEMORY_SIZE {
+            memory[addr..addr + 2].copy_from_slice(&opcode.to_be_bytes());
+        }
+    }
+
+    let mut builder = Chip8::builder()
+        .quirks(setup.quirks)
+        .memory(0, &memory)
+        .i(setup.i)
+        .pc(setup.pc)
+        .stack(&setup.stack)
+        .delay_timer(setup.delay_timer)
+        .sound_timer(setup.sound_timer);
+    for (x, &value) in setup.v.iter().enumerate() {
+        builder = builder.v(x, value);
+    }
+    for key in (0..16).filter(|&key| setup.keys[key]) {
+        builder = builder.key_pressed(key);
+    }
+
+    let mut model = Machine::new(setup.quirks, memory);
+    model.v = setup.v;
+    model.i = setup.i;
+    model.pc = setup.pc;
+    model.stack = setup.stack.clone();
+    model.delay_timer = setup.delay_timer;
+    model.sound_timer = setup.sound_timer;
+    model.keys = setup.keys;
+
+    (builder.build(), model)
+}
+
+fn assert_same(c8: &Chip8, model: &Machine) -> Result<(), TestCaseError> {
+    let v: Vec<u8> = (0..16).map(|x| c8.v(x)).collect();
+    prop_assert_eq!(&v[..], &model.v[..], "V0-VF");
+    prop_assert_eq!(c8.i(), model.i, "I");
+    prop_assert_eq!(c8.pc(), model.pc, "PC");
+    prop_assert_eq!(c8.stack(), &model.stack[..], "stack");
+    prop_assert_eq!(c8.delay_timer(), model.delay_timer, "DT");
+    prop_assert_eq!(c8.sound_timer(), model.sound_timer, "ST");
+    prop_assert_eq!(c8.waiting_for_key(), model.key_wait, "key wait");
+    prop_assert_eq!(c8.waiting_for_vblank(), model.vblank_wait, "vblank wait");
+
+    let memory = c8.memory();
+    let address = (0..MEMORY_SIZE).find(|&addr| memory[addr] != model.memory[addr]);
+    prop_assert!(
+        address.is_none(),
+        "memory differs at 0x{:03X}",
+        address.unwrap_or_default()
+    );
+
+    let screen = c8.get_framebuffer();
+    let pixel = (0..screen.len()).find(|&p| (screen[p] != 0) != model.screen[p]);
+    prop_assert!(
+        pixel.is_none(),
+        "screen differs at pixel {}",
+        pixel.unwrap_or_default()
+    );
+
+    Ok(())
+}
+
+/// Executes an instruction on both machines. Returns `false` if it's unknown
+/// to the reference, in which case nothing is compared.
+fn step(c8: &mut Chip8, model: &mut Machine) -> Result<bool, TestCaseError> {
+    let opcode = model.opcode();
+    let result = c8.step();
+
+    // Random numbers can't be reproduced: check that the result of CXNN is
+    // masked by giving the reference the one `Chip8` drew.
+    if let Some(opcode) = opcode.filter(|opcode| opcode >> 12 == 0xC) {
+        if result.is_ok() {
+            model.random = c8.v((opcode >> 8 & 0xF) as usize);
+        }
+    }
+
+    let expected = model.step();
+    if let Err(rs_chip8::Chip8Error::UnknownOpcode { .. }) = expected {
+        return Ok(false);
+    }
+
+    prop_assert_eq!(result, expected, "result of {:04X?}", opcode);
+    if result.is_ok() {
+        assert_same(c8, model)?;
+    }
+
+    Ok(result.is_ok())
+}
+
+proptest! {
+    #[test]
+    fn instructions_match_the_reference(setup in setup(), opcode in opcode()) {
+        let (mut c8, mut model) = machines(&setup, &[opcode]);
+        step(&mut c8, &mut model)?;
+    }
+
+    #[test]
+    fn programs_match_the_reference(
+        setup in setup(),
+        program in prop::collection::vec(opcode(), 1..32),
+        cache in any::<bool>(),
+    ) {
+        // Programs loop and overwrite themselves, which checks the instruction cache.
+        let (mut c8, mut model) = machines(&setup, &program);
+        c8.set_instruction_cache(cache);
+
+        for count in 1..=64 {
+            if !step(&mut c8, &mut model)? {
+                break;
+            }
+
+            // A frame every 8 instructions, ending waits for the vertical blank.
+            if count % 8 == 0 {
+                c8.tick_timers();
+                model.tick();
+                assert_same(&c8, &model)?;
+            }
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! A small reference interpreter, written from the CHIP-8 instruction set
+//! rather than from `Chip8`, for the property tests to compare it with.
+//!
+//! It only knows the 35 original instructions, on a 64x32 screen with 4 KiB
+//! of memory, following the same `Quirks`. Where implementations differ
+//! without a quirk, it does what `Chip8` documents: FX1E sets VF when I goes
+//! past 0xFFF, skips jump over an XO-CHIP F000 NNNN as a whole, and the stack
+//! holds the addresses of the calls.
+
+use rs_chip8::{Chip8Error, Quirks};
+
+pub const MEMORY_SIZE: usize = 4096;
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+const STACK_SIZE: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct Machine {
+    pub quirks: Quirks,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub stack: Vec<u16>,
+    pub memory: Vec<u8>,
+    /// One entry per pixel, in row-major order.
+    pub screen: Vec<bool>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub keys: [bool; 16],
+    /// The register FX0A stores the key in, while waiting for it.
+    pub key_wait: Option<usize>,
+    pub vblank_wait: bool,
+    /// The random byte that CXNN masks, set by the caller.
+    pub random: u8,
+}
+
+impl Machine {
+    pub fn new(quirks: Quirks, memory: Vec<u8>) -> Machine {
+        assert_eq!(memory.len(), MEMORY_SIZE);
+
+        Machine {
+            quirks,
+            v: [0; 16],
+            i: 0,
+            pc: 0x200,
+            stack: Vec::new(),
+            memory,
+            screen: vec![false; WIDTH * HEIGHT],
+            delay_timer: 0,
+            sound_timer: 0,
+            keys: [false; 16],
+            key_wait: None,
+            vblank_wait: false,
+            random: 0,
+        }
+    }
+
+    fn read(&self, addr: u16) -> Result<u8, Chip8Error> {
+        match self.memory.get(addr as usize) {
+            Some(&byte) => Ok(byte),
+            None => Err(self.out_of_range(addr)),
+        }
+    }
+
+    fn write(&mut self, addr: u16, byte: u8) -> Result<(), Chip8Error> {
+        let error = self.out_of_range(addr);
+        match self.memory.get_mut(addr as usize) {
+            Some(target) => {
+                *target = byte;
+                Ok(())
+            }
+            None => Err(error),
+        }
+    }
+
+    fn out_of_range(&self, target: u16) -> Chip8Error {
+        Chip8Error::MemoryOutOfRange {
+            addr: self.pc,
+            target,
+        }
+    }
+
+    /// Reads the big-endian word at `addr`, low byte first so that a word
+    /// straddling the end of memory reports the byte past it.
+    fn word(&self, addr: u16) -> Result<u16, Chip8Error> {
+        let low = self.read(addr.wrapping_add(1))?;
+        let high = self.read(addr)?;
+        Ok(u16::from_be_bytes([high, low]))
+    }
+
+    /// Returns the opcode at the PC, if it's in memory.
+    pub fn opcode(&self) -> Option<u16> {
+        self.word(self.pc).ok()
+    }
+
+    /// Decrements the timers and ends the wait for the vertical blank.
+    pub fn tick(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+        self.vblank_wait = false;
+    }
+
+    /// Executes the instruction at the PC, unless waiting for a key or the
+    /// vertical blank. Unknown instructions fail without changing anything.
+    pub fn step(&mut self) -> Result<(), Chip8Error> {
+        if self.key_wait.is_some() || self.vblank_wait {
+            return Ok(());
+        }
+
+        let pc = self.pc;
+        let opcode = self.word(pc)?;
+        let x = (opcode >> 8 & 0xF) as usize;
+        let y = (opcode >> 4 & 0xF) as usize;
+        let n = opcode & 0xF;
+        let nn = opcode as u8;
+        let nnn = opcode & 0xFFF;
+        let (vx, vy) = (self.v[x], self.v[y]);
+        let next = pc.wrapping_add(2);
+        let unknown = Chip8Error::UnknownOpcode { opcode, addr: pc };
+
+        match opcode >> 12 {
+            0x0 if opcode == 0x00E0 => {
+                self.screen.iter_mut().for_each(|pixel| *pixel = false);
+                self.pc = next;
+            }
+            0x0 if opcode == 0x00EE => {
+                let call = self
+                    .stack
+                    .pop()
+                    .ok_or(Chip8Error::StackUnderflow { addr: pc })?;
+                self.pc = call.wrapping_add(2);
+            }
+            0x1 => self.pc = nnn,
+            0x2 => {
+                if self.stack.len() == STACK_SIZE {
+                    return Err(Chip8Error::StackOverflow { addr: pc });
+                }
+                self.stack.push(pc);
+                self.pc = nnn;
+            }
+            0x3 => self.skip_if(vx == nn)?,
+            0x4 => self.skip_if(vx != nn)?,
+            0x5 if n == 0 => self.skip_if(vx == vy)?,
+            0x6 => {
+                self.v[x] = nn;
+                self.pc = next;
+            }
+            0x7 => {
+                self.v[x] = vx.wrapping_add(nn);
+                self.pc = next;
+            }
+            0x8 => {
+                // The result, and the flag if the instruction sets one.
+                let (result, flag) = match n {
+                    0x0 => (vy, None),
+                    0x1 => (vx | vy, self.logic_flag()),
+                    0x2 => (vx & vy, self.logic_flag()),
+                    0x3 => (vx ^ vy, self.logic_flag()),
+                    0x4 => {
+                        let sum = vx as u16 + vy as u16;
+                        (sum as u8, Some((sum > 0xFF) as u8))
+                    }
+                    0x5 => (vx.wrapping_sub(vy), Some((vx >= vy) as u8)),
+                    0x6 => {
+                        let source = if self.quirks.shift_uses_vy { vy } else { vx };
+                        (source >> 1, Some(source & 1))
+                    }
+                    0x7 => (vy.wrapping_sub(vx), Some((vy >= vx) as u8)),
+                    0xE => {
+                        let source = if self.quirks.shift_uses_vy { vy } else { vx };
+                        (source << 1, Some(source >> 7))
+                    }
+                    _ => return Err(unknown),
+                };
+
+                self.v[x] = result;
+                if let Some(flag) = flag {
+                    self.v[0xF] = flag;
+                }
+                self.pc = next;
+            }
+            0x9 if n == 0 => self.skip_if(vx != vy)?,
+            0xA => {
+                self.i = nnn;
+                self.pc = next;
+            }
+            0xB => {
+                let offset = if self.quirks.jump_uses_vx {
+                    vx
+                } else {
+                    self.v[0]
+                };
+                self.pc = nnn + offset as u16;
+            }
+            0xC => {
+                self.v[x] = self.random & nn;
+                self.pc = next;
+            }
+            0xD if n != 0 => {
+                self.draw(vx as usize % WIDTH, vy as usize % HEIGHT, n)?;
+                self.vblank_wait = self.quirks.display_wait;
+                self.pc = next;
+            }
+            0xE if nn == 0x9E => self.skip_if(self.keys[(vx & 0xF) as usize])?,
+            0xE if nn == 0xA1 => self.skip_if(!self.keys[(vx & 0xF) as usize])?,
+            0xF => {
+                match nn {
+                    0x07 => self.v[x] = self.delay_timer,
+                    0x0A => self.key_wait = Some(x),
+                    0x15 => self.delay_timer = vx,
+                    0x18 => self.sound_timer = vx,
+                    0x1E => {
+                        let sum = self.i as u32 + vx as u32;
+                        self.v[0xF] = (sum > 0xFFF) as u8;
+                        self.i = sum as u16;
+                    }
+                    0x29 => self.i = (vx & 0xF) as u16 * 5,
+                    0x33 => {
+                        self.write(self.i, vx / 100)?;
+                        self.write(self.i.wrapping_add(1), vx / 10 % 10)?;
+                        self.write(self.i.wrapping_add(2), vx % 10)?;
+                    }
+                    0x55 => {
+                        for r in 0..=x {
+                            self.write(self.i.wrapping_add(r as u16), self.v[r])?;
+                        }
+                        self.increment_i(x);
+                    }
+                    0x65 => {
+                        for r in 0..=x {
+                            self.v[r] = self.read(self.i.wrapping_add(r as u16))?;
+                        }
+                        self.increment_i(x);
+                    }
+                    _ => return Err(unknown),
+                }
+                self.pc = next;
+            }
+            _ => return Err(unknown),
+        }
+
+        Ok(())
+    }
+
+    fn logic_flag(&self) -> Option<u8> {
+        if self.quirks.logic_resets_vf {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    fn increment_i(&mut self, x: usize) {
+        if self.quirks.load_store_increments_i {
+            self.i = self.i.wrapping_add(x as u16 + 1);
+        }
+    }
+
+    /// Skips the next instruction if `condition` holds.
+    fn skip_if(&mut self, condition: bool) -> Result<(), Chip8Error> {
+        let next = self.pc.wrapping_add(2);
+
+        self.pc = if !condition {
+            next
+        } else if self.word(next)? == 0xF000 {
+            next.wrapping_add(4)
+        } else {
+            next.wrapping_add(2)
+        };
+
+        Ok(())
+    }
+
+    /// Draws the N-byte sprite at I, with its top-left corner at (`x`, `y`).
+    fn draw(&mut self, x: usize, y: usize, n: u16) -> Result<(), Chip8Error> {
+        self.v[0xF] = 0;
+
+        for row in 0..n {
+            let mut py = y + row as usize;
+            if py >= HEIGHT {
+                if self.quirks.clip_sprites {
+                    break;
+                }
+                py %= HEIGHT;
+            }
+
+            let bits = self.read(self.i.wrapping_add(row))?;
+            for col in 0..8 {
+                let mut px = x + col;
+                if px >= WIDTH {
+                    if self.quirks.clip_sprites {
+                        break;
+                    }
+                    px %= WIDTH;
+                }
+
+                if bits & (0x80 >> col) != 0 {
+                    let pixel = &mut self.screen[py * WIDTH + px];
+                    if *pixel {
+                        self.v[0xF] = 1;
+                    }
+                    *pixel = !*pixel;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
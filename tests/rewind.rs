@@ -0,0 +1,65 @@
+//! Tests of the rewind buffer against snapshots of the frames it went through.
+
+use std::path::PathBuf;
+
+use rs_chip8::{Chip8, KeyEvent, Quirks, Rewind};
+
+#[test]
+fn rewinds_to_earlier_frames() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("games/INVADERS");
+    let mut emulator = Chip8::with_quirks(Quirks::schip());
+    emulator.load_rom_file(path.to_str().unwrap()).unwrap();
+
+    let mut rewind = Rewind::new(100);
+    let mut snapshots = Vec::new();
+    for frame in 0..150 {
+        // Move, so that frames differ by more than the timers.
+        if frame == 60 {
+            emulator.handle_input(KeyEvent::Down(0x6));
+        }
+        emulator.run_frame(20).unwrap();
+        rewind.push(&emulator);
+        snapshots.push(emulator.save_state());
+    }
+    assert_eq!(rewind.len(), 100);
+
+    // Back one frame at a time, through the 100 kept.
+    for frame in (49..149).rev() {
+        assert!(rewind.rewind(&mut emulator));
+        assert!(
+            emulator.save_state() == snapshots[frame],
+            "differs from frame {}",
+            frame
+        );
+    }
+
+    assert!(rewind.is_empty());
+    assert!(!rewind.rewind(&mut emulator));
+    assert!(emulator.save_state() == snapshots[49]);
+}
+
+#[test]
+fn records_after_rewinding() {
+    let mut emulator = Chip8::new();
+    emulator.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap(); // ADD V0, 1; JP 0x200
+
+    let mut rewind = Rewind::new(10);
+    for _ in 0..5 {
+        emulator.run_frame(2).unwrap();
+        rewind.push(&emulator);
+    }
+    assert_eq!(emulator.v(0), 5);
+
+    assert!(rewind.rewind(&mut emulator));
+    assert!(rewind.rewind(&mut emulator));
+    assert_eq!(emulator.v(0), 3);
+
+    // The frames rewound are replaced by the new ones.
+    emulator.run_frame(2).unwrap();
+    emulator.set_v(0, 0x10);
+    rewind.push(&emulator);
+    assert_eq!(rewind.len(), 3);
+
+    assert!(rewind.rewind(&mut emulator));
+    assert_eq!(emulator.v(0), 3);
+}
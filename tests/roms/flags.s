@@ -0,0 +1,172 @@
+; Checks the flags set in VF by the arithmetic and shift instructions, in the
+; spirit of the flags test ROMs, including when VF is an operand: the flag is
+; written last, overwriting the result.
+;
+; Each check leaves 1 in V0 if it passes, 0 otherwise, and calls `report`.
+
+; 8XY4 carry
+    LD V1, 0xF0
+    LD V2, 0x20
+    ADD V1, V2
+    LD V3, VF
+    LD V0, 0
+    SNE V1, 0x10
+    LD V0, 1
+    SE V3, 1
+    LD V0, 0
+    CALL report
+
+; 8XY4 no carry
+    LD V1, 0x10
+    LD V2, 0x20
+    LD VF, 5
+    ADD V1, V2
+    LD V3, VF
+    LD V0, 0
+    SNE V1, 0x30
+    LD V0, 1
+    SE V3, 0
+    LD V0, 0
+    CALL report
+
+; 8XY5 no borrow
+    LD V1, 0x30
+    LD V2, 0x10
+    SUB V1, V2
+    LD V3, VF
+    LD V0, 0
+    SNE V1, 0x20
+    LD V0, 1
+    SE V3, 1
+    LD V0, 0
+    CALL report
+
+; 8XY5 borrow
+    LD V1, 0x10
+    LD V2, 0x30
+    SUB V1, V2
+    LD V3, VF
+    LD V0, 0
+    SNE V1, 0xE0
+    LD V0, 1
+    SE V3, 0
+    LD V0, 0
+    CALL report
+
+; 8XY5 with equal values doesn't borrow.
+    LD V1, 0x10
+    LD V2, 0x10
+    SUB V1, V2
+    LD V3, VF
+    LD V0, 0
+    SNE V1, 0
+    LD V0, 1
+    SE V3, 1
+    LD V0, 0
+    CALL report
+
+; 8XY7 no borrow
+    LD V1, 0x10
+    LD V2, 0x30
+    SUBN V1, V2
+    LD V3, VF
+    LD V0, 0
+    SNE V1, 0x20
+    LD V0, 1
+    SE V3, 1
+    LD V0, 0
+    CALL report
+
+; 8XY7 borrow
+    LD V1, 0x30
+    LD V2, 0x10
+    SUBN V1, V2
+    LD V3, VF
+    LD V0, 0
+    SNE V1, 0xE0
+    LD V0, 1
+    SE V3, 0
+    LD V0, 0
+    CALL report
+
+; 8XY6 shifts the low bit out.
+    LD V1, 0x05
+    SHR V1
+    LD V3, VF
+    LD V0, 0
+    SNE V1, 0x02
+    LD V0, 1
+    SE V3, 1
+    LD V0, 0
+    CALL report
+
+; 8XYE shifts the high bit out.
+    LD V1, 0x81
+    SHL V1
+    LD V3, VF
+    LD V0, 0
+    SNE V1, 0x02
+    LD V0, 1
+    SE V3, 1
+    LD V0, 0
+    CALL report
+
+; 8XY4 with VF as VX: the carry, not the sum 0x10.
+    LD VF, 0xF0
+    LD V1, 0x20
+    ADD VF, V1
+    LD V0, 0
+    SNE VF, 1
+    LD V0, 1
+    CALL report
+
+; 8XY4 with VF as VY
+    LD V1, 0xF0
+    LD VF, 0x20
+    ADD V1, VF
+    LD V3, VF
+    LD V0, 0
+    SNE V1, 0x10
+    LD V0, 1
+    SE V3, 1
+    LD V0, 0
+    CALL report
+
+; 8XY5 with VF as VX: the borrow, not the difference 0xE0.
+    LD VF, 0x10
+    LD V1, 0x30
+    SUB VF, V1
+    LD V0, 0
+    SNE VF, 0
+    LD V0, 1
+    CALL report
+
+; 8XY7 with VF as VX: no borrow, not the difference 0x20.
+    LD VF, 0x10
+    LD V1, 0x30
+    SUBN VF, V1
+    LD V0, 0
+    SNE VF, 1
+    LD V0, 1
+    CALL report
+
+; 8XY6 with VF as VX: the low bit, not the result 0x02.
+    LD VF, 0x04
+    SHR VF
+    LD V0, 0
+    SNE VF, 0
+    LD V0, 1
+    CALL report
+
+; 8XYE with VF as VX: the high bit, not the result 0x80.
+    LD VF, 0x40
+    SHL VF
+    LD V0, 0
+    SNE VF, 0
+    LD V0, 1
+    CALL report
+
+done:
+    JP done
+
+    include "report.s"
@@ -0,0 +1,52 @@
+; Checks the keypad instructions, in the spirit of the keypad test ROMs. The
+; test presses and releases key 5, then presses key A and releases it later.
+;
+; Each check leaves 1 in V0 if it passes, 0 otherwise, and calls `report`.
+; A check that waits for a key the test never presses is never reported.
+
+; FX0A stores the key pressed and released, drawn under the grid.
+    LD V1, K
+    LD F, V1
+    LD V2, 40
+    LD V3, 24
+    DRW V2, V3, 5
+    LD V0, 0
+    SNE V1, 5
+    LD V0, 1
+    CALL report
+
+; EX9E doesn't skip while the key isn't pressed.
+    LD V2, 0xA
+    LD V0, 1
+    SKP V2
+    JP not_pressed
+    LD V0, 0
+not_pressed:
+    CALL report
+
+; EX9E skips once the key is pressed.
+wait_press:
+    SKP V2
+    JP wait_press
+    LD V0, 1
+    CALL report
+
+; EXA1 doesn't skip while the key is pressed.
+    LD V0, 1
+    SKNP V2
+    JP pressed
+    LD V0, 0
+pressed:
+    CALL report
+
+; EXA1 skips once the key is released.
+wait_release:
+    SKNP V2
+    JP wait_release
+    LD V0, 1
+    CALL report
+
+done:
+    JP done
+
+    include "report.s"
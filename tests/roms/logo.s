@@ -0,0 +1,22 @@
+; Draws the 16 font digits in two rows, playing the part of the IBM logo ROM:
+; the smallest program that shows CLS, LD I, ADD and DRW work.
+
+    CLS
+    LD V1, 0            ; digit
+    LD V2, 12           ; x
+    LD V3, 8            ; y
+digit:
+    LD F, V1
+    DRW V2, V3, 5
+    ADD V1, 1
+    ADD V2, 5
+    SE V1, 8
+    JP next
+    LD V2, 12
+    LD V3, 18
+next:
+    SE V1, 16
+    JP digit
+
+done:
+    JP done
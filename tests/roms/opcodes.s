@@ -0,0 +1,312 @@
+; Checks the result of every CHIP-8 instruction, in the spirit of the opcode
+; test ROMs. Flags are left to flags.s, quirks to quirks.s and keys to keypad.s:
+; the checks here give the same results whatever the quirks.
+;
+; Each check leaves 1 in V0 if it passes, 0 otherwise, and calls `report`,
+; which records it at RESULTS and draws a mark in the grid.
+
+SCRATCH = 0xF00
+
+; 00E0: a sprite drawn after clearing the screen doesn't collide.
+    LD V1, 0
+    LD F, V1
+    DRW V1, V1, 5
+    CLS
+    DRW V1, V1, 5
+    LD V2, VF
+    CLS
+    LD V0, 0
+    SNE V2, 0
+    LD V0, 1
+    CALL report
+
+; 1NNN
+    LD V0, 0
+    JP jp_taken
+    JP jp_done
+jp_taken:
+    LD V0, 1
+jp_done:
+    CALL report
+
+; 3XNN
+    LD V1, 5
+    LD V0, 0
+    SE V1, 5
+    JP se_imm_done
+    SE V1, 6
+    LD V0, 1
+se_imm_done:
+    CALL report
+
+; 4XNN
+    LD V1, 5
+    LD V0, 0
+    SNE V1, 6
+    JP sne_imm_done
+    SNE V1, 5
+    LD V0, 1
+sne_imm_done:
+    CALL report
+
+; 5XY0
+    LD V1, 5
+    LD V2, 5
+    LD V3, 6
+    LD V0, 0
+    SE V1, V2
+    JP se_reg_done
+    SE V1, V3
+    LD V0, 1
+se_reg_done:
+    CALL report
+
+; 9XY0
+    LD V0, 0
+    SNE V1, V3
+    JP sne_reg_done
+    SNE V1, V2
+    LD V0, 1
+sne_reg_done:
+    CALL report
+
+; 6XNN
+    LD V1, 0x42
+    LD V0, 0
+    SNE V1, 0x42
+    LD V0, 1
+    CALL report
+
+; 7XNN wraps around, and leaves VF alone.
+    LD V1, 0xFF
+    LD VF, 7
+    ADD V1, 2
+    LD V0, 0
+    SNE V1, 1
+    LD V0, 1
+    SE VF, 7
+    LD V0, 0
+    CALL report
+
+; 8XY0
+    LD V2, 0x33
+    LD V1, V2
+    LD V0, 0
+    SNE V1, 0x33
+    LD V0, 1
+    CALL report
+
+; 8XY1
+    LD V1, 0x0F
+    LD V2, 0x30
+    OR V1, V2
+    LD V0, 0
+    SNE V1, 0x3F
+    LD V0, 1
+    CALL report
+
+; 8XY2
+    LD V1, 0x3C
+    LD V2, 0x0F
+    AND V1, V2
+    LD V0, 0
+    SNE V1, 0x0C
+    LD V0, 1
+    CALL report
+
+; 8XY3
+    LD V1, 0x3C
+    LD V2, 0x0F
+    XOR V1, V2
+    LD V0, 0
+    SNE V1, 0x33
+    LD V0, 1
+    CALL report
+
+; 8XY4
+    LD V1, 0x12
+    LD V2, 0x34
+    ADD V1, V2
+    LD V0, 0
+    SNE V1, 0x46
+    LD V0, 1
+    CALL report
+
+; 8XY5
+    LD V1, 0x34
+    LD V2, 0x12
+    SUB V1, V2
+    LD V0, 0
+    SNE V1, 0x22
+    LD V0, 1
+    CALL report
+
+; 8XY6, in place so that the shifting quirk doesn't matter.
+    LD V1, 0x84
+    SHR V1
+    LD V0, 0
+    SNE V1, 0x42
+    LD V0, 1
+    CALL report
+
+; 8XY7
+    LD V1, 0x12
+    LD V2, 0x34
+    SUBN V1, V2
+    LD V0, 0
+    SNE V1, 0x22
+    LD V0, 1
+    CALL report
+
+; 8XYE
+    LD V1, 0x21
+    SHL V1
+    LD V0, 0
+    SNE V1, 0x42
+    LD V0, 1
+    CALL report
+
+; ANNN and FX1E
+    LD I, table
+    LD V1, 2
+    ADD I, V1
+    LD V0, [I]
+    LD V1, V0
+    LD V0, 0
+    SNE V1, 0x33
+    LD V0, 1
+    CALL report
+
+; BNNN, as on the VIP: quirks.s checks the SUPER-CHIP jumps.
+    LD V0, 2
+    JP V0, jump_table
+jump_table:
+    JP jump_wrong
+    JP jump_right
+jump_right:
+    LD V0, 1
+    JP jump_done
+jump_wrong:
+    LD V0, 0
+jump_done:
+    CALL report
+
+; 2NNN and 00EE
+    LD V1, 0
+    CALL subroutine
+    LD V0, 0
+    SNE V1, 1
+    LD V0, 1
+    CALL report
+
+; FX33
+    LD V1, 137
+    LD I, SCRATCH
+    LD B, V1
+    LD I, SCRATCH
+    LD V2, [I]
+    LD V3, V0
+    LD V0, 0
+    SNE V3, 1
+    LD V0, 1
+    SE V1, 3
+    LD V0, 0
+    SE V2, 7
+    LD V0, 0
+    CALL report
+
+; FX55 and FX65 store and load V0 to VX included, and nothing more.
+    LD V0, 0xEE
+    LD I, SCRATCH + 3
+    LD [I], V0
+    LD V0, 0xA0
+    LD V1, 0xA1
+    LD V2, 0xA2
+    LD I, SCRATCH
+    LD [I], V2
+    LD V0, 0
+    LD V1, 0
+    LD V2, 0
+    LD I, SCRATCH
+    LD V3, [I]
+    LD V4, V0
+    LD V0, 0
+    SNE V4, 0xA0
+    LD V0, 1
+    SE V1, 0xA1
+    LD V0, 0
+    SE V2, 0xA2
+    LD V0, 0
+    SE V3, 0xEE
+    LD V0, 0
+    CALL report
+
+; FX15 and FX07: the delay timer can be read back, and counts down to 0.
+    LD V1, 3
+    LD DT, V1
+    LD V3, DT
+delay:
+    LD V2, DT
+    SE V2, 0
+    JP delay
+    LD V0, 1
+    SNE V3, 0
+    LD V0, 0
+    CALL report
+
+; CXNN is masked with NN.
+    RND V1, 0
+    RND V3, 0x0F
+    LD V2, 0xF0
+    AND V2, V3
+    LD V0, 0
+    SNE V1, 0
+    LD V0, 1
+    SE V2, 0
+    LD V0, 0
+    CALL report
+
+; DXYN sets VF on collisions, and XORs the sprite with the screen.
+    LD V1, 40
+    LD V2, 26
+    LD I, block
+    DRW V1, V2, 1
+    LD V3, VF
+    DRW V1, V2, 1
+    LD V4, VF
+    DRW V1, V2, 1
+    LD V0, 0
+    SNE V3, 0
+    LD V0, 1
+    SE V4, 1
+    LD V0, 0
+    SE VF, 0
+    LD V0, 0
+    CALL report
+
+; FX29 points I to the sprite of the digit in VX.
+    LD V1, 1
+    LD F, V1
+    LD V1, [I]
+    LD V2, V0
+    LD V0, 0
+    SNE V2, 0x20
+    LD V0, 1
+    SE V1, 0x60
+    LD V0, 0
+    CALL report
+
+done:
+    JP done
+
+subroutine:
+    LD V1, 1
+    RET
+
+table:
+    db 0x11, 0x22, 0x33
+
+block:
+    db 0xFF
+
+    include "report.s"
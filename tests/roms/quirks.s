@@ -0,0 +1,99 @@
+; Detects the quirks of the interpreter, in the spirit of the quirks test ROMs.
+; Each check leaves 1 in V0 if the quirk is on, 0 if it's off, and calls
+; `report`: the grid shows the quirks that are on, in this order.
+
+SCRATCH = 0xF00
+
+; vF reset: 8XY1 resets VF.
+    LD VF, 5
+    LD V1, 0
+    OR V1, V1
+    LD V0, 0
+    SNE VF, 0
+    LD V0, 1
+    CALL report
+
+; Memory: FX55 increments I, so that FX65 then loads the byte after the ones stored.
+    LD I, SCRATCH + 2
+    LD V0, 0x33
+    LD [I], V0
+    LD V0, 0x11
+    LD V1, 0x22
+    LD I, SCRATCH
+    LD [I], V1
+    LD V0, [I]
+    LD V1, V0
+    LD V0, 0
+    SNE V1, 0x33
+    LD V0, 1
+    CALL report
+
+; Display wait: the delay timer ticks at every sprite drawn, instead of at most
+; once for a few instructions.
+    LD V1, 40
+    LD V2, 26
+    LD I, block
+    LD V3, 10
+    LD DT, V3
+    DRW V1, V2, 1
+    DRW V1, V2, 1
+    DRW V1, V2, 1
+    DRW V1, V2, 1
+    LD V3, DT
+    LD V0, 1
+    SNE V3, 10
+    LD V0, 0
+    SNE V3, 9
+    LD V0, 0
+    CALL report
+
+; Clipping: a sprite past the right edge doesn't wrap around to collide with
+; one on the left edge.
+    LD V1, 0
+    LD V2, 26
+    LD I, block
+    DRW V1, V2, 1
+    LD V1, 60
+    DRW V1, V2, 1
+    LD V3, VF
+    DRW V1, V2, 1
+    LD V1, 0
+    DRW V1, V2, 1
+    LD V0, 0
+    SNE V3, 0
+    LD V0, 1
+    CALL report
+
+; Shifting: 8XY6 shifts VY into VX.
+    LD V1, 0x10
+    LD V2, 0x04
+    SHR V1, V2
+    LD V0, 0
+    SNE V1, 0x02
+    LD V0, 1
+    CALL report
+
+; Jumping: BNNN adds VX, X being the high nibble of NNN, instead of V0. The
+; program is below 0x400, so X is 2 or 3.
+    LD V0, 0
+    LD V2, 2
+    LD V3, 2
+    JP V0, jump_table
+jump_table:
+    JP jump_v0
+    JP jump_vx
+jump_vx:
+    LD V0, 1
+    JP jump_done
+jump_v0:
+    LD V0, 0
+jump_done:
+    CALL report
+
+done:
+    JP done
+
+block:
+    db 0xFF
+
+    include "report.s"
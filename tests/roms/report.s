@@ -0,0 +1,38 @@
+; Records and draws the result of the checks of a test ROM.
+;
+; A check leaves 1 (pass) or 0 (fail) in V0 and calls `report`. The results are
+; stored from RESULTS on, one byte per check, and drawn as a grid of marks,
+; 8 per row. `report` uses V0, VC, VD, VE and I: checks must leave VC-VE alone,
+; and start with VC, VD and VE set to 0.
+
+RESULTS = 0xE00
+
+report:
+    LD I, RESULTS
+    ADD I, VE
+    LD [I], V0
+    LD I, mark_fail
+    SE V0, 0
+    LD I, mark_pass
+    DRW VC, VD, 5
+    ADD VE, 1
+    ADD VC, 8
+    SE VC, 64
+    RET
+    LD VC, 0
+    ADD VD, 6
+    RET
+
+mark_pass:
+    db 0b00000010
+    db 0b00000100
+    db 0b10001000
+    db 0b01010000
+    db 0b00100000
+
+mark_fail:
+    db 0b10001000
+    db 0b01010000
+    db 0b00100000
+    db 0b01010000
+    db 0b10001000
@@ -0,0 +1,120 @@
+//! Tests of source maps: parsing, formatting, and looking up lines and addresses.
+
+use std::env;
+use std::fs;
+
+use rs_chip8::{assemble_file_with_map, SourceLine, SourceMap};
+
+fn source(file: &str, line: usize) -> Option<SourceLine> {
+    Some(SourceLine {
+        file: file.to_string(),
+        line,
+    })
+}
+
+#[test]
+fn parse() {
+    let map = SourceMap::parse(
+        "
+        # Written by hand.
+        0x200 game.s:12
+        202   lib/sprites.s:3
+        0x2A4\tgame.s:40
+        ",
+    )
+    .unwrap();
+
+    assert_eq!(map.line_at(0x200).cloned(), source("game.s", 12));
+    assert_eq!(map.line_at(0x202).cloned(), source("lib/sprites.s", 3));
+    assert_eq!(map.line_at(0x2A4).cloned(), source("game.s", 40));
+    assert_eq!(map.line_at(0x204), None);
+}
+
+#[test]
+fn paths_containing_colons() {
+    let map = SourceMap::parse("0x200 C:\\games\\game.s:7\n0x202 a:b:c.s:8").unwrap();
+
+    assert_eq!(map.line_at(0x200).cloned(), source("C:\\games\\game.s", 7));
+    assert_eq!(map.line_at(0x202).cloned(), source("a:b:c.s", 8));
+}
+
+#[test]
+fn paths_containing_spaces() {
+    let map = SourceMap::parse("0x200 my games/game.s:7").unwrap();
+    assert_eq!(map.line_at(0x200).cloned(), source("my games/game.s", 7));
+}
+
+#[test]
+fn malformed_lines() {
+    for (text, line) in [
+        ("0x200", 1),
+        ("0x200 game.s", 1),
+        ("0x200 game.s:", 1),
+        ("0x200 game.s:x", 1),
+        ("0x200 game.s:-1", 1),
+        ("0xZZZ game.s:1", 1),
+        ("0x10000 game.s:1", 1),
+        ("0x200 game.s:1\n\n0x202: game.s:2", 3),
+    ] {
+        assert_eq!(
+            SourceMap::parse(text),
+            Err(format!("line {}: expected 'ADDR FILE:LINE'", line)),
+            "{:?}",
+            text
+        );
+    }
+}
+
+#[test]
+fn display_round_trip() {
+    let mut map = SourceMap::new();
+    map.insert(0x2A4, "game.s", 40);
+    map.insert(0x200, "C:\\games\\game.s", 12);
+
+    let text = map.to_string();
+    assert_eq!(text, "0x200 C:\\games\\game.s:12\n0x2A4 game.s:40\n");
+    assert_eq!(SourceMap::parse(&text), Ok(map));
+}
+
+#[test]
+fn address_of() {
+    let mut map = SourceMap::new();
+    map.insert(0x200, "game.s", 3);
+    map.insert(0x204, "lib/sprites.s", 3);
+    map.insert(0x206, "game.s", 3);
+
+    // The first instruction of the line.
+    assert_eq!(map.address_of("game.s", 3), Some(0x200));
+    // Paths match when one ends with the other.
+    assert_eq!(
+        map.address_of("/home/me/game/lib/sprites.s", 3),
+        Some(0x204)
+    );
+    assert_eq!(map.address_of("sprites.s", 3), Some(0x204));
+    // But only on whole components.
+    assert_eq!(map.address_of("prites.s", 3), None);
+    assert_eq!(map.address_of("game.s", 4), None);
+}
+
+#[test]
+fn assembled_file() {
+    let dir = env::temp_dir().join(format!("rs-chip8-source-map-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("main.s"), "CLS\n\ninclude \"data.s\"\nJP 0x200\n").unwrap();
+    fs::write(dir.join("data.s"), "; sprite\nLD V0, 1\n").unwrap();
+
+    let (rom, map) = assemble_file_with_map(&dir.join("main.s")).unwrap();
+    assert_eq!(rom.len(), 6);
+
+    let location = |addr| {
+        let line = map.line_at(addr).unwrap();
+        let file = line.file.rsplit(['/', '\\']).next().unwrap().to_string();
+        (file, line.line)
+    };
+    assert_eq!(location(0x200), ("main.s".to_string(), 1));
+    assert_eq!(location(0x202), ("data.s".to_string(), 2));
+    assert_eq!(location(0x204), ("main.s".to_string(), 4));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
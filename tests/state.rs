@@ -0,0 +1,196 @@
+//! Tests of save states: the round trip, and the states that can't be loaded
+//! or are loaded partially.
+
+use std::path::PathBuf;
+
+use rs_chip8::{Chip8, KeyEvent, Quirks, StateError};
+
+/// Size of the header, before the first chunk.
+const HEADER_SIZE: usize = 16;
+
+fn game(name: &str) -> Vec<u8> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("games")
+        .join(name);
+    std::fs::read(path).unwrap()
+}
+
+/// Runs INVADERS, which draws no random numbers, for a while with a key held down.
+fn running_emulator() -> Chip8 {
+    let mut emulator = Chip8::with_quirks(Quirks::schip());
+    emulator.load_rom(&game("INVADERS")).unwrap();
+    emulator.handle_input(KeyEvent::Down(0x7));
+    for _ in 0..90 {
+        emulator.run_frame(20).unwrap();
+    }
+    emulator
+}
+
+/// A chunk of a state, as its tag and its payload.
+type Chunk = ([u8; 4], Vec<u8>);
+
+/// Splits a state into its header and its chunks.
+fn chunks(state: &[u8]) -> (Vec<u8>, Vec<Chunk>) {
+    let mut chunks = Vec::new();
+    let mut rest = &state[HEADER_SIZE..];
+
+    while !rest.is_empty() {
+        let mut tag = [0; 4];
+        tag.copy_from_slice(&rest[..4]);
+        let len = u32::from_be_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+        chunks.push((tag, rest[8..8 + len].to_vec()));
+        rest = &rest[8 + len..];
+    }
+
+    (state[..HEADER_SIZE].to_vec(), chunks)
+}
+
+fn join(header: &[u8], chunks: &[Chunk]) -> Vec<u8> {
+    let mut state = header.to_vec();
+    for (tag, payload) in chunks {
+        state.extend_from_slice(tag);
+        state.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        state.extend_from_slice(payload);
+    }
+    state
+}
+
+#[test]
+fn round_trip() {
+    let emulator = running_emulator();
+    let state = emulator.save_state();
+
+    // Into a new emulator running the same ROM.
+    let mut restored = Chip8::new();
+    restored.load_rom(&game("INVADERS")).unwrap();
+    restored.load_state(&state).unwrap();
+
+    assert_eq!(restored.save_state(), state);
+    assert_eq!(restored.pc(), emulator.pc());
+    assert_eq!(restored.i(), emulator.i());
+    assert_eq!(restored.stack(), emulator.stack());
+    assert_eq!(restored.memory(), emulator.memory());
+    assert_eq!(restored.get_framebuffer(), emulator.get_framebuffer());
+    assert_eq!(restored.quirks, emulator.quirks);
+}
+
+#[test]
+fn restored_emulator_runs_the_same() {
+    let mut emulator = running_emulator();
+    let state = emulator.save_state();
+    let mut restored = emulator.clone();
+    restored.run_frame(50).unwrap();
+    restored.load_state(&state).unwrap();
+
+    for _ in 0..30 {
+        emulator.run_frame(20).unwrap();
+        restored.run_frame(20).unwrap();
+    }
+    assert_eq!(restored.save_state(), emulator.save_state());
+}
+
+/// A failed load leaves the machine as it was.
+fn assert_fails(state: &[u8], expected: StateError) {
+    let mut emulator = running_emulator();
+    let before = emulator.save_state();
+
+    assert_eq!(emulator.load_state(state), Err(expected));
+    assert_eq!(emulator.save_state(), before);
+}
+
+#[test]
+fn truncated() {
+    let state = running_emulator().save_state();
+
+    assert_fails(&state[..2], StateError::BadMagic);
+    assert_fails(&state[..10], StateError::Truncated { tag: *b"HEAD" });
+    // In the middle of the header of the first chunk.
+    assert_fails(
+        &state[..HEADER_SIZE + 6],
+        StateError::Truncated { tag: *b"CPU " },
+    );
+    // In the middle of the payload of the last one.
+    assert_fails(
+        &state[..state.len() - 1],
+        StateError::Truncated { tag: *b"QRKS" },
+    );
+}
+
+#[test]
+fn bad_magic() {
+    let mut state = running_emulator().save_state();
+    state[0] = b'X';
+    assert_fails(&state, StateError::BadMagic);
+}
+
+#[test]
+fn other_rom() {
+    let mut emulator = Chip8::new();
+    emulator.load_rom(&game("PONG")).unwrap();
+    let state = emulator.save_state();
+
+    assert_fails(&state, StateError::RomMismatch);
+}
+
+#[test]
+fn newer_version() {
+    let mut state = running_emulator().save_state();
+
+    // Written by version 3, readable by version 2 and later.
+    state[4..8].copy_from_slice(&[0, 3, 0, 2]);
+    assert_fails(&state, StateError::UnsupportedVersion { version: 3 });
+
+    // Written by version 3, still readable by version 1.
+    state[4..8].copy_from_slice(&[0, 3, 0, 1]);
+    let mut emulator = running_emulator();
+    emulator.load_state(&state).unwrap();
+
+    // There is no version 0.
+    state[4..8].copy_from_slice(&[0, 0, 0, 0]);
+    assert_fails(&state, StateError::UnsupportedVersion { version: 0 });
+}
+
+#[test]
+fn unknown_chunks_are_skipped() {
+    let emulator = running_emulator();
+    let state = emulator.save_state();
+    let (header, mut chunks) = chunks(&state);
+    chunks.insert(1, (*b"NEW ", vec![1, 2, 3]));
+    // Fields appended to a known chunk are skipped too.
+    chunks.last_mut().unwrap().1.push(0xFF);
+
+    let mut restored = running_emulator();
+    restored.run_frame(10).unwrap();
+    restored.load_state(&join(&header, &chunks)).unwrap();
+    assert_eq!(restored.save_state(), state);
+}
+
+#[test]
+fn missing_chunks_leave_the_machine_unchanged() {
+    let emulator = running_emulator();
+    let (header, chunks) = chunks(&emulator.save_state());
+    let without_quirks: Vec<_> = chunks
+        .into_iter()
+        .filter(|(tag, _)| tag != b"QRKS")
+        .collect();
+
+    let mut restored = emulator.clone();
+    restored.quirks = Quirks::vip();
+    restored
+        .load_state(&join(&header, &without_quirks))
+        .unwrap();
+    assert_eq!(restored.quirks, Quirks::vip());
+    assert_eq!(restored.pc(), emulator.pc());
+}
+
+#[test]
+fn memory_of_the_wrong_size() {
+    let (header, mut chunks) = chunks(&running_emulator().save_state());
+    let memory = chunks.iter_mut().find(|(tag, _)| tag == b"MEM ").unwrap();
+    memory.1.truncate(1000);
+
+    assert_fails(
+        &join(&header, &chunks),
+        StateError::Invalid { tag: *b"MEM " },
+    );
+}
@@ -0,0 +1,99 @@
+//! Tests of tracing: the instructions a filter selects, and the entries traced.
+
+use std::sync::{Arc, Mutex};
+
+use rs_chip8::{Chip8, Instruction, TraceEntry, TraceFilter, Tracer};
+
+fn decode(opcode: u16) -> Instruction {
+    Instruction::decode(opcode).unwrap()
+}
+
+#[test]
+fn empty_filter() {
+    let filter = TraceFilter::default();
+    assert!(filter.matches(0x000, &decode(0x00E0)));
+    assert!(filter.matches(0xFFFF, &decode(0xD015)));
+}
+
+#[test]
+fn ranges() {
+    let filter = TraceFilter {
+        ranges: vec![(0x200, 0x2FF), (0x400, 0x400)],
+        mnemonics: Vec::new(),
+    };
+
+    let cls = decode(0x00E0);
+    assert!(filter.matches(0x200, &cls));
+    assert!(filter.matches(0x2FF, &cls));
+    assert!(filter.matches(0x400, &cls));
+    assert!(!filter.matches(0x1FF, &cls));
+    assert!(!filter.matches(0x300, &cls));
+    assert!(!filter.matches(0x402, &cls));
+}
+
+#[test]
+fn mnemonics() {
+    let filter = TraceFilter {
+        ranges: Vec::new(),
+        mnemonics: vec!["drw".to_string(), "CALL".to_string()],
+    };
+
+    assert!(filter.matches(0x200, &decode(0xD015)));
+    assert!(filter.matches(0x200, &decode(0x2300)));
+    assert!(!filter.matches(0x200, &decode(0x1300)));
+    assert!(!filter.matches(0x200, &decode(0x00E0)));
+}
+
+#[test]
+fn ranges_and_mnemonics() {
+    let filter = TraceFilter {
+        ranges: vec![(0x200, 0x2FF)],
+        mnemonics: vec!["DRW".to_string()],
+    };
+
+    assert!(filter.matches(0x200, &decode(0xD015)));
+    assert!(!filter.matches(0x300, &decode(0xD015)));
+    assert!(!filter.matches(0x200, &decode(0x00E0)));
+}
+
+/// Collects the traced entries.
+#[derive(Clone, Default)]
+struct Entries(Arc<Mutex<Vec<TraceEntry>>>);
+
+impl Tracer for Entries {
+    fn trace(&mut self, entry: &TraceEntry) {
+        self.0.lock().unwrap().push(*entry);
+    }
+}
+
+#[test]
+fn traced_entries() {
+    // LD V0, 5; ADD V0, 1; JP 0x202
+    let mut c8 = Chip8::builder()
+        .memory(0x200, &[0x60, 0x05, 0x70, 0x01, 0x12, 0x02])
+        .build();
+    let entries = Entries::default();
+    let filter = TraceFilter {
+        ranges: vec![(0x202, 0x202)],
+        mnemonics: Vec::new(),
+    };
+    c8.set_tracer(entries.clone(), filter);
+
+    for _ in 0..5 {
+        c8.step().unwrap();
+    }
+
+    let entries = entries.0.lock().unwrap();
+    let traced: Vec<_> = entries
+        .iter()
+        .map(|entry| (entry.cycle, entry.pc, entry.opcode, entry.v[0]))
+        .collect();
+    assert_eq!(traced, [(1, 0x202, 0x7001, 5), (3, 0x202, 0x7001, 6)]);
+    assert_eq!(
+        entries[0].to_string(),
+        format!(
+            "       1 0202 7001 ADD V0, 0x01       V=05{} I=0000 SP=0 DT=00 ST=00",
+            " 00".repeat(15)
+        )
+    );
+}
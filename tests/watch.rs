@@ -0,0 +1,129 @@
+//! Tests of watchpoints: which accesses they report, and from where.
+
+use rs_chip8::{Access, Chip8, Chip8Builder, MemoryAccess, Watchpoint};
+
+fn watchpoint(addr: u16, len: u16, read: bool, write: bool) -> Watchpoint {
+    Watchpoint {
+        addr,
+        len,
+        read,
+        write,
+    }
+}
+
+fn access(pc: u16, addr: u16, access: Access, value: u8) -> MemoryAccess {
+    MemoryAccess {
+        pc,
+        addr,
+        access,
+        value,
+    }
+}
+
+/// Builds an emulator watching reads and writes of 0x300-0x303.
+fn watched(builder: Chip8Builder) -> Chip8 {
+    let mut c8 = builder.build();
+    c8.add_watchpoint(watchpoint(0x300, 4, true, true));
+    c8
+}
+
+#[test]
+fn matches() {
+    let watchpoint = watchpoint(0x300, 4, false, true);
+
+    assert!(watchpoint.matches(0x300, Access::Write));
+    assert!(watchpoint.matches(0x303, Access::Write));
+    assert!(!watchpoint.matches(0x304, Access::Write));
+    assert!(!watchpoint.matches(0x2FF, Access::Write));
+    assert!(!watchpoint.matches(0x300, Access::Read));
+}
+
+#[test]
+fn read_and_write() {
+    let mut c8 = watched(Chip8::builder().memory(0x301, &[0x42]));
+
+    assert_eq!(c8.read(0x301), Ok(0x42));
+    c8.write(0x303, 0x07).unwrap();
+    c8.write(0x304, 0x08).unwrap();
+    c8.read(0x2FF).unwrap();
+
+    assert_eq!(
+        c8.take_accesses(),
+        [
+            access(0x200, 0x301, Access::Read, 0x42),
+            access(0x200, 0x303, Access::Write, 0x07),
+        ]
+    );
+    assert!(c8.take_accesses().is_empty());
+}
+
+#[test]
+fn instructions() {
+    // LD B, V0; LD V1, [I]
+    let mut c8 = watched(
+        Chip8::builder()
+            .memory(0x200, &[0xF0, 0x33, 0xF1, 0x65])
+            .v(0x0, 123)
+            .i(0x301),
+    );
+    c8.step().unwrap();
+    c8.step().unwrap();
+
+    assert_eq!(
+        c8.take_accesses(),
+        [
+            access(0x200, 0x301, Access::Write, 1),
+            access(0x200, 0x302, Access::Write, 2),
+            access(0x200, 0x303, Access::Write, 3),
+            access(0x202, 0x301, Access::Read, 1),
+            access(0x202, 0x302, Access::Read, 2),
+        ]
+    );
+}
+
+#[test]
+fn sprite_rows_are_read_once() {
+    // DRW V0, V1, 3, at the right edge so that most columns are clipped.
+    let mut c8 = watched(
+        Chip8::builder()
+            .memory(0x200, &[0xD0, 0x13])
+            .memory(0x300, &[0xF0, 0x90, 0xF0])
+            .v(0x0, 60)
+            .i(0x300),
+    );
+    c8.step().unwrap();
+
+    assert_eq!(
+        c8.take_accesses(),
+        [
+            access(0x200, 0x300, Access::Read, 0xF0),
+            access(0x200, 0x301, Access::Read, 0x90),
+            access(0x200, 0x302, Access::Read, 0xF0),
+        ]
+    );
+}
+
+#[test]
+fn instruction_fetches_are_not_reported() {
+    // JP 0x300, then LD V0, 1 there.
+    let mut c8 = watched(
+        Chip8::builder()
+            .memory(0x200, &[0x13, 0x00])
+            .memory(0x300, &[0x60, 0x01]),
+    );
+    c8.step().unwrap();
+    c8.step().unwrap();
+
+    assert_eq!(c8.pc(), 0x302);
+    assert!(c8.take_accesses().is_empty());
+}
+
+#[test]
+fn removed_watchpoint() {
+    let mut c8 = watched(Chip8::builder());
+    assert!(c8.remove_watchpoint(&watchpoint(0x300, 4, true, true)));
+    assert!(!c8.remove_watchpoint(&watchpoint(0x300, 4, true, true)));
+
+    c8.write(0x300, 1).unwrap();
+    assert!(c8.take_accesses().is_empty());
+}